@@ -45,6 +45,8 @@ mod main {
         router.get("/authorize", ohandler.authorize(handle_get), "authorize");
         router.post("/authorize", ohandler.authorize(IronOwnerAuthorizer(handle_post)), "authorize");
         router.post("/token", ohandler.token(), "token");
+        router.post("/revoke", ohandler.revoke(), "revoke");
+        router.post("/introspect", ohandler.introspect(), "introspect");
 
         // Set up a protected resource, only accessible with a token with `default scope`.
         protected.link_before(ohandler.guard(vec!["default".parse().unwrap()]));
@@ -9,17 +9,21 @@
 //! Another consideration is the possiblilty of reusing some components with other oauth schemes.
 //! In this way, the backend is used to group necessary types and as an interface to implementors,
 //! to be able to infer the range of applicable end effectors (i.e. authorizers, issuer, registrars).
-use primitives::authorizer::Authorizer;
-use primitives::registrar::{PreGrant, ClientUrl, Registrar, RegistrarError};
-use primitives::grant::GrantRequest;
+use primitives::authorizer::{Authorizer, RedeemedCode};
+use primitives::clock::{Clock, SystemClock};
+use primitives::device::{DeviceAuthorizer, DeviceCodes, DevicePoll};
+use primitives::registrar::{Client, ClientRegistration, PreGrant, ClientUrl, Registrar, RegistrarError};
+use primitives::grant::{CodeChallenge, Grant, GrantRequest};
 use primitives::issuer::{IssuedToken, Issuer};
+use primitives::generator::SecretGenerator;
 use super::{Scope};
 use super::error::{AccessTokenError, AccessTokenErrorExt, AccessTokenErrorType};
 use super::error::{AuthorizationError, AuthorizationErrorExt, AuthorizationErrorType};
+use super::error::RegistrationErrorType;
 use std::borrow::Cow;
 use std::collections::HashMap;
-use url::Url;
-use chrono::Utc;
+use url::{form_urlencoded, Url};
+use chrono::{DateTime, Utc};
 use serde_json;
 
 /// Defines the correct treatment of the error.
@@ -37,7 +41,9 @@ pub enum CodeError {
 /// of this should be enforced by the frontend instead.
 pub struct ErrorUrl {
     base_url: Url,
+    state: Option<String>,
     error: AuthorizationError,
+    fragment: bool,
 }
 
 /// Defines actions for the response to an access token request.
@@ -66,10 +72,19 @@ type AccessResult<T> = Result<T, AccessError>;
 
 impl ErrorUrl {
     /// Construct a new error, already fixing the state parameter if it exists.
-    fn new<S>(mut url: Url, state: Option<S>, error: AuthorizationError) -> ErrorUrl where S: AsRef<str> {
-        url.query_pairs_mut()
-            .extend_pairs(state.as_ref().map(|st| ("state", st.as_ref())));
-        ErrorUrl{ base_url: url, error: error }
+    fn new<S>(url: Url, state: Option<S>, error: AuthorizationError) -> ErrorUrl where S: AsRef<str> {
+        ErrorUrl {
+            base_url: url,
+            state: state.map(|st| st.as_ref().to_string()),
+            error: error,
+            fragment: false,
+        }
+    }
+
+    /// Construct an error which is encoded in the fragment instead of the query, as required for
+    /// responses of the implicit grant.
+    fn new_fragment<S>(url: Url, state: Option<S>, error: AuthorizationError) -> ErrorUrl where S: AsRef<str> {
+        ErrorUrl { fragment: true, .. ErrorUrl::new(url, state, error) }
     }
 
     /// Modify the contained error.
@@ -84,11 +99,22 @@ impl ErrorUrl {
 }
 
 impl Into<Url> for ErrorUrl {
-    /// Finalize the error url by saving its parameters in the query part of the redirect_url
+    /// Finalize the error url by saving its parameters in the query part of the redirect_url, or
+    /// its fragment for errors of the implicit grant.
     fn into(self) -> Url {
         let mut url = self.base_url;
-        url.query_pairs_mut()
-            .extend_pairs(self.error.into_iter());
+        let state = self.state.as_ref().map(|st| ("state", st.as_str()));
+        if self.fragment {
+            let fragment = form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(state)
+                .extend_pairs(self.error.into_iter())
+                .finish();
+            url.set_fragment(Some(&fragment));
+        } else {
+            url.query_pairs_mut()
+                .extend_pairs(state)
+                .extend_pairs(self.error.into_iter());
+        }
         url
     }
 }
@@ -124,19 +150,35 @@ impl ErrorDescription {
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////
 
-pub struct BearerToken(IssuedToken, String);
+/// An issued token together with the scope it grants.
+///
+/// The scope is always part of the response since it may differ from the scope the client
+/// requested, for example after negotiation with the registrar.
+pub struct BearerToken {
+    token: IssuedToken,
+    scope: String,
+    issued_at: DateTime<Utc>,
+}
 
 impl BearerToken {
+    fn new(token: IssuedToken, scope: String, now: DateTime<Utc>) -> BearerToken {
+        BearerToken { token, scope, issued_at: now }
+    }
+
     /// Convert the token into a json string, viable for being sent over a network with
     /// `application/json` encoding.
+    ///
+    /// The refresh token is only included if one was issued.
     pub fn to_json(self) -> String {
-        let remaining = self.0.until.signed_duration_since(Utc::now());
-        let kvmap: HashMap<_, _> = vec![
-            ("access_token", self.0.token),
-            ("refresh_token", self.0.refresh),
+        let remaining = self.token.until.signed_duration_since(self.issued_at);
+        let mut kvmap: HashMap<_, _> = vec![
+            ("access_token", self.token.token),
             ("token_type", "bearer".to_string()),
             ("expires_in", remaining.num_seconds().to_string()),
-            ("scope", self.1)].into_iter().collect();
+            ("scope", self.scope)].into_iter().collect();
+        if !self.token.refresh.is_empty() {
+            kvmap.insert("refresh_token", self.token.refresh);
+        }
         serde_json::to_string(&kvmap).unwrap()
     }
 }
@@ -160,20 +202,34 @@ pub trait CodeRequest {
     fn redirect_url(&self) -> Option<Cow<str>>;
     /// Optional parameter the client can use to identify the redirected user-agent.
     fn state(&self) -> Option<Cow<str>>;
-    /// The method requested, MUST be `code`
+    /// The method requested, either `code` or `token` for the implicit grant.
     fn method(&self) -> Option<Cow<str>>;
+    /// The PKCE code challenge the client commits to.
+    fn code_challenge(&self) -> Option<Cow<str>>;
+    /// The transformation applied to the code verifier, `plain` if not given.
+    fn code_challenge_method(&self) -> Option<Cow<str>>;
 }
 
 /// CodeRef is a thin wrapper around necessary types to execute an authorization code grant.
+///
+/// If an issuer is supplied, clients which opted into the implicit grant may also request a token
+/// directly from the authorization endpoint.
 pub struct CodeRef<'a> {
     registrar: &'a Registrar,
     authorizer: &'a mut Authorizer,
+    issuer: Option<&'a mut Issuer>,
+    clock: &'a Clock,
 }
 
+/// The clock of backend refs which were not given another one.
+static SYSTEM_CLOCK: SystemClock = SystemClock;
+
 /// Represents a valid, currently pending authorization request not bound to an owner. The frontend
 /// can signal a reponse using this object.
 pub struct AuthorizationRequest<'a> {
     pre_grant: PreGrant<'a>,
+    code_challenge: Option<CodeChallenge>,
+    implicit: bool,
     code: CodeRef<'a>,
     request: &'a CodeRequest,
 }
@@ -213,23 +269,43 @@ impl<'u> CodeRef<'u> {
             Err(RegistrarError::Unregistered) => return Err(CodeError::Ignore),
             Err(RegistrarError::MismatchedRedirect) => return Err(CodeError::Ignore),
             Err(RegistrarError::UnauthorizedClient) => return Err(CodeError::Ignore),
+            Err(RegistrarError::InvalidScope) => return Err(CodeError::Ignore),
+            Err(RegistrarError::AlreadyRegistered) => return Err(CodeError::Ignore),
             Ok(pre_grant) => pre_grant,
         };
 
         let state = request.state();
 
+        let implicit = match request.method() {
+            Some(ref method) => method.as_ref() == "token",
+            None => false,
+        };
+
         // Setup an error with url and state, makes the code flow afterwards easier
         let error_url = bound_client.redirect_url.clone().into_owned();
-        let prepared_error = ErrorUrl::new(error_url.clone(), state,
-            AuthorizationError::with(()));
+        let prepared_error = if implicit {
+            ErrorUrl::new_fragment(error_url.clone(), state, AuthorizationError::with(()))
+        } else {
+            ErrorUrl::new(error_url.clone(), state, AuthorizationError::with(()))
+        };
 
         match request.method() {
             Some(ref method) if method.as_ref() == "code"
                 => (),
+            Some(ref method) if method.as_ref() == "token" && self.issuer.is_some()
+                => if !bound_client.client.allows_implicit_grant() {
+                    return Err(CodeError::Redirect(prepared_error.with(
+                        AuthorizationErrorType::UnauthorizedClient)))
+                },
             _ => return Err(CodeError::Redirect(prepared_error.with(
                     AuthorizationErrorType::UnsupportedResponseType))),
         }
 
+        if !bound_client.client.allows_response_type(if implicit { "token" } else { "code" }) {
+            return Err(CodeError::Redirect(prepared_error.with(
+                AuthorizationErrorType::UnauthorizedClient)))
+        }
+
         // Extract additional parameters
         let scope = request.scope();
         let scope = match scope.map(|scope| scope.as_ref().parse()) {
@@ -240,15 +316,55 @@ impl<'u> CodeRef<'u> {
             Some(Ok(scope)) => Some(scope),
         };
 
+        let code_challenge = match request.code_challenge() {
+            None => None,
+            Some(challenge) => {
+                let method = request.code_challenge_method();
+                match CodeChallenge::from_parameters(&challenge, method.as_ref().map(|m| m.as_ref())) {
+                    Err(()) => return Err(CodeError::Redirect(prepared_error.with(
+                        (AuthorizationErrorType::InvalidRequest, "Invalid code challenge")))),
+                    Ok(challenge) => Some(challenge),
+                }
+            },
+        };
+
+        if !implicit && code_challenge.is_none() && self.registrar.requires_pkce(bound_client.client) {
+            return Err(CodeError::Redirect(prepared_error.with(
+                (AuthorizationErrorType::InvalidRequest, "Code challenge required"))))
+        }
+
+        let pre_grant = match bound_client.negotiate(scope) {
+            Err(_) => return Err(CodeError::Redirect(prepared_error.with(
+                    AuthorizationErrorType::InvalidScope))),
+            Ok(pre_grant) => pre_grant,
+        };
+
+        let issuer: Option<&'r mut Issuer> = match self.issuer {
+            Some(issuer) => Some(issuer),
+            None => None,
+        };
+
         Ok(AuthorizationRequest {
-            pre_grant: bound_client.negotiate(scope),
-            code: CodeRef { registrar: self.registrar, authorizer: self.authorizer },
+            pre_grant,
+            code_challenge,
+            implicit,
+            code: CodeRef { registrar: self.registrar, authorizer: self.authorizer, issuer, clock: self.clock },
             request,
         })
     }
 
     pub fn with(registrar: &'u Registrar, t: &'u mut Authorizer) -> Self {
-        CodeRef { registrar, authorizer: t }
+        CodeRef { registrar, authorizer: t, issuer: None, clock: &SYSTEM_CLOCK }
+    }
+
+    /// Also support the implicit grant, issuing tokens with the given issuer.
+    pub fn with_issuer(registrar: &'u Registrar, t: &'u mut Authorizer, i: &'u mut Issuer) -> Self {
+        CodeRef { registrar, authorizer: t, issuer: Some(i), clock: &SYSTEM_CLOCK }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: &'u Clock) -> Self {
+        CodeRef { clock, .. self }
     }
 }
 
@@ -257,18 +373,38 @@ impl<'a> AuthorizationRequest<'a> {
     pub fn deny(self) -> CodeResult<Url> {
         let url = self.pre_grant.redirect_url.into_owned();
         let error = AuthorizationError::with(AuthorizationErrorType::AccessDenied);
-        let error = ErrorUrl::new(url, self.request.state(), error);
+        let error = if self.implicit {
+            ErrorUrl::new_fragment(url, self.request.state(), error)
+        } else {
+            ErrorUrl::new(url, self.request.state(), error)
+        };
         Err(CodeError::Redirect(error))
     }
 
     /// Inform the backend about consent from a resource owner. Use negotiated parameters to
     /// authorize a client for an owner.
+    ///
+    /// For the implicit grant, the access token is issued immediately and returned in the fragment
+    /// of the redirect url. No refresh token is handed out in this case.
     pub fn authorize(self, owner_id: Cow<'a, str>) -> CodeResult<Url> {
-       let grant = self.code.authorizer.authorize(GrantRequest{
+       if self.implicit {
+           return self.authorize_implicit(owner_id)
+       }
+
+       let request = GrantRequest{
            owner_id: &owner_id,
            client_id: &self.pre_grant.client_id,
            redirect_url: &self.pre_grant.redirect_url,
-           scope: &self.pre_grant.scope});
+           scope: &self.pre_grant.scope};
+       let grant = match self.code_challenge {
+           None => self.code.authorizer.authorize(request),
+           Some(ref challenge) => match self.code.authorizer.authorize_challenged(request, challenge) {
+               Ok(grant) => grant,
+               Err(()) => return Err(CodeError::Redirect(ErrorUrl::new(
+                   self.pre_grant.redirect_url.into_owned(), self.request.state(), AuthorizationError::with(
+                       (AuthorizationErrorType::InvalidRequest, "Code challenges are not supported"))))),
+           },
+       };
        let mut url = self.pre_grant.redirect_url.into_owned();
        url.query_pairs_mut()
            .append_pair("code", grant.as_str())
@@ -277,6 +413,27 @@ impl<'a> AuthorizationRequest<'a> {
        Ok(url)
     }
 
+    fn authorize_implicit(self, owner_id: Cow<'a, str>) -> CodeResult<Url> {
+       let issuer = self.code.issuer.ok_or(CodeError::Ignore)?;
+       let token = issuer.issue_access_only(GrantRequest{
+           owner_id: &owner_id,
+           client_id: &self.pre_grant.client_id,
+           redirect_url: &self.pre_grant.redirect_url,
+           scope: &self.pre_grant.scope});
+       let expires_in = token.until.signed_duration_since(self.code.clock.now()).num_seconds().to_string();
+       let scope = self.pre_grant.scope.to_string();
+       let fragment = form_urlencoded::Serializer::new(String::new())
+           .append_pair("access_token", &token.token)
+           .append_pair("token_type", "bearer")
+           .append_pair("expires_in", &expires_in)
+           .append_pair("scope", &scope)
+           .extend_pairs(self.request.state().map(|v| ("state", v)))
+           .finish();
+       let mut url = self.pre_grant.redirect_url.into_owned();
+       url.set_fragment(Some(&fragment));
+       Ok(url)
+    }
+
     /// Retrieve a reference to the negotiated parameters (e.g. scope). These should be displayed
     /// to the resource owner when asking for his authorization.
     pub fn pre_grant(&self) -> &PreGrant<'a> {
@@ -290,9 +447,33 @@ impl<'a> AuthorizationRequest<'a> {
 
 /// Issuer is a thin wrapper around necessary types to execute an bearer token request..
 pub struct IssuerRef<'a> {
-    registrar: &'a Registrar,
+    registrar: &'a mut Registrar,
     authorizer: &'a mut Authorizer,
     issuer: &'a mut Issuer,
+    owner_verifier: Option<&'a OwnerVerifier>,
+    devices: Option<&'a mut DeviceAuthorizer>,
+    replay_reporter: Option<&'a ReplayReporter>,
+    clock: &'a Clock,
+}
+
+/// Checks credentials which a resource owner entrusted directly to a client.
+///
+/// Required for the resource owner password credentials grant. This is the counterpart to an
+/// `OwnerAuthorizer` for clients which can not redirect the owner and should only be offered to
+/// clients which are trusted to handle the credentials of the owner, such as first-party apps.
+pub trait OwnerVerifier {
+    /// Verify the username and password, returning the identifier of the owner on success.
+    fn verify_owner(&self, username: &str, password: &str) -> Option<String>;
+}
+
+/// Receives reports of authorization codes which were redeemed more than once.
+///
+/// A replayed code indicates that it was leaked, possibly to an attacker who raced the client.
+/// The tokens issued for the code have already been revoked when the report is made, if the
+/// issuer supports revocation.
+pub trait ReplayReporter {
+    /// Report the replay of a code, and whether its tokens could be revoked.
+    fn code_replayed(&self, code: &RedeemedCode, revoked: bool);
 }
 
 /// Necessary
@@ -310,30 +491,35 @@ pub trait AccessTokenRequest {
     fn client_id(&self) -> Option<Cow<str>>;
     /// Valid request have the redirect url used to request the authorization code grant.
     fn redirect_url(&self) -> Option<Cow<str>>;
-    /// Valid requests have this set to "authorization_code"
+    /// Valid requests have this set to "authorization_code" or "refresh_token"
     fn grant_type(&self) -> Option<Cow<str>>;
+    /// The refresh token to trade for a new access token.
+    fn refresh_token(&self) -> Option<Cow<str>>;
+    /// Optionally requests a scope, for refreshed tokens or client credentials.
+    fn scope(&self) -> Option<Cow<str>>;
+    /// The PKCE verifier, required if a code challenge was given for the authorization code.
+    fn code_verifier(&self) -> Option<Cow<str>>;
+    /// The username of the resource owner for the password grant.
+    fn username(&self) -> Option<Cow<str>>;
+    /// The password of the resource owner for the password grant.
+    fn password(&self) -> Option<Cow<str>>;
+    /// The device code of a pending device authorization.
+    fn device_code(&self) -> Option<Cow<str>>;
 }
 
 impl<'u> IssuerRef<'u> {
     /// Try to redeem an authorization code.
+    ///
+    /// Redeeming a code a second time revokes the tokens issued for it, if the authorizer
+    /// remembers redeemed codes.
     pub fn use_code<'r>(&mut self, request: &'r AccessTokenRequest)
     -> AccessTokenResult<BearerToken> where 'u: 'r {
         if !request.valid() {
             return Err(IssuerError::invalid(()))
         }
 
-        let authorization = request.authorization();
-        let client_id = request.client_id();
-        let (client_id, auth): (&str, Option<&[u8]>) = match (&client_id, &authorization) {
-            (&None, &Some((ref client_id, ref auth))) => (client_id.as_ref(), Some(auth.as_ref())),
-            (&Some(ref client_id), &None) => (client_id.as_ref(), None),
-            _ => return Err(IssuerError::invalid(())),
-        };
-
-        let client = self.registrar.client(&client_id).ok_or(
-            IssuerError::unauthorized((), "basic"))?;
-        client.check_authentication(auth).map_err(|_|
-            IssuerError::unauthorized((), "basic"))?;
+        let client = self.authenticate(request)?;
+        let client_id = client.client_id();
 
         match request.grant_type() {
             Some(ref cow) if cow == "authorization_code" => (),
@@ -341,12 +527,16 @@ impl<'u> IssuerRef<'u> {
             Some(_) => return Err(IssuerError::invalid(AccessTokenErrorType::UnsupportedGrantType)),
         };
 
+        if !client.allows_grant_type("authorization_code") {
+            return Err(unregistered_grant_type())
+        }
+
         let code = request.code()
             .ok_or(IssuerError::invalid(()))?;
         let code = code.as_ref();
 
-        let saved_params = match self.authorizer.extract(code) {
-            None => return Err(IssuerError::invalid(())),
+        let (saved_params, code_challenge) = match self.authorizer.extract_challenged(code) {
+            None => return Err(self.revoke_replayed(code, client_id)),
             Some(v) => v,
         };
 
@@ -358,21 +548,1096 @@ impl<'u> IssuerRef<'u> {
             return Err(IssuerError::invalid(AccessTokenErrorType::InvalidGrant))
         }
 
-        if *saved_params.until.as_ref() < Utc::now() {
+        if *saved_params.until.as_ref() < self.clock.now() {
             return Err(IssuerError::invalid((AccessTokenErrorType::InvalidGrant, "Grant expired")).into())
         }
 
+        match (code_challenge, request.code_verifier()) {
+            (None, None) => (),
+            (Some(challenge), Some(ref verifier)) if challenge.verify(verifier).is_ok() => (),
+            _ => return Err(IssuerError::invalid((AccessTokenErrorType::InvalidGrant,
+                "Code verifier does not match the challenge"))),
+        }
+
         let token = self.issuer.issue(GrantRequest{
             client_id: &saved_params.client_id,
             owner_id: &saved_params.owner_id,
             redirect_url: &saved_params.redirect_url,
             scope: &saved_params.scope,
         });
-        Ok(BearerToken{0: token, 1: saved_params.scope.as_ref().to_string()})
+        self.authorizer.redeemed(code, &saved_params, &token);
+        Ok(BearerToken::new(token, saved_params.scope.as_ref().to_string(), self.clock.now()))
+    }
+
+    /// Revoke the tokens issued for a code which is redeemed again.
+    ///
+    /// Codes which are simply unknown are rejected as invalid requests, as before the detection
+    /// of replays. Only the client to which the code was issued can trigger the revocation, codes
+    /// presented by any other client are merely invalid grants.
+    fn revoke_replayed(&mut self, code: &str, client_id: &str) -> IssuerError {
+        let redeemed = match self.authorizer.replayed(code) {
+            None => return IssuerError::invalid(()),
+            Some(ref redeemed) if redeemed.client_id != client_id =>
+                return IssuerError::invalid(AccessTokenErrorType::InvalidGrant),
+            Some(redeemed) => redeemed,
+        };
+
+        let mut revoked = true;
+        for token in redeemed.tokens.iter() {
+            revoked &= self.issuer.revoke(token).is_ok();
+        }
+
+        if let Some(reporter) = self.replay_reporter {
+            reporter.code_replayed(&redeemed, revoked);
+        }
+
+        IssuerError::invalid((AccessTokenErrorType::InvalidGrant, "Authorization code was already used"))
+    }
+
+    /// Try to trade a refresh token for a new access token.
+    ///
+    /// The new token is issued for the same grant as the refresh token. A client may request a
+    /// narrower scope than originally granted but never a wider one. The expiration date of the
+    /// grant refers only to the access token issued alongside the refresh token, whose own lifetime
+    /// is checked by the issuer. For clients with refresh token rotation, the refresh token is replaced
+    /// and presenting it again revokes all tokens of its family. Otherwise, the client keeps using
+    /// the same refresh token.
+    pub fn refresh<'r>(&mut self, request: &'r AccessTokenRequest)
+    -> AccessTokenResult<BearerToken> where 'u: 'r {
+        if !request.valid() {
+            return Err(IssuerError::invalid(()))
+        }
+
+        let client = self.authenticate(request)?;
+
+        match request.grant_type() {
+            Some(ref cow) if cow == "refresh_token" => (),
+            None => return Err(IssuerError::invalid(())),
+            Some(_) => return Err(IssuerError::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+        };
+
+        if !client.allows_grant_type("refresh_token") {
+            return Err(unregistered_grant_type())
+        }
+
+        let refresh = request.refresh_token()
+            .ok_or(IssuerError::invalid(()))?;
+
+        let saved_params: Grant = match self.issuer.recover_refresh(&refresh) {
+            None => return Err(self.revoke_reused(&refresh)),
+            Some(v) => v.into(),
+        };
+
+        if saved_params.client_id != client.client_id() {
+            return Err(IssuerError::invalid(AccessTokenErrorType::InvalidGrant))
+        }
+
+        let scope: Scope = match request.scope().map(|scope| scope.as_ref().parse()) {
+            None => saved_params.scope.clone(),
+            Some(Err(_)) => return Err(IssuerError::invalid(AccessTokenErrorType::InvalidScope)),
+            Some(Ok(scope)) => scope,
+        };
+
+        if !(scope <= saved_params.scope) {
+            return Err(IssuerError::invalid((AccessTokenErrorType::InvalidScope,
+                "Scope exceeds the original grant")))
+        }
+
+        let token = self.issuer.refresh(&refresh, GrantRequest{
+            client_id: &saved_params.client_id,
+            owner_id: &saved_params.owner_id,
+            redirect_url: &saved_params.redirect_url,
+            scope: &scope,
+        }, client.rotates_refresh_tokens()).map_err(|()| IssuerError::invalid(AccessTokenErrorType::InvalidGrant))?;
+        Ok(BearerToken::new(token, scope.to_string(), self.clock.now()))
+    }
+
+    /// Revoke the family of a refresh token which was replaced by rotation.
+    fn revoke_reused(&mut self, refresh: &str) -> IssuerError {
+        if self.issuer.revoke_reused(refresh) {
+            IssuerError::invalid((AccessTokenErrorType::InvalidGrant, "Refresh token was already used"))
+        } else {
+            IssuerError::invalid(AccessTokenErrorType::InvalidGrant)
+        }
+    }
+
+    /// Issue a token to a client acting on its own behalf.
+    ///
+    /// Only confidential clients can use this grant as the token is issued purely based on the
+    /// authentication of the client. The client itself is recorded as the owner of the grant.
+    /// The scope is negotiated with the registrar just like in an authorization request. No
+    /// refresh token is handed out, the client can simply request a new token instead.
+    pub fn client_credentials<'r>(&mut self, request: &'r AccessTokenRequest)
+    -> AccessTokenResult<BearerToken> where 'u: 'r {
+        if !request.valid() {
+            return Err(IssuerError::invalid(()))
+        }
+
+        let client = self.authenticate(request)?;
+
+        match request.grant_type() {
+            Some(ref cow) if cow == "client_credentials" => (),
+            None => return Err(IssuerError::invalid(())),
+            Some(_) => return Err(IssuerError::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+        };
+
+        if !client.allows_grant_type("client_credentials") {
+            return Err(unregistered_grant_type())
+        }
+
+        if request.authorization().is_none() {
+            return Err(IssuerError::invalid((AccessTokenErrorType::UnauthorizedClient,
+                "Only confidential clients may use client credentials")))
+        }
+
+        let pre_grant = negotiate_scope(&*self.registrar, &client, request.scope())?;
+
+        let token = self.issuer.issue_access_only(GrantRequest{
+            client_id: &pre_grant.client_id,
+            owner_id: &pre_grant.client_id,
+            redirect_url: &pre_grant.redirect_url,
+            scope: &pre_grant.scope,
+        });
+        Ok(BearerToken::new(token, pre_grant.scope.to_string(), self.clock.now()))
+    }
+
+    /// Issue a token in exchange for the credentials of a resource owner.
+    ///
+    /// The grant is only available if an `OwnerVerifier` was configured and the client was
+    /// explicitly trusted with the credentials of owners during registration.
+    pub fn password<'r>(&mut self, request: &'r AccessTokenRequest)
+    -> AccessTokenResult<BearerToken> where 'u: 'r {
+        if !request.valid() {
+            return Err(IssuerError::invalid(()))
+        }
+
+        let client = self.authenticate(request)?;
+
+        match request.grant_type() {
+            Some(ref cow) if cow == "password" => (),
+            None => return Err(IssuerError::invalid(())),
+            Some(_) => return Err(IssuerError::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+        };
+
+        let verifier = match self.owner_verifier {
+            None => return Err(IssuerError::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+            Some(verifier) => verifier,
+        };
+
+        if !client.allows_password_grant() {
+            return Err(IssuerError::invalid((AccessTokenErrorType::UnauthorizedClient,
+                "Client is not trusted with owner credentials")))
+        }
+
+        let owner_id = match (request.username(), request.password()) {
+            (Some(username), Some(password)) => verifier.verify_owner(&username, &password),
+            _ => return Err(IssuerError::invalid(())),
+        }.ok_or(IssuerError::invalid(AccessTokenErrorType::InvalidGrant))?;
+
+        let pre_grant = negotiate_scope(&*self.registrar, &client, request.scope())?;
+
+        let token = self.issuer.issue(GrantRequest{
+            client_id: &pre_grant.client_id,
+            owner_id: &owner_id,
+            redirect_url: &pre_grant.redirect_url,
+            scope: &pre_grant.scope,
+        });
+        Ok(BearerToken::new(token, pre_grant.scope.to_string(), self.clock.now()))
+    }
+
+    /// Poll for the token of a device authorization.
+    ///
+    /// While the resource owner has not yet completed the verification, the client is informed
+    /// that the authorization is pending or that it should slow down its polling.
+    pub fn device_code<'r>(&mut self, request: &'r AccessTokenRequest)
+    -> AccessTokenResult<BearerToken> where 'u: 'r {
+        if !request.valid() {
+            return Err(IssuerError::invalid(()))
+        }
+
+        let client = self.authenticate(request)?;
+
+        match request.grant_type() {
+            Some(ref cow) if cow == DEVICE_CODE_GRANT_TYPE => (),
+            None => return Err(IssuerError::invalid(())),
+            Some(_) => return Err(IssuerError::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+        };
+
+        if !client.allows_grant_type(DEVICE_CODE_GRANT_TYPE) {
+            return Err(unregistered_grant_type())
+        }
+
+        let devices = match self.devices {
+            None => return Err(IssuerError::invalid(AccessTokenErrorType::UnsupportedGrantType)),
+            Some(ref mut devices) => devices,
+        };
+
+        let device_code = request.device_code().ok_or(IssuerError::invalid(()))?;
+        let grant = match devices.poll(client.client_id(), &device_code) {
+            DevicePoll::Approved(grant) => grant,
+            DevicePoll::Pending => return Err(IssuerError::invalid(AccessTokenErrorType::AuthorizationPending)),
+            DevicePoll::SlowDown => return Err(IssuerError::invalid(AccessTokenErrorType::SlowDown)),
+            DevicePoll::Denied => return Err(IssuerError::invalid(AccessTokenErrorType::AccessDenied)),
+            DevicePoll::Expired => return Err(IssuerError::invalid(AccessTokenErrorType::ExpiredToken)),
+            DevicePoll::Unknown => return Err(IssuerError::invalid(AccessTokenErrorType::InvalidGrant)),
+        };
+
+        let token = self.issuer.issue(GrantRequest{
+            client_id: &grant.client_id,
+            owner_id: &grant.owner_id,
+            redirect_url: &grant.redirect_url,
+            scope: &grant.scope,
+        });
+        Ok(BearerToken::new(token, grant.scope.to_string(), self.clock.now()))
+    }
+
+    /// Identify the client of a token request and check its credentials.
+    ///
+    /// Clients which are migrated to another password policy are rehashed at the registrar.
+    fn authenticate<'r>(&mut self, request: &'r AccessTokenRequest)
+    -> AccessTokenResult<Client> where 'u: 'r {
+        let (client_id, authorization) = (request.client_id(), request.authorization());
+        let (client, rehashed) = {
+            let (client_id, auth) = client_credentials(&client_id, &authorization)?;
+            let client = self.registrar.client(client_id).ok_or(
+                IssuerError::unauthorized((), "basic"))?;
+            let rehashed = client.authenticate(auth).map_err(|_|
+                IssuerError::unauthorized((), "basic"))?;
+            (client.clone(), rehashed)
+        };
+
+        match rehashed {
+            Some(rehashed) => {
+                self.registrar.rehash(rehashed.clone());
+                Ok(rehashed)
+            },
+            None => Ok(client),
+        }
+    }
+
+    pub fn with(r: &'u mut Registrar, t: &'u mut Authorizer, i: &'u mut Issuer) -> Self {
+        IssuerRef { registrar: r, authorizer: t, issuer: i, owner_verifier: None, devices: None,
+            replay_reporter: None, clock: &SYSTEM_CLOCK }
+    }
+
+    /// Enable the resource owner password credentials grant with the given verifier.
+    pub fn with_owner_verifier(self, verifier: &'u OwnerVerifier) -> Self {
+        IssuerRef { owner_verifier: Some(verifier), .. self }
+    }
+
+    /// Enable polling for device authorizations started with the given device authorizer.
+    pub fn with_devices(self, devices: &'u mut DeviceAuthorizer) -> Self {
+        IssuerRef { devices: Some(devices), .. self }
+    }
+
+    /// Report replayed authorization codes to the given reporter.
+    pub fn with_replay_reporter(self, reporter: &'u ReplayReporter) -> Self {
+        IssuerRef { replay_reporter: Some(reporter), .. self }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: &'u Clock) -> Self {
+        IssuerRef { clock, .. self }
+    }
+}
+
+/// Negotiate a scope with the registrar for a client which did not specify a redirect url.
+fn negotiate_scope<'a>(registrar: &'a Registrar, client: &'a Client, scope: Option<Cow<str>>)
+-> AccessTokenResult<PreGrant<'a>> {
+    let scope = match scope.map(|scope| scope.as_ref().parse()) {
+        None => None,
+        Some(Err(_)) => return Err(IssuerError::invalid(AccessTokenErrorType::InvalidScope)),
+        Some(Ok(scope)) => Some(scope),
+    };
+
+    let bound_client = registrar.bound_redirect(ClientUrl {
+        client_id: Cow::Borrowed(client.client_id()),
+        redirect_url: None,
+    }).map_err(|_| IssuerError::unauthorized((), "basic"))?;
+
+    bound_client.negotiate(scope).map_err(|_|
+        IssuerError::invalid(AccessTokenErrorType::InvalidScope))
+}
+
+/// Identify the client of a request and check its credentials.
+///
+/// The client is either given as an explicit `client_id` parameter, which is only possible for
+/// public clients, or through the authorization header of the request.
+fn authenticate_client<'a>(registrar: &'a Registrar, client_id: Option<Cow<str>>,
+    authorization: Option<(Cow<str>, Cow<[u8]>)>)
+-> AccessTokenResult<&'a Client> {
+    let (client_id, auth) = client_credentials(&client_id, &authorization)?;
+    let client = registrar.client(client_id).ok_or(
+        IssuerError::unauthorized((), "basic"))?;
+    client.check_authentication(auth).map_err(|_|
+        IssuerError::unauthorized((), "basic"))
+}
+
+/// The error for a grant type which a dynamically registered client did not register for.
+fn unregistered_grant_type() -> IssuerError {
+    IssuerError::invalid((AccessTokenErrorType::UnauthorizedClient,
+        "Grant type was not registered by the client"))
+}
+
+/// Select the identifier and passphrase of the client from the request parameters.
+fn client_credentials<'a>(client_id: &'a Option<Cow<str>>, authorization: &'a Option<(Cow<str>, Cow<[u8]>)>)
+-> AccessTokenResult<(&'a str, Option<&'a [u8]>)> {
+    match (client_id, authorization) {
+        (&None, &Some((ref client_id, ref auth))) => Ok((client_id.as_ref(), Some(auth.as_ref()))),
+        (&Some(ref client_id), &None) => Ok((client_id.as_ref(), None)),
+        _ => Err(IssuerError::invalid(())),
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//                                       Revocation Endpoint                                    //
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Thin wrapper around the types necessary to revoke tokens, as defined in RFC 7009.
+pub struct RevocationRef<'a> {
+    registrar: &'a Registrar,
+    issuer: &'a mut Issuer,
+}
+
+/// Parameters of a token revocation request.
+pub trait RevocationRequest {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+    /// The access or refresh token to revoke.
+    fn token(&self) -> Option<Cow<str>>;
+    /// Optionally indicates the type of the token, `access_token` or `refresh_token`.
+    fn token_type_hint(&self) -> Option<Cow<str>>;
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+    /// The client_id, optional parameter for public clients.
+    fn client_id(&self) -> Option<Cow<str>>;
+}
+
+impl<'u> RevocationRef<'u> {
+    /// Revoke a token and the token issued alongside it.
+    ///
+    /// Only the client to which a token was issued may revoke it. Unknown or already revoked
+    /// tokens are not considered an error, so that clients can not probe for valid tokens and
+    /// need not handle this case specially.
+    pub fn revoke<'r>(&mut self, request: &'r RevocationRequest)
+    -> AccessTokenResult<()> where 'u: 'r {
+        if !request.valid() {
+            return Err(IssuerError::invalid(()))
+        }
+
+        let client = authenticate_client(self.registrar, request.client_id(), request.authorization())?;
+
+        let token = request.token()
+            .ok_or(IssuerError::invalid(()))?;
+
+        // Unknown hints are ignored, the rfc only uses them to speed up the lookup
+        let refresh_first = match request.token_type_hint() {
+            Some(ref hint) if hint == "refresh_token" => true,
+            _ => false,
+        };
+
+        let owner = {
+            let issuer: &Issuer = self.issuer;
+            let grant = if refresh_first {
+                issuer.recover_refresh(&token).or_else(|| issuer.recover_token(&token))
+            } else {
+                issuer.recover_token(&token).or_else(|| issuer.recover_refresh(&token))
+            };
+            grant.map(|grant| grant.client_id.into_owned())
+        };
+
+        match owner {
+            None => return Ok(()),
+            Some(ref owner) if owner == client.client_id() => (),
+            Some(_) => return Err(IssuerError::invalid((AccessTokenErrorType::InvalidGrant,
+                "Token was issued to another client"))),
+        }
+
+        self.issuer.revoke(&token).map_err(|()|
+            IssuerError::invalid(AccessTokenErrorType::UnsupportedTokenType))
+    }
+
+    pub fn with(r: &'u Registrar, i: &'u mut Issuer) -> Self {
+        RevocationRef { registrar: r, issuer: i }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//                                     Introspection Endpoint                                   //
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Thin wrapper around the types necessary to answer token introspection requests, as defined
+/// in RFC 7662.
+pub struct IntrospectionRef<'a> {
+    registrar: &'a Registrar,
+    issuer: &'a Issuer,
+    clock: &'a Clock,
+}
+
+/// Parameters of a token introspection request.
+pub trait IntrospectionRequest {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried. This method exists mainly to make
+    /// frontends straightforward by not having them handle special cases for malformed requests.
+    fn valid(&self) -> bool;
+    /// The access or refresh token to introspect.
+    fn token(&self) -> Option<Cow<str>>;
+    /// Optionally indicates the type of the token, `access_token` or `refresh_token`.
+    fn token_type_hint(&self) -> Option<Cow<str>>;
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+}
+
+/// Meta information about a token, the answer to an introspection request.
+#[derive(Serialize)]
+pub struct Introspection {
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exp: Option<i64>,
+}
+
+impl Introspection {
+    fn inactive() -> Introspection {
+        Introspection { active: false, scope: None, client_id: None, sub: None, exp: None }
+    }
+
+    /// Whether the token is currently valid.
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Convert the introspection into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
+
+impl<'u> IntrospectionRef<'u> {
+    /// Look up the grant associated with a token.
+    ///
+    /// Only resource servers, registered as confidential clients with `Client::with_introspection`,
+    /// may introspect tokens. They identify themselves with the authorization header. Invalid,
+    /// unknown and expired tokens are reported as inactive.
+    pub fn introspect<'r>(&self, request: &'r IntrospectionRequest)
+    -> AccessTokenResult<Introspection> where 'u: 'r {
+        if !request.valid() {
+            return Err(IssuerError::invalid(()))
+        }
+
+        let client = authenticate_client(self.registrar, None, request.authorization())?;
+        if !client.allows_introspection() {
+            return Err(IssuerError::invalid((AccessTokenErrorType::UnauthorizedClient,
+                "Client is not allowed to introspect tokens")))
+        }
+
+        let token = request.token()
+            .ok_or(IssuerError::invalid(()))?;
+
+        let refresh_first = match request.token_type_hint() {
+            Some(ref hint) if hint == "refresh_token" => true,
+            _ => false,
+        };
+
+        let access = || self.issuer.recover_token(&token).map(|grant| (grant, true));
+        let refresh = || self.issuer.recover_refresh(&token).map(|grant| (grant, false));
+        let found = if refresh_first {
+            refresh().or_else(access)
+        } else {
+            access().or_else(refresh)
+        };
+
+        let (grant, is_access) = match found {
+            None => return Ok(Introspection::inactive()),
+            Some(found) => found,
+        };
+
+        // The issuer checks the lifetime of refresh tokens, the grant refers to the access token.
+        if is_access && *grant.until.as_ref() < self.clock.now() {
+            return Ok(Introspection::inactive())
+        }
+
+        Ok(Introspection {
+            active: true,
+            scope: Some(grant.scope.to_string()),
+            client_id: Some(grant.client_id.into_owned()),
+            sub: Some(grant.owner_id.into_owned()),
+            exp: if is_access { Some(grant.until.timestamp()) } else { None },
+        })
+    }
+
+    pub fn with(r: &'u Registrar, i: &'u Issuer) -> Self {
+        IntrospectionRef { registrar: r, issuer: i, clock: &SYSTEM_CLOCK }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: &'u Clock) -> Self {
+        IntrospectionRef { clock, .. self }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//                                 Device Authorization Endpoint                                //
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// The grant type with which clients poll for the token of a device authorization.
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Starts device authorizations for clients which can not receive redirects.
+pub struct DeviceRef<'a> {
+    registrar: &'a Registrar,
+    devices: &'a mut DeviceAuthorizer,
+    clock: &'a Clock,
+}
+
+/// Necessary parameters of a device authorization request.
+pub trait DeviceRequest {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried.
+    fn valid(&self) -> bool;
+    /// The client_id, optional parameter for public clients.
+    fn client_id(&self) -> Option<Cow<str>>;
+    /// Optionally specifies the requested scope.
+    fn scope(&self) -> Option<Cow<str>>;
+    /// User:password of a basic authorization header.
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)>;
+}
+
+/// The codes of a started device authorization together with the verification uri.
+pub struct DeviceAuthorization {
+    codes: DeviceCodes,
+    verification_uri: Url,
+    started_at: DateTime<Utc>,
+}
+
+impl DeviceAuthorization {
+    /// Convert the authorization into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(self) -> String {
+        let mut complete = self.verification_uri.clone();
+        complete.query_pairs_mut().append_pair("user_code", &self.codes.user_code);
+        let remaining = self.codes.until.signed_duration_since(self.started_at);
+        let mut kvmap: HashMap<_, serde_json::Value> = HashMap::new();
+        kvmap.insert("device_code", self.codes.device_code.into());
+        kvmap.insert("user_code", self.codes.user_code.into());
+        kvmap.insert("verification_uri", self.verification_uri.into_string().into());
+        kvmap.insert("verification_uri_complete", complete.into_string().into());
+        kvmap.insert("expires_in", remaining.num_seconds().into());
+        kvmap.insert("interval", self.codes.interval.into());
+        serde_json::to_string(&kvmap).unwrap()
+    }
+}
+
+impl<'u> DeviceRef<'u> {
+    /// Start a device authorization, which the owner completes at the verification uri.
+    pub fn start<'r>(&mut self, request: &'r DeviceRequest, verification_uri: &Url)
+    -> AccessTokenResult<DeviceAuthorization> where 'u: 'r {
+        if !request.valid() {
+            return Err(IssuerError::invalid(()))
+        }
+
+        let client = authenticate_client(self.registrar, request.client_id(), request.authorization())?;
+        let pre_grant = negotiate_scope(self.registrar, client, request.scope())?;
+
+        Ok(DeviceAuthorization {
+            codes: self.devices.start(&pre_grant),
+            verification_uri: verification_uri.clone(),
+            started_at: self.clock.now(),
+        })
+    }
+
+    pub fn with(r: &'u Registrar, d: &'u mut DeviceAuthorizer) -> Self {
+        DeviceRef { registrar: r, devices: d, clock: &SYSTEM_CLOCK }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: &'u Clock) -> Self {
+        DeviceRef { clock, .. self }
+    }
+}
+
+/// Lets the resource owner approve a device authorization by its user code.
+pub struct VerificationRef<'a> {
+    devices: &'a mut DeviceAuthorizer,
+}
+
+/// Necessary parameters of a verification request.
+pub trait VerificationRequest {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried.
+    fn valid(&self) -> bool;
+    /// The user code entered by the resource owner.
+    fn user_code(&self) -> Option<Cow<str>>;
+}
+
+/// A pending device authorization, waiting for the decision of the resource owner.
+pub struct PendingVerification<'a> {
+    pre_grant: PreGrant<'static>,
+    user_code: String,
+    devices: &'a mut DeviceAuthorizer,
+}
+
+impl<'u> VerificationRef<'u> {
+    /// Look up the pending authorization for the user code of the request.
+    ///
+    /// Fails if the code is unknown, expired or was already used.
+    pub fn negotiate<'r>(self, request: &'r VerificationRequest)
+    -> Result<PendingVerification<'u>, ()> {
+        if !request.valid() {
+            return Err(())
+        }
+
+        let user_code = request.user_code().ok_or(())?.into_owned();
+        let pre_grant = self.devices.pending(&user_code).ok_or(())?;
+        Ok(PendingVerification { pre_grant, user_code, devices: self.devices })
+    }
+
+    pub fn with(d: &'u mut DeviceAuthorizer) -> Self {
+        VerificationRef { devices: d }
+    }
+}
+
+impl<'a> PendingVerification<'a> {
+    /// Approve the device authorization on behalf of the owner.
+    pub fn authorize(self, owner_id: &str) -> Result<(), ()> {
+        self.devices.approve(&self.user_code, owner_id)
+    }
+
+    /// Deny the device authorization.
+    pub fn deny(self) -> Result<(), ()> {
+        self.devices.deny(&self.user_code)
+    }
+
+    /// Retrieve a reference to the negotiated parameters, to be displayed to the resource owner.
+    pub fn pre_grant(&self) -> &PreGrant<'static> {
+        &self.pre_grant
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//                                     Registration Endpoint                                    //
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Registers clients at the registrar from the metadata they submit themselves.
+///
+/// Registered clients may request at most the configured scope, which is also their default scope
+/// when they do not specify one. The password grant is never offered to dynamically registered
+/// clients as it requires explicit trust.
+pub struct RegistrationRef<'a> {
+    registrar: &'a mut Registrar,
+    generator: &'a SecretGenerator,
+    scope: &'a Scope,
+    management_uri: Option<&'a Url>,
+    clock: &'a Clock,
+}
+
+/// Client metadata as submitted to the registration endpoint.
+///
+/// Only the fields relevant to this library are recognized, others are silently ignored as
+/// permitted by [RFC 7591](https://tools.ietf.org/html/rfc7591#section-2).
+#[derive(Deserialize, Default, Debug)]
+pub struct ClientMetadata {
+    /// The redirection urls, exactly one is required.
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    /// Either `client_secret_basic` (the default) for a confidential client or `none`.
+    pub token_endpoint_auth_method: Option<String>,
+    /// The grant types the client intends to use, `authorization_code` if empty.
+    #[serde(default)]
+    pub grant_types: Vec<String>,
+    /// The response types the client intends to use, `code` if empty.
+    #[serde(default)]
+    pub response_types: Vec<String>,
+    /// The scope the client may request.
+    pub scope: Option<String>,
+    /// A human-readable name of the client.
+    pub client_name: Option<String>,
+    /// The identifier of the client, required when updating its configuration.
+    pub client_id: Option<String>,
+    /// The current secret of the client, which must match if included in an update.
+    pub client_secret: Option<String>,
+}
+
+/// Necessary parameters of a registration request.
+pub trait RegistrationRequest {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried.
+    fn valid(&self) -> bool;
+    /// The submitted client metadata.
+    fn metadata(&self) -> Option<&ClientMetadata>;
+}
+
+/// Describes why a registration was rejected.
+pub struct RegistrationError {
+    error: RegistrationErrorType,
+    description: &'static str,
+}
+
+/// The registration response, including the generated credentials of the client.
+#[derive(Serialize)]
+pub struct Registration {
+    client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<String>,
+    client_id_issued_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret_expires_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registration_access_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registration_client_uri: Option<String>,
+    redirect_uris: Vec<String>,
+    token_endpoint_auth_method: &'static str,
+    grant_types: Vec<String>,
+    response_types: Vec<String>,
+    scope: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_name: Option<String>,
+}
+
+/// Metadata which passed validation, ready to be turned into a client.
+struct ValidMetadata {
+    redirect_url: Url,
+    confidential: bool,
+    grant_types: Vec<String>,
+    response_types: Vec<String>,
+    scope: Scope,
+    client_name: Option<String>,
+}
+
+const REGISTRATION_GRANT_TYPES: &[&str] = &["authorization_code", "implicit", "refresh_token",
+    "client_credentials", DEVICE_CODE_GRANT_TYPE];
+
+impl RegistrationError {
+    fn redirect_uri(description: &'static str) -> RegistrationError {
+        RegistrationError { error: RegistrationErrorType::InvalidRedirectUri, description }
+    }
+
+    fn metadata(description: &'static str) -> RegistrationError {
+        RegistrationError { error: RegistrationErrorType::InvalidClientMetadata, description }
+    }
+
+    /// Convert the error into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(self) -> String {
+        let kvmap: HashMap<_, _> = vec![
+            ("error", self.error.as_ref()),
+            ("error_description", self.description)].into_iter().collect();
+        serde_json::to_string(&kvmap).unwrap()
+    }
+}
+
+impl Registration {
+    /// Describe the current configuration of a registered client.
+    ///
+    /// Credentials are not included, as only their digests are stored.
+    fn describe(client: &Client, management_uri: Option<&Url>) -> Registration {
+        let registration = client.registration();
+        let registration_client_uri = management_uri.map(|uri| {
+            let mut uri = uri.clone();
+            uri.query_pairs_mut().append_pair("client_id", client.client_id());
+            uri.into_string()
+        });
+
+        Registration {
+            client_id: client.client_id().to_string(),
+            client_secret: None,
+            client_id_issued_at: registration.map_or(0, |reg| reg.issued_at),
+            client_secret_expires_at: None,
+            registration_access_token: None,
+            registration_client_uri,
+            redirect_uris: vec![client.redirect_url().as_str().to_string()],
+            token_endpoint_auth_method: if client.is_confidential() { "client_secret_basic" } else { "none" },
+            grant_types: registration.map_or(Vec::new(), |reg| reg.grant_types.clone()),
+            response_types: registration.map_or(Vec::new(), |reg| reg.response_types.clone()),
+            scope: client.allowed_scope().to_string(),
+            client_name: registration.and_then(|reg| reg.client_name.clone()),
+        }
+    }
+
+    /// The generated identifier of the registered client.
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Convert the registration into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl ValidMetadata {
+    /// Validate submitted metadata against the capabilities of the server.
+    fn validate(metadata: &ClientMetadata, max_scope: &Scope) -> Result<ValidMetadata, RegistrationError> {
+        let redirect_url = match metadata.redirect_uris.len() {
+            0 => return Err(RegistrationError::redirect_uri("Missing redirect uri")),
+            1 => Url::parse(&metadata.redirect_uris[0]).map_err(|_|
+                    RegistrationError::redirect_uri("Malformed redirect uri"))?,
+            _ => return Err(RegistrationError::redirect_uri("Only a single redirect uri is supported")),
+        };
+        validate_redirect_url(&redirect_url)?;
+
+        let confidential = match metadata.token_endpoint_auth_method.as_ref().map(String::as_str) {
+            None | Some("client_secret_basic") => true,
+            Some("none") => false,
+            Some(_) => return Err(RegistrationError::metadata("Unsupported token endpoint authentication method")),
+        };
+
+        let grant_types = if metadata.grant_types.is_empty() {
+            vec!["authorization_code".to_string()]
+        } else {
+            metadata.grant_types.clone()
+        };
+        if grant_types.iter().any(|grant| !REGISTRATION_GRANT_TYPES.contains(&grant.as_str())) {
+            return Err(RegistrationError::metadata("Unsupported grant type"))
+        }
+        if !confidential && grant_types.iter().any(|grant| grant == "client_credentials") {
+            return Err(RegistrationError::metadata("Client credentials require a confidential client"))
+        }
+
+        let response_types = if metadata.response_types.is_empty() {
+            vec!["code".to_string()]
+        } else {
+            metadata.response_types.clone()
+        };
+        if response_types.iter().any(|response| response != "code" && response != "token") {
+            return Err(RegistrationError::metadata("Unsupported response type"))
+        }
+
+        let scope: Scope = match metadata.scope {
+            None => max_scope.clone(),
+            Some(ref scope) => scope.parse().map_err(|_|
+                RegistrationError::metadata("Malformed scope"))?,
+        };
+        if scope.is_empty() || !scope.privileged_to(max_scope) {
+            return Err(RegistrationError::metadata("Scope can not be granted"))
+        }
+
+        Ok(ValidMetadata {
+            redirect_url,
+            confidential,
+            grant_types,
+            response_types,
+            scope,
+            client_name: metadata.client_name.clone(),
+        })
+    }
+
+    fn implicit(&self) -> bool {
+        self.grant_types.iter().any(|grant| grant == "implicit")
+            || self.response_types.iter().any(|response| response == "token")
+    }
+
+    /// Create the client, with a secret if it is confidential.
+    fn into_client(self, client_id: &str, secret: Option<&str>, mut registration: ClientRegistration) -> Client {
+        let implicit = self.implicit();
+        let client = match secret {
+            Some(secret) => Client::confidential(client_id, self.redirect_url, self.scope, secret.as_bytes()),
+            None => Client::public(client_id, self.redirect_url, self.scope),
+        };
+        let client = if implicit { client.with_implicit_grant() } else { client };
+        registration.client_name = self.client_name;
+        registration.grant_types = self.grant_types;
+        registration.response_types = self.response_types;
+        client.with_registration(registration)
+    }
+}
+
+impl<'u> RegistrationRef<'u> {
+    /// Validate the submitted metadata and register the described client.
+    ///
+    /// The `client_id` and, for confidential clients, the `client_secret` are produced by the
+    /// generator. They are returned only once, in the registration response. If a management
+    /// endpoint is configured, a registration access token for it is generated as well.
+    pub fn register<'r>(&mut self, request: &'r RegistrationRequest)
+    -> Result<Registration, RegistrationError> {
+        if !request.valid() {
+            return Err(RegistrationError::metadata("Malformed client metadata"))
+        }
+
+        let metadata = request.metadata().ok_or(RegistrationError::metadata("Malformed client metadata"))?;
+        let valid = ValidMetadata::validate(metadata, self.scope)?;
+
+        let client_id = self.generator.secret();
+        let client_secret = if valid.confidential {
+            Some(self.generator.secret())
+        } else {
+            None
+        };
+        let access_token = self.management_uri.map(|_| self.generator.secret());
+
+        let issued_at = self.clock.now().timestamp();
+        let registration = match access_token {
+            Some(ref token) => ClientRegistration::new(issued_at).with_access_token(token),
+            None => ClientRegistration::new(issued_at),
+        };
+        let client = valid.into_client(&client_id, client_secret.as_ref().map(String::as_str), registration);
+        let mut response = Registration::describe(&client, self.management_uri);
+
+        self.registrar.register(client).map_err(|err| match err {
+            RegistrarError::AlreadyRegistered =>
+                RegistrationError::metadata("Client identifier is already registered"),
+            _ => RegistrationError::metadata("Clients can not be registered"),
+        })?;
+
+        response.client_secret_expires_at = client_secret.as_ref().map(|_| 0);
+        response.client_secret = client_secret;
+        response.registration_access_token = access_token;
+        Ok(response)
+    }
+
+    pub fn with(r: &'u mut Registrar, g: &'u SecretGenerator, scope: &'u Scope) -> Self {
+        RegistrationRef { registrar: r, generator: g, scope, management_uri: None, clock: &SYSTEM_CLOCK }
+    }
+
+    /// Hand out registration access tokens for the client configuration endpoint at the uri.
+    pub fn with_management(self, management_uri: &'u Url) -> Self {
+        RegistrationRef { management_uri: Some(management_uri), .. self }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: &'u Clock) -> Self {
+        RegistrationRef { clock, .. self }
+    }
+}
+
+/// Check that a redirect url can be registered.
+///
+/// Plain http is only accepted for loopback addresses, custom schemes of native applications are
+/// permitted. Fragments are forbidden by RFC 6749.
+fn validate_redirect_url(url: &Url) -> Result<(), RegistrationError> {
+    if url.fragment().is_some() {
+        return Err(RegistrationError::redirect_uri("Redirect uri must not contain a fragment"))
+    }
+
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" => match url.host_str() {
+            Some("localhost") | Some("127.0.0.1") | Some("[::1]") => Ok(()),
+            _ => Err(RegistrationError::redirect_uri("Redirect uri must use https")),
+        },
+        // Private-use schemes of native apps, named after a reverse domain name (RFC 8252).
+        scheme if scheme.contains('.') => Ok(()),
+        _ => Err(RegistrationError::redirect_uri("Unsupported redirect uri scheme")),
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////
+//                                 Client Configuration Endpoint                                //
+//////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Lets dynamically registered clients read, update and delete their own configuration.
+///
+/// Requests are authenticated with the registration access token handed out during registration,
+/// as specified in [RFC 7592](https://tools.ietf.org/html/rfc7592).
+pub struct ManagementRef<'a> {
+    registrar: &'a mut Registrar,
+    scope: &'a Scope,
+    management_uri: &'a Url,
+}
+
+/// Necessary parameters of a client configuration request.
+pub trait ManagementRequest {
+    /// Received request might not be encoded correctly. This method gives implementors the chance
+    /// to signal that a request was received but its encoding was generally malformed. If this is
+    /// the case, then no other attribute will be queried.
+    fn valid(&self) -> bool;
+    /// The client whose configuration is managed.
+    fn client_id(&self) -> Option<Cow<str>>;
+    /// The registration access token, sent as a bearer token.
+    fn token(&self) -> Option<Cow<str>>;
+    /// The replacement metadata for an update.
+    fn metadata(&self) -> Option<&ClientMetadata>;
+}
+
+/// Describes why a client configuration request was rejected.
+pub enum ManagementError {
+    /// The registration access token is missing, invalid or does not belong to the client.
+    Unauthorized,
+
+    /// The updated metadata was rejected.
+    Invalid(RegistrationError),
+}
+
+impl<'u> ManagementRef<'u> {
+    /// Retrieve the current configuration of the client.
+    pub fn read<'r>(&mut self, request: &'r ManagementRequest) -> Result<Registration, ManagementError> {
+        let client = self.authenticate(request)?;
+        Ok(Registration::describe(client, Some(self.management_uri)))
+    }
+
+    /// Replace the configuration of the client.
+    ///
+    /// The submitted metadata must identify the client and any included secret must match the
+    /// current one. Credentials and the type of the client can not be changed this way.
+    pub fn update<'r>(&mut self, request: &'r ManagementRequest) -> Result<Registration, ManagementError> {
+        let previous = self.authenticate(request)?.clone();
+        let metadata = request.metadata().ok_or(ManagementError::Invalid(
+            RegistrationError::metadata("Malformed client metadata")))?;
+
+        if metadata.client_id.as_ref().map(String::as_str) != Some(previous.client_id()) {
+            return Err(ManagementError::Invalid(RegistrationError::metadata("Client identifier does not match")))
+        }
+
+        if let Some(ref secret) = metadata.client_secret {
+            if previous.check_authentication(Some(secret.as_bytes())).is_err() {
+                return Err(ManagementError::Invalid(RegistrationError::metadata("Client secret does not match")))
+            }
+        }
+
+        let valid = ValidMetadata::validate(metadata, self.scope).map_err(ManagementError::Invalid)?;
+        if valid.confidential != previous.is_confidential() {
+            return Err(ManagementError::Invalid(
+                RegistrationError::metadata("Token endpoint authentication method can not be changed")))
+        }
+
+        let registration = previous.registration().cloned().ok_or(ManagementError::Unauthorized)?;
+        let client = valid.into_client(previous.client_id(), None, registration)
+            .with_credentials_of(&previous);
+        let response = Registration::describe(&client, Some(self.management_uri));
+
+        self.registrar.update(client).map_err(|_| ManagementError::Unauthorized)?;
+        Ok(response)
     }
 
-    pub fn with(r: &'u Registrar, t: &'u mut Authorizer, i: &'u mut Issuer) -> Self {
-        IssuerRef { registrar: r, authorizer: t, issuer: i }
+    /// Delete the client, invalidating its registration access token as well.
+    pub fn delete<'r>(&mut self, request: &'r ManagementRequest) -> Result<(), ManagementError> {
+        let client_id = self.authenticate(request)?.client_id().to_string();
+        self.registrar.remove(&client_id).map_err(|_| ManagementError::Unauthorized)
+    }
+
+    /// Find the client of the request and check its registration access token.
+    fn authenticate<'r>(&self, request: &'r ManagementRequest) -> Result<&Client, ManagementError> {
+        if !request.valid() {
+            return Err(ManagementError::Unauthorized)
+        }
+
+        let client_id = request.client_id().ok_or(ManagementError::Unauthorized)?;
+        let token = request.token().ok_or(ManagementError::Unauthorized)?;
+        let client = self.registrar.client(&client_id).ok_or(ManagementError::Unauthorized)?;
+
+        match client.registration() {
+            Some(registration) if registration.check_token(&token) => Ok(client),
+            _ => Err(ManagementError::Unauthorized),
+        }
+    }
+
+    pub fn with(r: &'u mut Registrar, scope: &'u Scope, management_uri: &'u Url) -> Self {
+        ManagementRef { registrar: r, scope, management_uri }
     }
 }
 
@@ -384,6 +1649,7 @@ impl<'u> IssuerRef<'u> {
 pub struct GuardRef<'a> {
     scopes: &'a [Scope],
     issuer: &'a mut Issuer,
+    clock: &'a Clock,
 }
 
 pub trait GuardRequest {
@@ -409,7 +1675,7 @@ impl<'a> GuardRef<'a> {
         let grant = self.issuer.recover_token(&token)
             .ok_or(AccessError::AccessDenied)?;
 
-        if *grant.until.as_ref() < Utc::now() {
+        if *grant.until.as_ref() < self.clock.now() {
             return Err(AccessError::AccessDenied);
         }
 
@@ -426,6 +1692,11 @@ impl<'a> GuardRef<'a> {
     /// ONE of the scopes to access the resource but each scope can require multiple subscopes.
     pub fn with<S>(issuer: &'a mut Issuer, scopes: &'a S) -> Self
     where S: AsRef<[Scope]> {
-        GuardRef { scopes: scopes.as_ref(), issuer: issuer }
+        GuardRef { scopes: scopes.as_ref(), issuer: issuer, clock: &SYSTEM_CLOCK }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: &'a Clock) -> Self {
+        GuardRef { clock, .. self }
     }
 }
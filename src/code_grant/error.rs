@@ -183,6 +183,25 @@ pub enum AccessTokenErrorType {
     /// The requested scope is invalid, unknown, malformed, or exceeds the scope granted by the
     /// resource owner.
     InvalidScope,
+
+    /// The authorization server does not support the revocation of the presented token type.
+    /// That is, the client tried to revoke an access token on a server not supporting this
+    /// feature. Defined in [RFC 7009](https://tools.ietf.org/html/rfc7009#section-2.2.1).
+    UnsupportedTokenType,
+
+    /// The device authorization is still pending as the owner has not yet completed the
+    /// verification. Defined in [RFC 8628](https://tools.ietf.org/html/rfc8628#section-3.5).
+    AuthorizationPending,
+
+    /// The device authorization is still pending but the device is polling too quickly and
+    /// should increase its interval by 5 seconds.
+    SlowDown,
+
+    /// The device code has expired and the device should start a new authorization.
+    ExpiredToken,
+
+    /// The resource owner has denied the device authorization.
+    AccessDenied,
 }
 
 impl AccessTokenErrorType {
@@ -194,6 +213,11 @@ impl AccessTokenErrorType {
             AccessTokenErrorType::UnauthorizedClient => "unauthorized_client",
             AccessTokenErrorType::UnsupportedGrantType => "unsupported_grant_type",
             AccessTokenErrorType::InvalidScope => "invalid_scope",
+            AccessTokenErrorType::UnsupportedTokenType => "unsupported_token_type",
+            AccessTokenErrorType::AuthorizationPending => "authorization_pending",
+            AccessTokenErrorType::SlowDown => "slow_down",
+            AccessTokenErrorType::ExpiredToken => "expired_token",
+            AccessTokenErrorType::AccessDenied => "access_denied",
         }
     }
 }
@@ -297,3 +321,40 @@ impl IntoIterator for AccessTokenError {
         vec.into_iter()
     }
 }
+
+//////////////////////////////////////////////////////////////////////////////////
+//                            Registration Error                                //
+// detailed in https://tools.ietf.org/html/rfc7591#section-3.2.2                //
+//////////////////////////////////////////////////////////////////////////////////
+
+/// Error codes of the dynamic client registration endpoint.
+#[derive(Debug)]
+pub enum RegistrationErrorType {
+    /// The value of one or more redirection URIs is invalid.
+    InvalidRedirectUri,
+
+    /// The value of one of the client metadata fields is invalid and the server has rejected this
+    /// request.
+    InvalidClientMetadata,
+}
+
+impl RegistrationErrorType {
+    fn description(&self) -> &'static str {
+        match *self {
+            RegistrationErrorType::InvalidRedirectUri => "invalid_redirect_uri",
+            RegistrationErrorType::InvalidClientMetadata => "invalid_client_metadata",
+        }
+    }
+}
+
+impl AsRef<str> for RegistrationErrorType {
+    fn as_ref(&self) -> &str {
+        self.description()
+    }
+}
+
+impl fmt::Display for RegistrationErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
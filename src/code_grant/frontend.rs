@@ -17,9 +17,14 @@ use std::str::from_utf8;
 
 use primitives::registrar::PreGrant;
 use super::backend::{AccessTokenRequest, CodeRef, CodeRequest, CodeError, ErrorUrl, IssuerError, IssuerRef};
-use super::backend::{AccessError, GuardRequest, GuardRef};
+use super::backend::{AccessError, GuardRequest, GuardRef, RevocationRequest, RevocationRef};
+use super::backend::{IntrospectionRequest, IntrospectionRef};
+use super::backend::{DeviceRequest, DeviceRef, VerificationRequest, VerificationRef, DEVICE_CODE_GRANT_TYPE};
+use super::backend::{ClientMetadata, RegistrationRequest, RegistrationRef};
+use super::backend::{ManagementError, ManagementRequest, ManagementRef};
 use url::Url;
 use base64;
+use serde_json;
 
 /// Holds the decode query fragments from the url
 struct AuthorizationParameter<'a> {
@@ -29,6 +34,8 @@ struct AuthorizationParameter<'a> {
     scope: Option<Cow<'a, str>>,
     redirect_url: Option<Cow<'a, str>>,
     state: Option<Cow<'a, str>>,
+    code_challenge: Option<Cow<'a, str>>,
+    code_challenge_method: Option<Cow<'a, str>>,
 }
 
 /// Answer from OwnerAuthorizer to indicate the owners choice.
@@ -45,6 +52,51 @@ struct AccessTokenParameter<'a> {
     redirect_url: Option<Cow<'a, str>>,
     grant_type: Option<Cow<'a, str>>,
     code: Option<Cow<'a, str>>,
+    refresh_token: Option<Cow<'a, str>>,
+    scope: Option<Cow<'a, str>>,
+    code_verifier: Option<Cow<'a, str>>,
+    username: Option<Cow<'a, str>>,
+    password: Option<Cow<'a, str>>,
+    device_code: Option<Cow<'a, str>>,
+    authorization: Option<(String, Vec<u8>)>,
+}
+
+struct RevocationParameter<'a> {
+    valid: bool,
+    client_id: Option<Cow<'a, str>>,
+    token: Option<Cow<'a, str>>,
+    token_type_hint: Option<Cow<'a, str>>,
+    authorization: Option<(String, Vec<u8>)>,
+}
+
+struct DeviceParameter<'a> {
+    valid: bool,
+    client_id: Option<Cow<'a, str>>,
+    scope: Option<Cow<'a, str>>,
+    authorization: Option<(String, Vec<u8>)>,
+}
+
+struct VerificationParameter {
+    valid: bool,
+    user_code: Option<String>,
+}
+
+struct RegistrationParameter {
+    valid: bool,
+    metadata: Option<ClientMetadata>,
+}
+
+struct ManagementParameter {
+    valid: bool,
+    client_id: Option<String>,
+    token: Option<String>,
+    metadata: Option<ClientMetadata>,
+}
+
+struct IntrospectionParameter<'a> {
+    valid: bool,
+    token: Option<Cow<'a, str>>,
+    token_type_hint: Option<Cow<'a, str>>,
     authorization: Option<(String, Vec<u8>)>,
 }
 
@@ -70,6 +122,12 @@ pub trait WebRequest {
     /// Contents of the authorization header or none if none exists. An Err value indicates a
     /// malformed header or request.
     fn authheader(&mut self) -> Result<Option<Cow<str>>, ()>;
+    /// The raw body of the request, such as json encoded client metadata. An Err value indicates
+    /// that the body could not be read. The default implementation provides no body, so requests
+    /// which need one are rejected as malformed.
+    fn body(&mut self) -> Result<Vec<u8>, ()> {
+        Err(())
+    }
 }
 
 /// Response representation into which the Request is transformed by the code_grant types.
@@ -93,6 +151,10 @@ pub trait WebResponse where Self: Sized {
         Self::redirect(target.into())
     }
 
+    /// Set the response status to 201
+    fn as_created(self) -> Result<Self, Self::Error>;
+    /// Set the response status to 204
+    fn as_no_content(self) -> Result<Self, Self::Error>;
     /// Set the response status to 400
     fn as_client_error(self) -> Result<Self, Self::Error>;
     /// Set the response status to 401
@@ -128,6 +190,8 @@ fn extract_parameters(params: HashMap<String, Vec<String>>) -> AuthorizationPara
         scope: map.get("scope").map(|scope| scope.to_string().into()),
         redirect_url: map.get("redirect_url").map(|url| url.to_string().into()),
         state: map.get("state").map(|state| state.to_string().into()),
+        code_challenge: map.get("code_challenge").map(|challenge| challenge.to_string().into()),
+        code_challenge_method: map.get("code_challenge_method").map(|method| method.to_string().into()),
     }
 }
 
@@ -138,12 +202,14 @@ impl<'s> CodeRequest for AuthorizationParameter<'s> {
     fn redirect_url(&self) -> Option<Cow<str>> { self.redirect_url.as_ref().map(|c| c.as_ref().into()) }
     fn state(&self) -> Option<Cow<str>> { self.state.as_ref().map(|c| c.as_ref().into()) }
     fn method(&self) -> Option<Cow<str>> { self.method.as_ref().map(|c| c.as_ref().into()) }
+    fn code_challenge(&self) -> Option<Cow<str>> { self.code_challenge.as_ref().map(|c| c.as_ref().into()) }
+    fn code_challenge_method(&self) -> Option<Cow<str>> { self.code_challenge_method.as_ref().map(|c| c.as_ref().into()) }
 }
 
 impl<'s> AuthorizationParameter<'s> {
     fn invalid() -> Self {
         AuthorizationParameter { valid: false, method: None, client_id: None, scope: None,
-            redirect_url: None, state: None }
+            redirect_url: None, state: None, code_challenge: None, code_challenge_method: None }
     }
 }
 
@@ -207,6 +273,12 @@ fn extract_access_token<'l>(params: &'l HashMap<String, Vec<String>>) -> AccessT
         code: map.get("code").map(|v| (*v).into()),
         redirect_url: map.get("redirect_url").map(|v| (*v).into()),
         grant_type: map.get("grant_type").map(|v| (*v).into()),
+        refresh_token: map.get("refresh_token").map(|v| (*v).into()),
+        scope: map.get("scope").map(|v| (*v).into()),
+        code_verifier: map.get("code_verifier").map(|v| (*v).into()),
+        username: map.get("username").map(|v| (*v).into()),
+        password: map.get("password").map(|v| (*v).into()),
+        device_code: map.get("device_code").map(|v| (*v).into()),
         authorization: None,
     }
 }
@@ -217,6 +289,12 @@ impl<'l> AccessTokenRequest for AccessTokenParameter<'l> {
     fn client_id(&self) -> Option<Cow<str>> { self.client_id.clone() }
     fn redirect_url(&self) -> Option<Cow<str>> { self.redirect_url.clone() }
     fn grant_type(&self) -> Option<Cow<str>> { self.grant_type.clone() }
+    fn refresh_token(&self) -> Option<Cow<str>> { self.refresh_token.clone() }
+    fn scope(&self) -> Option<Cow<str>> { self.scope.clone() }
+    fn code_verifier(&self) -> Option<Cow<str>> { self.code_verifier.clone() }
+    fn username(&self) -> Option<Cow<str>> { self.username.clone() }
+    fn password(&self) -> Option<Cow<str>> { self.password.clone() }
+    fn device_code(&self) -> Option<Cow<str>> { self.device_code.clone() }
     fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
         match self.authorization {
             None => None,
@@ -229,7 +307,8 @@ impl<'l> AccessTokenRequest for AccessTokenParameter<'l> {
 impl<'l> AccessTokenParameter<'l> {
     fn invalid() -> Self {
         AccessTokenParameter { valid: false, code: None, client_id: None, redirect_url: None,
-            grant_type: None, authorization: None }
+            grant_type: None, refresh_token: None, scope: None, code_verifier: None,
+            username: None, password: None, device_code: None, authorization: None }
     }
 }
 
@@ -241,36 +320,9 @@ impl GrantFlow {
     }
 
     fn create_valid_params<'a, W: WebRequest>(req: &'a mut W) -> Option<AccessTokenParameter<'a>> {
-        let authorization = match req.authheader() {
+        let authorization = match extract_basic_authorization(req) {
             Err(_) => return None,
-            Ok(None) => None,
-            Ok(Some(ref header)) => {
-                if !header.starts_with("Basic ") {
-                    return None
-                }
-
-                let combined = match base64::decode(&header[6..]) {
-                    Err(_) => return None,
-                    Ok(vec) => vec,
-                };
-
-                let mut split = combined.splitn(2, |&c| c == b':');
-                let client_bin = match split.next() {
-                    None => return None,
-                    Some(client) => client,
-                };
-                let passwd = match split.next() {
-                    None => return None,
-                    Some(passwd64) => passwd64,
-                };
-
-                let client = match from_utf8(client_bin) {
-                    Err(_) => return None,
-                    Ok(client) => client,
-                };
-
-                Some((client.to_string(), passwd.to_vec()))
-            },
+            Ok(authorization) => authorization,
         };
 
         let mut params = match req.urlbody() {
@@ -287,7 +339,15 @@ impl GrantFlow {
     -> Result<Req::Response, Req::Error> where Req: WebRequest
     {
         let PreparedGrant { params, .. } = prepared;
-        match issuer.use_code(&params) {
+        let result = match params.grant_type() {
+            Some(ref grant_type) if grant_type == "refresh_token" => issuer.refresh(&params),
+            Some(ref grant_type) if grant_type == "client_credentials" => issuer.client_credentials(&params),
+            Some(ref grant_type) if grant_type == "password" => issuer.password(&params),
+            Some(ref grant_type) if grant_type == DEVICE_CODE_GRANT_TYPE => issuer.device_code(&params),
+            _ => issuer.use_code(&params),
+        };
+
+        match result {
             Err(IssuerError::Invalid(json_data))
                 => return Req::Response::json(&json_data.to_json())?.as_client_error(),
             Err(IssuerError::Unauthorized(json_data, scheme))
@@ -297,6 +357,490 @@ impl GrantFlow {
     }
 }
 
+/// Decode the client credentials from a `Basic` authorization header, if one exists.
+fn extract_basic_authorization<W: WebRequest>(req: &mut W) -> Result<Option<(String, Vec<u8>)>, ()> {
+    let header = match req.authheader()? {
+        None => return Ok(None),
+        Some(header) => header,
+    };
+
+    if !header.starts_with("Basic ") {
+        return Err(())
+    }
+
+    let combined = base64::decode(&header[6..]).map_err(|_| ())?;
+    let mut split = combined.splitn(2, |&c| c == b':');
+    let client_bin = split.next().ok_or(())?;
+    let passwd = split.next().ok_or(())?;
+    let client = from_utf8(client_bin).map_err(|_| ())?;
+
+    Ok(Some((client.to_string(), passwd.to_vec())))
+}
+
+pub struct RevocationFlow;
+pub struct PreparedRevocation<'l, Req> where
+    Req: WebRequest + 'l,
+{
+    params: RevocationParameter<'l>,
+    req: PhantomData<Req>,
+}
+
+fn extract_revocation<'l>(params: &'l HashMap<String, Vec<String>>) -> RevocationParameter<'l> {
+    let map = params.iter()
+        .filter(|&(_, v)| v.len() == 1)
+        .map(|(k, v)| (k.as_str(), v[0].as_str()))
+        .collect::<HashMap<_, _>>();
+
+    RevocationParameter {
+        valid: true,
+        client_id: map.get("client_id").map(|v| (*v).into()),
+        token: map.get("token").map(|v| (*v).into()),
+        token_type_hint: map.get("token_type_hint").map(|v| (*v).into()),
+        authorization: None,
+    }
+}
+
+impl<'l> RevocationRequest for RevocationParameter<'l> {
+    fn valid(&self) -> bool { self.valid }
+    fn token(&self) -> Option<Cow<str>> { self.token.clone() }
+    fn token_type_hint(&self) -> Option<Cow<str>> { self.token_type_hint.clone() }
+    fn client_id(&self) -> Option<Cow<str>> { self.client_id.clone() }
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        match self.authorization {
+            None => None,
+            Some((ref id, ref pass))
+                => Some((id.as_str().into(), pass.as_slice().into())),
+        }
+    }
+}
+
+impl<'l> RevocationParameter<'l> {
+    fn invalid() -> Self {
+        RevocationParameter { valid: false, client_id: None, token: None, token_type_hint: None,
+            authorization: None }
+    }
+}
+
+impl RevocationFlow {
+    pub fn prepare<W: WebRequest>(req: &mut W) -> Result<PreparedRevocation<W>, W::Error> {
+        let params = RevocationFlow::create_valid_params(req)
+            .unwrap_or(RevocationParameter::invalid());
+        Ok(PreparedRevocation { params: params, req: PhantomData })
+    }
+
+    fn create_valid_params<'a, W: WebRequest>(req: &'a mut W) -> Option<RevocationParameter<'a>> {
+        let authorization = match extract_basic_authorization(req) {
+            Err(_) => return None,
+            Ok(authorization) => authorization,
+        };
+
+        let mut params = match req.urlbody() {
+            Err(_) => return None,
+            Ok(body) => extract_revocation(body),
+        };
+
+        params.authorization = authorization;
+
+        Some(params)
+    }
+
+    /// Revoke the token, responding with an empty body on success as required by RFC 7009.
+    pub fn handle<Req>(mut revoker: RevocationRef, prepared: PreparedRevocation<Req>)
+    -> Result<Req::Response, Req::Error> where Req: WebRequest
+    {
+        let PreparedRevocation { params, .. } = prepared;
+        match revoker.revoke(&params) {
+            Err(IssuerError::Invalid(json_data))
+                => return Req::Response::json(&json_data.to_json())?.as_client_error(),
+            Err(IssuerError::Unauthorized(json_data, scheme))
+                => return Req::Response::json(&json_data.to_json())?.as_unauthorized()?.with_authorization(&scheme),
+            Ok(()) => Req::Response::text(""),
+        }
+    }
+}
+
+pub struct DeviceFlow;
+pub struct PreparedDevice<'l, Req> where
+    Req: WebRequest + 'l,
+{
+    params: DeviceParameter<'l>,
+    req: PhantomData<Req>,
+}
+
+fn extract_device<'l>(params: &'l HashMap<String, Vec<String>>) -> DeviceParameter<'l> {
+    let map = params.iter()
+        .filter(|&(_, v)| v.len() == 1)
+        .map(|(k, v)| (k.as_str(), v[0].as_str()))
+        .collect::<HashMap<_, _>>();
+
+    DeviceParameter {
+        valid: true,
+        client_id: map.get("client_id").map(|v| (*v).into()),
+        scope: map.get("scope").map(|v| (*v).into()),
+        authorization: None,
+    }
+}
+
+impl<'l> DeviceRequest for DeviceParameter<'l> {
+    fn valid(&self) -> bool { self.valid }
+    fn client_id(&self) -> Option<Cow<str>> { self.client_id.clone() }
+    fn scope(&self) -> Option<Cow<str>> { self.scope.clone() }
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        match self.authorization {
+            None => None,
+            Some((ref id, ref pass))
+                => Some((id.as_str().into(), pass.as_slice().into())),
+        }
+    }
+}
+
+impl<'l> DeviceParameter<'l> {
+    fn invalid() -> Self {
+        DeviceParameter { valid: false, client_id: None, scope: None, authorization: None }
+    }
+}
+
+impl DeviceFlow {
+    pub fn prepare<W: WebRequest>(req: &mut W) -> Result<PreparedDevice<W>, W::Error> {
+        let params = DeviceFlow::create_valid_params(req)
+            .unwrap_or(DeviceParameter::invalid());
+        Ok(PreparedDevice { params: params, req: PhantomData })
+    }
+
+    fn create_valid_params<'a, W: WebRequest>(req: &'a mut W) -> Option<DeviceParameter<'a>> {
+        let authorization = match extract_basic_authorization(req) {
+            Err(_) => return None,
+            Ok(authorization) => authorization,
+        };
+
+        let mut params = match req.urlbody() {
+            Err(_) => return None,
+            Ok(body) => extract_device(body),
+        };
+
+        params.authorization = authorization;
+
+        Some(params)
+    }
+
+    /// Start a device authorization, directing the owner to the given verification uri.
+    pub fn handle<Req>(mut device: DeviceRef, prepared: PreparedDevice<Req>, verification_uri: &Url)
+    -> Result<Req::Response, Req::Error> where Req: WebRequest
+    {
+        let PreparedDevice { params, .. } = prepared;
+        match device.start(&params, verification_uri) {
+            Err(IssuerError::Invalid(json_data))
+                => return Req::Response::json(&json_data.to_json())?.as_client_error(),
+            Err(IssuerError::Unauthorized(json_data, scheme))
+                => return Req::Response::json(&json_data.to_json())?.as_unauthorized()?.with_authorization(&scheme),
+            Ok(authorization) => Req::Response::json(&authorization.to_json()),
+        }
+    }
+}
+
+pub struct VerificationFlow;
+pub struct PreparedVerification<'l, Req> where
+    Req: WebRequest + 'l,
+{
+    request: &'l mut Req,
+    params: VerificationParameter,
+}
+
+fn extract_verification(params: &HashMap<String, Vec<String>>) -> VerificationParameter {
+    let user_code = match params.get("user_code") {
+        Some(values) if values.len() == 1 => Some(values[0].clone()),
+        _ => None,
+    };
+
+    VerificationParameter {
+        valid: true,
+        user_code,
+    }
+}
+
+impl VerificationRequest for VerificationParameter {
+    fn valid(&self) -> bool { self.valid }
+    fn user_code(&self) -> Option<Cow<str>> { self.user_code.as_ref().map(|v| v.as_str().into()) }
+}
+
+impl VerificationParameter {
+    fn invalid() -> Self {
+        VerificationParameter { valid: false, user_code: None }
+    }
+}
+
+impl VerificationFlow {
+    /// Read the user code from the query, or from the body if the owner submitted a form.
+    pub fn prepare<W: WebRequest>(incoming: &mut W) -> Result<PreparedVerification<W>, W::Error> {
+        let mut params = incoming.query()
+            .map(|query| extract_verification(&query))
+            .unwrap_or_else(|_| VerificationParameter::invalid());
+
+        if params.user_code.is_none() {
+            if let Ok(body) = incoming.urlbody() {
+                params = extract_verification(body);
+            }
+        }
+
+        Ok(PreparedVerification { request: incoming, params })
+    }
+
+    /// Let the owner approve or deny the device authorization of the user code.
+    ///
+    /// Unlike the authorization code flow, there is no client to redirect to. The response of the
+    /// owner authorizer is returned in all cases, such that it can inform the owner about the
+    /// outcome and that the device will continue on its own.
+    pub fn handle<'c, Req>(verifier: VerificationRef<'c>, prepared: PreparedVerification<'c, Req>, page_handler: &OwnerAuthorizer<Request=Req>)
+    -> Result<Req::Response, Req::Error> where
+        Req: WebRequest,
+    {
+        let PreparedVerification { request: req, params } = prepared;
+        let pending = match verifier.negotiate(&params) {
+            Err(()) => return Err(OAuthError::AccessDenied.into()),
+            Ok(pending) => pending,
+        };
+
+        let (result, response) = match page_handler.get_owner_authorization(req, pending.pre_grant())? {
+            (Authentication::Failed, response)
+                => (pending.deny(), response),
+            (Authentication::InProgress, response)
+                => return Ok(response),
+            (Authentication::Authenticated(owner), response)
+                => (pending.authorize(&owner), response),
+        };
+
+        match result {
+            Err(()) => Err(OAuthError::AccessDenied.into()),
+            Ok(()) => Ok(response),
+        }
+    }
+}
+
+pub struct RegistrationFlow;
+pub struct PreparedRegistration<Req> where
+    Req: WebRequest,
+{
+    params: RegistrationParameter,
+    req: PhantomData<Req>,
+}
+
+impl RegistrationRequest for RegistrationParameter {
+    fn valid(&self) -> bool { self.valid }
+    fn metadata(&self) -> Option<&ClientMetadata> { self.metadata.as_ref() }
+}
+
+impl RegistrationParameter {
+    fn invalid() -> Self {
+        RegistrationParameter { valid: false, metadata: None }
+    }
+}
+
+impl RegistrationFlow {
+    pub fn prepare<W: WebRequest>(req: &mut W) -> Result<PreparedRegistration<W>, W::Error> {
+        let params = req.body().ok()
+            .and_then(|body| serde_json::from_slice(&body).ok())
+            .map(|metadata| RegistrationParameter { valid: true, metadata: Some(metadata) })
+            .unwrap_or(RegistrationParameter::invalid());
+        Ok(PreparedRegistration { params: params, req: PhantomData })
+    }
+
+    /// Register the client, responding with its credentials and status 201 on success.
+    pub fn handle<Req>(mut registration: RegistrationRef, prepared: PreparedRegistration<Req>)
+    -> Result<Req::Response, Req::Error> where Req: WebRequest
+    {
+        let PreparedRegistration { params, .. } = prepared;
+        match registration.register(&params) {
+            Err(error) => Req::Response::json(&error.to_json())?.as_client_error(),
+            Ok(registered) => Req::Response::json(&registered.to_json())?.as_created(),
+        }
+    }
+}
+
+pub struct ClientManagementFlow;
+pub struct PreparedManagement<Req> where
+    Req: WebRequest,
+{
+    params: ManagementParameter,
+    req: PhantomData<Req>,
+}
+
+impl ManagementRequest for ManagementParameter {
+    fn valid(&self) -> bool { self.valid }
+    fn client_id(&self) -> Option<Cow<str>> { self.client_id.as_ref().map(|id| id.as_str().into()) }
+    fn token(&self) -> Option<Cow<str>> { self.token.as_ref().map(|token| token.as_str().into()) }
+    fn metadata(&self) -> Option<&ClientMetadata> { self.metadata.as_ref() }
+}
+
+impl ManagementParameter {
+    fn invalid() -> Self {
+        ManagementParameter { valid: false, client_id: None, token: None, metadata: None }
+    }
+}
+
+impl ClientManagementFlow {
+    /// Prepare a request to the client configuration endpoint.
+    ///
+    /// The body is only parsed as client metadata when `with_body` is set, as is the case for
+    /// update requests.
+    pub fn prepare<W: WebRequest>(req: &mut W, with_body: bool) -> Result<PreparedManagement<W>, W::Error> {
+        let params = ClientManagementFlow::create_valid_params(req, with_body)
+            .unwrap_or(ManagementParameter::invalid());
+        Ok(PreparedManagement { params: params, req: PhantomData })
+    }
+
+    fn create_valid_params<W: WebRequest>(req: &mut W, with_body: bool) -> Option<ManagementParameter> {
+        let client_id = match req.query() {
+            Err(()) => return None,
+            Ok(query) => match query.get("client_id") {
+                Some(values) if values.len() == 1 => Some(values[0].clone()),
+                _ => None,
+            },
+        };
+
+        let token = match req.authheader() {
+            Err(()) => return None,
+            Ok(None) => None,
+            Ok(Some(header)) => {
+                if !header.starts_with("Bearer ") {
+                    return None
+                }
+                Some(header[7..].to_string())
+            },
+        };
+
+        let metadata = if with_body {
+            match req.body().ok().and_then(|body| serde_json::from_slice(&body).ok()) {
+                None => return None,
+                metadata => metadata,
+            }
+        } else {
+            None
+        };
+
+        Some(ManagementParameter { valid: true, client_id, token, metadata })
+    }
+
+    /// Respond with the current configuration of the client.
+    pub fn handle_read<Req>(mut management: ManagementRef, prepared: PreparedManagement<Req>)
+    -> Result<Req::Response, Req::Error> where Req: WebRequest
+    {
+        let PreparedManagement { params, .. } = prepared;
+        match management.read(&params) {
+            Err(error) => ClientManagementFlow::error_response::<Req>(error),
+            Ok(registration) => Req::Response::json(&registration.to_json()),
+        }
+    }
+
+    /// Replace the configuration of the client, responding with the new configuration.
+    pub fn handle_update<Req>(mut management: ManagementRef, prepared: PreparedManagement<Req>)
+    -> Result<Req::Response, Req::Error> where Req: WebRequest
+    {
+        let PreparedManagement { params, .. } = prepared;
+        match management.update(&params) {
+            Err(error) => ClientManagementFlow::error_response::<Req>(error),
+            Ok(registration) => Req::Response::json(&registration.to_json()),
+        }
+    }
+
+    /// Delete the client, responding with status 204 on success.
+    pub fn handle_delete<Req>(mut management: ManagementRef, prepared: PreparedManagement<Req>)
+    -> Result<Req::Response, Req::Error> where Req: WebRequest
+    {
+        let PreparedManagement { params, .. } = prepared;
+        match management.delete(&params) {
+            Err(error) => ClientManagementFlow::error_response::<Req>(error),
+            Ok(()) => Req::Response::text("")?.as_no_content(),
+        }
+    }
+
+    fn error_response<Req>(error: ManagementError) -> Result<Req::Response, Req::Error> where Req: WebRequest {
+        match error {
+            ManagementError::Unauthorized
+                => Req::Response::text("")?.as_unauthorized()?.with_authorization("Bearer"),
+            ManagementError::Invalid(error)
+                => Req::Response::json(&error.to_json())?.as_client_error(),
+        }
+    }
+}
+
+pub struct IntrospectionFlow;
+pub struct PreparedIntrospection<'l, Req> where
+    Req: WebRequest + 'l,
+{
+    params: IntrospectionParameter<'l>,
+    req: PhantomData<Req>,
+}
+
+fn extract_introspection<'l>(params: &'l HashMap<String, Vec<String>>) -> IntrospectionParameter<'l> {
+    let map = params.iter()
+        .filter(|&(_, v)| v.len() == 1)
+        .map(|(k, v)| (k.as_str(), v[0].as_str()))
+        .collect::<HashMap<_, _>>();
+
+    IntrospectionParameter {
+        valid: true,
+        token: map.get("token").map(|v| (*v).into()),
+        token_type_hint: map.get("token_type_hint").map(|v| (*v).into()),
+        authorization: None,
+    }
+}
+
+impl<'l> IntrospectionRequest for IntrospectionParameter<'l> {
+    fn valid(&self) -> bool { self.valid }
+    fn token(&self) -> Option<Cow<str>> { self.token.clone() }
+    fn token_type_hint(&self) -> Option<Cow<str>> { self.token_type_hint.clone() }
+    fn authorization(&self) -> Option<(Cow<str>, Cow<[u8]>)> {
+        match self.authorization {
+            None => None,
+            Some((ref id, ref pass))
+                => Some((id.as_str().into(), pass.as_slice().into())),
+        }
+    }
+}
+
+impl<'l> IntrospectionParameter<'l> {
+    fn invalid() -> Self {
+        IntrospectionParameter { valid: false, token: None, token_type_hint: None, authorization: None }
+    }
+}
+
+impl IntrospectionFlow {
+    pub fn prepare<W: WebRequest>(req: &mut W) -> Result<PreparedIntrospection<W>, W::Error> {
+        let params = IntrospectionFlow::create_valid_params(req)
+            .unwrap_or(IntrospectionParameter::invalid());
+        Ok(PreparedIntrospection { params: params, req: PhantomData })
+    }
+
+    fn create_valid_params<'a, W: WebRequest>(req: &'a mut W) -> Option<IntrospectionParameter<'a>> {
+        let authorization = match extract_basic_authorization(req) {
+            Err(_) => return None,
+            Ok(authorization) => authorization,
+        };
+
+        let mut params = match req.urlbody() {
+            Err(_) => return None,
+            Ok(body) => extract_introspection(body),
+        };
+
+        params.authorization = authorization;
+
+        Some(params)
+    }
+
+    pub fn handle<Req>(introspector: IntrospectionRef, prepared: PreparedIntrospection<Req>)
+    -> Result<Req::Response, Req::Error> where Req: WebRequest
+    {
+        let PreparedIntrospection { params, .. } = prepared;
+        match introspector.introspect(&params) {
+            Err(IssuerError::Invalid(json_data))
+                => return Req::Response::json(&json_data.to_json())?.as_client_error(),
+            Err(IssuerError::Unauthorized(json_data, scheme))
+                => return Req::Response::json(&json_data.to_json())?.as_unauthorized()?.with_authorization(&scheme),
+            Ok(introspection) => Req::Response::json(&introspection.to_json()),
+        }
+    }
+}
+
 pub struct AccessFlow;
 pub struct PreparedAccess<'l, Req> where
     Req: WebRequest + 'l,
@@ -0,0 +1,152 @@
+//! Authorization server metadata for discovery by clients.
+//!
+//! The document describes the endpoints and capabilities of an authorization server as specified
+//! in [RFC 8414](https://tools.ietf.org/html/rfc8414). It is usually served at
+//! `/.well-known/oauth-authorization-server` relative to the issuer and independent of the
+//! frontend, so that clients need not be configured with each endpoint url individually.
+use primitives::scope::Scope;
+use url::Url;
+use serde_json;
+
+/// The well-known path at which the metadata document is expected.
+pub const WELL_KNOWN_PATH: &str = "/.well-known/oauth-authorization-server";
+
+/// An authorization server metadata document.
+///
+/// Endpoints and capabilities are added with the consuming `with_*` methods. Repeatedly adding
+/// the same capability has no additional effect.
+#[derive(Serialize, Clone, Debug)]
+pub struct Metadata {
+    issuer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    authorization_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revocation_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    introspection_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_authorization_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registration_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jwks_uri: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    scopes_supported: Vec<String>,
+    response_types_supported: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    grant_types_supported: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    token_endpoint_auth_methods_supported: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    code_challenge_methods_supported: Vec<String>,
+}
+
+impl Metadata {
+    /// Start a document for the issuer identifier, which must be an `https` url without query or
+    /// fragment in production use.
+    pub fn new(issuer: Url) -> Metadata {
+        Metadata {
+            issuer: issuer.into_string(),
+            authorization_endpoint: None,
+            token_endpoint: None,
+            revocation_endpoint: None,
+            introspection_endpoint: None,
+            device_authorization_endpoint: None,
+            registration_endpoint: None,
+            jwks_uri: None,
+            scopes_supported: Vec::new(),
+            response_types_supported: Vec::new(),
+            grant_types_supported: Vec::new(),
+            token_endpoint_auth_methods_supported: Vec::new(),
+            code_challenge_methods_supported: Vec::new(),
+        }
+    }
+
+    /// Set the url of the authorization endpoint.
+    pub fn with_authorization_endpoint(mut self, url: Url) -> Metadata {
+        self.authorization_endpoint = Some(url.into_string());
+        self
+    }
+
+    /// Set the url of the token endpoint.
+    pub fn with_token_endpoint(mut self, url: Url) -> Metadata {
+        self.token_endpoint = Some(url.into_string());
+        self
+    }
+
+    /// Set the url of the token revocation endpoint.
+    pub fn with_revocation_endpoint(mut self, url: Url) -> Metadata {
+        self.revocation_endpoint = Some(url.into_string());
+        self
+    }
+
+    /// Set the url of the token introspection endpoint.
+    pub fn with_introspection_endpoint(mut self, url: Url) -> Metadata {
+        self.introspection_endpoint = Some(url.into_string());
+        self
+    }
+
+    /// Set the url of the device authorization endpoint.
+    pub fn with_device_authorization_endpoint(mut self, url: Url) -> Metadata {
+        self.device_authorization_endpoint = Some(url.into_string());
+        self
+    }
+
+    /// Set the url of the dynamic client registration endpoint.
+    pub fn with_registration_endpoint(mut self, url: Url) -> Metadata {
+        self.registration_endpoint = Some(url.into_string());
+        self
+    }
+
+    /// Set the url of the JSON Web Key Set with which signed tokens can be verified.
+    pub fn with_jwks_uri(mut self, url: Url) -> Metadata {
+        self.jwks_uri = Some(url.into_string());
+        self
+    }
+
+    /// Advertise all scope-tokens of the scope.
+    pub fn with_scope(mut self, scope: &Scope) -> Metadata {
+        for token in scope.to_string().split(' ').filter(|token| !token.is_empty()) {
+            Metadata::insert(&mut self.scopes_supported, token);
+        }
+        self
+    }
+
+    /// Advertise a supported `response_type`, such as `code` or `token`.
+    pub fn with_response_type(mut self, response_type: &str) -> Metadata {
+        Metadata::insert(&mut self.response_types_supported, response_type);
+        self
+    }
+
+    /// Advertise a supported `grant_type` of the token endpoint.
+    pub fn with_grant_type(mut self, grant_type: &str) -> Metadata {
+        Metadata::insert(&mut self.grant_types_supported, grant_type);
+        self
+    }
+
+    /// Advertise a method with which clients can authenticate at the token endpoint.
+    pub fn with_token_endpoint_auth_method(mut self, method: &str) -> Metadata {
+        Metadata::insert(&mut self.token_endpoint_auth_methods_supported, method);
+        self
+    }
+
+    /// Advertise a supported PKCE code challenge method.
+    pub fn with_code_challenge_method(mut self, method: &str) -> Metadata {
+        Metadata::insert(&mut self.code_challenge_methods_supported, method);
+        self
+    }
+
+    /// Convert the document into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn insert(list: &mut Vec<String>, value: &str) {
+        if !list.iter().any(|present| present == value) {
+            list.push(value.to_string());
+        }
+    }
+}
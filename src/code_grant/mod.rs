@@ -17,6 +17,7 @@
 pub mod backend;
 pub mod error;
 pub mod frontend;
+pub mod metadata;
 
 #[cfg(test)]
 mod tests;
@@ -25,5 +26,6 @@ pub use primitives::scope::Scope;
 
 pub mod prelude {
     pub use primitives::prelude::*;
-    pub use super::backend::{CodeRef, IssuerRef, GuardRef};
+    pub use super::backend::{CodeRef, IssuerRef, GuardRef, IntrospectionRef, RevocationRef};
+    pub use super::backend::{DeviceRef, VerificationRef};
 }
@@ -1,16 +1,20 @@
 use super::frontend::*;
-use super::backend::{CodeRef, ErrorUrl, IssuerRef, GuardRef};
-use primitives::authorizer::Storage;
+use super::backend::{CodeRef, ErrorUrl, IssuerRef, GuardRef, IntrospectionRef, OwnerVerifier, ReplayReporter, RevocationRef};
+use super::backend::{DeviceRef, ManagementRef, RegistrationRef, VerificationRef, DEVICE_CODE_GRANT_TYPE};
+use primitives::authorizer::{Authorizer, RedeemedCode, Storage};
+use primitives::clock::MockClock;
+use primitives::device::DeviceStorage;
 use primitives::generator::{TokenGenerator, RandomGenerator};
-use primitives::issuer::TokenMap;
-use primitives::registrar::{Client, ClientMap, PreGrant};
+use primitives::issuer::{IssuedToken, Issuer, TokenMap, TokenSigner};
+use primitives::registrar::{Client, ClientMap, ClientRegistration, PreGrant};
 use primitives::scope::Scope;
 use primitives::grant::{GrantRef, GrantRequest};
 
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use url::Url;
+use url::{self, Url};
 use serde_json;
 use base64;
 
@@ -20,12 +24,21 @@ struct CraftedRequest {
     auth: Option<String>,
 }
 
+/// A request with a raw body, for endpoints not using url encoded parameters.
+struct CraftedBodyRequest {
+    query: HashMap<String, Vec<String>>,
+    auth: Option<String>,
+    body: String,
+}
+
 #[derive(Debug)]
 enum CraftedResponse {
     Redirect(Url),
     Text(String),
     Json(String),
     RedirectFromError(Url),
+    Created(Box<CraftedResponse>),
+    NoContent(Box<CraftedResponse>),
     ClientError(Box<CraftedResponse>),
     Unauthorized(Box<CraftedResponse>),
     Authorization(Box<CraftedResponse>, String),
@@ -48,6 +61,27 @@ impl WebRequest for CraftedRequest {
     }
 }
 
+impl WebRequest for CraftedBodyRequest {
+    type Response = CraftedResponse;
+    type Error = OAuthError;
+
+    fn query(&mut self) -> Result<HashMap<String, Vec<String>>, ()> {
+        Ok(self.query.clone())
+    }
+
+    fn urlbody(&mut self) -> Result<&HashMap<String, Vec<String>>, ()> {
+        Err(())
+    }
+
+    fn authheader(&mut self) -> Result<Option<Cow<str>>, ()> {
+        Ok(self.auth.as_ref().map(|bearer| bearer.as_str().into()))
+    }
+
+    fn body(&mut self) -> Result<Vec<u8>, ()> {
+        Ok(self.body.as_bytes().to_vec())
+    }
+}
+
 impl WebResponse for CraftedResponse {
     type Error = OAuthError;
     fn redirect(url: Url) -> Result<Self, OAuthError> {
@@ -66,6 +100,14 @@ impl WebResponse for CraftedResponse {
         Ok(CraftedResponse::RedirectFromError(target.into()))
     }
 
+    fn as_created(self) -> Result<Self, OAuthError> {
+        Ok(CraftedResponse::Created(self.into()))
+    }
+
+    fn as_no_content(self) -> Result<Self, OAuthError> {
+        Ok(CraftedResponse::NoContent(self.into()))
+    }
+
     fn as_client_error(self) -> Result<Self, OAuthError> {
         Ok(CraftedResponse::ClientError(self.into()))
     }
@@ -408,6 +450,49 @@ fn auth_request_error_malformed_scope() {
         &Allow(EXAMPLE_OWNER_ID.to_string()));
 }
 
+#[test]
+fn auth_request_error_invalid_scope() {
+    // A scope which the client is not allowed to request
+    let invalid_scope = CraftedRequest {
+        query: Some(vec![("response_type", "code"),
+                         ("client_id", EXAMPLE_CLIENT_ID),
+                         ("redirect_url", EXAMPLE_REDIRECT_URL),
+                         ("scope", "admin")]
+            .iter().as_single_value_query()),
+        urlbody: None,
+        auth: None,
+    };
+
+    AuthorizationSetup::new().test_error_redirect(invalid_scope,
+        &Allow(EXAMPLE_OWNER_ID.to_string()));
+}
+
+#[test]
+fn auth_request_negotiated_scope() {
+    use primitives::authorizer::Authorizer;
+    let mut setup = AuthorizationSetup::new();
+    // Only the allowed part of the requested scope is granted
+    let mut partial_scope = CraftedRequest {
+        query: Some(vec![("response_type", "code"),
+                         ("client_id", EXAMPLE_CLIENT_ID),
+                         ("redirect_url", EXAMPLE_REDIRECT_URL),
+                         ("scope", "example admin")]
+            .iter().as_single_value_query()),
+        urlbody: None,
+        auth: None,
+    };
+
+    let prepared = AuthorizationFlow::prepare(&mut partial_scope).expect("Failure during authorization preparation");
+    let pagehandler = Allow(EXAMPLE_OWNER_ID.to_string());
+    match AuthorizationFlow::handle(CodeRef::with(&mut setup.registrar, &mut setup.authorizer), prepared, &pagehandler) {
+        Ok(CraftedResponse::Redirect(_)) => (),
+        resp => panic!("Expected redirect with code, got {:?}", resp),
+    }
+
+    let grant = setup.authorizer.extract("AuthToken").expect("No code was issued");
+    assert_eq!(grant.scope.as_ref(), &"example".parse::<Scope>().unwrap());
+}
+
 struct AccessTokenSetup {
     registrar: ClientMap,
     authorizer: Storage<TestGenerator>,
@@ -497,7 +582,7 @@ impl AccessTokenSetup {
 
     fn test_simple_error(&mut self, mut req: CraftedRequest) {
         let prepared = GrantFlow::prepare(&mut req).expect("Failed during access request preparation");
-        match GrantFlow::handle(IssuerRef::with(&self.registrar, &mut self.authorizer, &mut self.issuer), prepared) {
+        match GrantFlow::handle(IssuerRef::with(&mut self.registrar, &mut self.authorizer, &mut self.issuer), prepared) {
             Ok(ref response) =>
                 Self::assert_json_error_set(response),
             resp => panic!("Expected non-error reponse, got {:?}", resp),
@@ -720,119 +805,1752 @@ fn access_request_wrong_grant_type() {
     setup.test_simple_error(wrong_grant_type);
 }
 
-struct ResourceSetup {
+struct CountingReporter(::std::cell::Cell<usize>);
+
+impl ReplayReporter for CountingReporter {
+    fn code_replayed(&self, code: &RedeemedCode, revoked: bool) {
+        assert_eq!(code.client_id, EXAMPLE_CLIENT_ID);
+        assert_eq!(code.owner_id, EXAMPLE_OWNER_ID);
+        assert!(revoked);
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+#[test]
+fn access_request_replayed_code() {
+    let mut setup = AccessTokenSetup::private_client();
+    let reporter = CountingReporter(::std::cell::Cell::new(0));
+    let request = || CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "authorization_code"),
+                         ("code", &setup.authtoken),
+                         ("redirect_url", EXAMPLE_REDIRECT_URL)]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    let mut first = request();
+    let mut second = request();
+    let prepared = GrantFlow::prepare(&mut first).expect("Failed during access request preparation");
+    let token = match GrantFlow::handle(IssuerRef::with(&mut setup.registrar, &mut setup.authorizer, &mut setup.issuer)
+        .with_replay_reporter(&reporter), prepared) {
+        Ok(CraftedResponse::Json(json)) => {
+            let parsed: HashMap<String, String> = serde_json::from_str(&json).unwrap();
+            parsed.get("access_token").expect("No access token issued").clone()
+        },
+        resp => panic!("Expected json response, got {:?}", resp),
+    };
+    assert!(setup.issuer.recover_token(&token).is_some());
+
+    // Redeeming the code again fails and revokes the token issued for it
+    let prepared = GrantFlow::prepare(&mut second).expect("Failed during access request preparation");
+    match GrantFlow::handle(IssuerRef::with(&mut setup.registrar, &mut setup.authorizer, &mut setup.issuer)
+        .with_replay_reporter(&reporter), prepared) {
+        Ok(CraftedResponse::ClientError(ref inner)) => match **inner {
+            CraftedResponse::Json(ref json) => {
+                let parsed: HashMap<String, String> = serde_json::from_str(json).unwrap();
+                assert_eq!(parsed.get("error").map(String::as_str), Some("invalid_grant"));
+            },
+            ref resp => panic!("Expected json error response, got {:?}", resp),
+        },
+        resp => panic!("Expected json error response, got {:?}", resp),
+    }
+    assert!(setup.issuer.recover_token(&token).is_none());
+    assert_eq!(reporter.0.get(), 1);
+}
+
+#[test]
+fn access_request_replayed_code_after_expiry() {
+    use primitives::lifetime::{LifetimePolicy, Lifetimes};
+    use chrono::{Duration, Utc};
+    let mut setup = AccessTokenSetup::private_client();
+    let clock = MockClock::new(Utc::now());
+    let mut issuer = TokenMap::new(RandomGenerator::new(16))
+        .with_lifetimes(LifetimePolicy::new()
+            .with_default(Lifetimes::new().with_access(Duration::minutes(1))))
+        .with_clock(Arc::new(clock.clone()));
+    setup.registrar.register_client(Client::confidential(EXAMPLE_CLIENT_ID,
+        EXAMPLE_REDIRECT_URL.parse().unwrap(),
+        EXAMPLE_SCOPE.parse().unwrap(),
+        EXAMPLE_PASSPHRASE.as_bytes()).with_refresh_rotation());
+    let reporter = CountingReporter(::std::cell::Cell::new(0));
+    let code = || CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "authorization_code"),
+                         ("code", &setup.authtoken),
+                         ("redirect_url", EXAMPLE_REDIRECT_URL)]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    let mut first = code();
+    let mut second = code();
+    let prepared = GrantFlow::prepare(&mut first).expect("Failed during access request preparation");
+    let issued = match GrantFlow::handle(IssuerRef::with(&mut setup.registrar, &mut setup.authorizer, &mut issuer)
+        .with_replay_reporter(&reporter), prepared) {
+        Ok(CraftedResponse::Json(json)) => serde_json::from_str::<HashMap<String, String>>(&json).unwrap(),
+        resp => panic!("Expected json response, got {:?}", resp),
+    };
+
+    // The refresh token is rotated and the original access token expires
+    let mut refresh = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("refresh_token", issued.get("refresh_token").unwrap())]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+    let prepared = GrantFlow::prepare(&mut refresh).expect("Failed during refresh request preparation");
+    let refreshed = match GrantFlow::handle(IssuerRef::with(&mut setup.registrar, &mut setup.authorizer, &mut issuer),
+        prepared) {
+        Ok(CraftedResponse::Json(json)) => serde_json::from_str::<HashMap<String, String>>(&json).unwrap(),
+        resp => panic!("Expected json response, got {:?}", resp),
+    };
+    let refreshed = refreshed.get("refresh_token").unwrap().clone();
+    clock.advance(Duration::minutes(2));
+    issuer.purge_expired();
+    assert!(issuer.recover_token(issued.get("access_token").unwrap()).is_none());
+    assert!(issuer.recover_refresh(&refreshed).is_some());
+
+    // Redeeming the code again still revokes the whole family
+    let prepared = GrantFlow::prepare(&mut second).expect("Failed during access request preparation");
+    let response = GrantFlow::handle(IssuerRef::with(&mut setup.registrar, &mut setup.authorizer, &mut issuer)
+        .with_replay_reporter(&reporter), prepared).expect("Failed during access request handling");
+    DeviceSetup::assert_error(&response, "invalid_grant");
+    assert!(issuer.recover_refresh(&refreshed).is_none());
+    assert_eq!(reporter.0.get(), 1);
+}
+
+#[test]
+fn access_request_replayed_code_other_client() {
+    let mut setup = AccessTokenSetup::private_client();
+    setup.registrar.register_client(Client::public("OtherClient",
+        EXAMPLE_REDIRECT_URL.parse().unwrap(), EXAMPLE_SCOPE.parse().unwrap()));
+    let reporter = CountingReporter(::std::cell::Cell::new(0));
+    let mut first = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "authorization_code"),
+                         ("code", &setup.authtoken),
+                         ("redirect_url", EXAMPLE_REDIRECT_URL)]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+    let mut other = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "authorization_code"),
+                         ("client_id", "OtherClient"),
+                         ("code", &setup.authtoken),
+                         ("redirect_url", EXAMPLE_REDIRECT_URL)]
+            .iter().as_single_value_query()),
+        auth: None,
+    };
+
+    let prepared = GrantFlow::prepare(&mut first).expect("Failed during access request preparation");
+    let token = match GrantFlow::handle(IssuerRef::with(&mut setup.registrar, &mut setup.authorizer, &mut setup.issuer)
+        .with_replay_reporter(&reporter), prepared) {
+        Ok(CraftedResponse::Json(json)) => {
+            let parsed: HashMap<String, String> = serde_json::from_str(&json).unwrap();
+            parsed.get("access_token").expect("No access token issued").clone()
+        },
+        resp => panic!("Expected json response, got {:?}", resp),
+    };
+
+    // Another client presenting the code can not revoke the tokens of the legitimate client
+    let prepared = GrantFlow::prepare(&mut other).expect("Failed during access request preparation");
+    match GrantFlow::handle(IssuerRef::with(&mut setup.registrar, &mut setup.authorizer, &mut setup.issuer)
+        .with_replay_reporter(&reporter), prepared) {
+        Ok(CraftedResponse::ClientError(ref inner)) => match **inner {
+            CraftedResponse::Json(ref json) => {
+                let parsed: HashMap<String, String> = serde_json::from_str(json).unwrap();
+                assert_eq!(parsed.get("error").map(String::as_str), Some("invalid_grant"));
+                assert!(parsed.get("error_description").is_none());
+            },
+            ref resp => panic!("Expected json error response, got {:?}", resp),
+        },
+        resp => panic!("Expected json error response, got {:?}", resp),
+    }
+    assert!(setup.issuer.recover_token(&token).is_some());
+    assert_eq!(reporter.0.get(), 0);
+}
+
+#[test]
+fn access_request_expired_code() {
+    use primitives::authorizer::Authorizer;
+    use chrono::{Duration, Utc};
+    let clock = MockClock::new(Utc::now());
+    let mut registrar = ClientMap::new();
+    let mut authorizer = Storage::new(RandomGenerator::new(16)).with_clock(Arc::new(clock.clone()));
+    let mut issuer = TokenMap::new(RandomGenerator::new(16)).with_clock(Arc::new(clock.clone()));
+    registrar.register_client(Client::public(EXAMPLE_CLIENT_ID,
+        EXAMPLE_REDIRECT_URL.parse().unwrap(),
+        EXAMPLE_SCOPE.parse().unwrap()));
+
+    let mut authorize = || authorizer.authorize(GrantRequest {
+        client_id: EXAMPLE_CLIENT_ID,
+        owner_id: EXAMPLE_OWNER_ID,
+        redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
+        scope: &EXAMPLE_SCOPE.parse().unwrap(),
+    });
+    let fresh = authorize();
+    let stale = authorize();
+    let request = |code: &str| CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "authorization_code"),
+                         ("client_id", EXAMPLE_CLIENT_ID),
+                         ("code", code),
+                         ("redirect_url", EXAMPLE_REDIRECT_URL)]
+            .iter().as_single_value_query()),
+        auth: None,
+    };
+
+    // Codes are valid for ten minutes and the token lifetime is counted from the mock time
+    clock.advance(Duration::minutes(9));
+    let mut fresh_request = request(&fresh);
+    let prepared = GrantFlow::prepare(&mut fresh_request).expect("Failed during access request preparation");
+    match GrantFlow::handle(IssuerRef::with(&mut registrar, &mut authorizer, &mut issuer)
+        .with_clock(&clock), prepared) {
+        Ok(CraftedResponse::Json(json)) => {
+            let parsed: HashMap<String, String> = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed.get("expires_in").map(String::as_str), Some("3600"));
+        },
+        resp => panic!("Expected json response, got {:?}", resp),
+    }
+
+    clock.advance(Duration::minutes(2));
+    let mut stale_request = request(&stale);
+    let prepared = GrantFlow::prepare(&mut stale_request).expect("Failed during access request preparation");
+    match GrantFlow::handle(IssuerRef::with(&mut registrar, &mut authorizer, &mut issuer)
+        .with_clock(&clock), prepared) {
+        Ok(ref response) => AccessTokenSetup::assert_json_error_set(response),
+        resp => panic!("Expected json error response, got {:?}", resp),
+    }
+}
+
+struct RefreshTokenSetup {
+    registrar: ClientMap,
+    authorizer: Storage<TestGenerator>,
     issuer: TokenMap<RandomGenerator>,
-    authtoken: String,
-    wrong_scope_token: String,
-    small_scope_token: String,
-    resource_scope: [Scope; 1],
+    refresh_token: String,
+    basic_authorization: String,
 }
 
-impl ResourceSetup {
-    fn new() -> ResourceSetup {
-        use primitives::issuer::Issuer;
+impl RefreshTokenSetup {
+    fn private_client() -> Self {
+        RefreshTokenSetup::with_client(Client::confidential(EXAMPLE_CLIENT_ID,
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes()))
+    }
 
-        // Ensure that valid tokens are 16 bytes long, so we can craft an invalid one
-        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+    fn rotating_public_client() -> Self {
+        RefreshTokenSetup::with_client(Client::public(EXAMPLE_CLIENT_ID,
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap()).with_refresh_rotation())
+    }
 
-        let authtoken = issuer.issue(GrantRequest {
-            client_id: EXAMPLE_CLIENT_ID,
-            owner_id: EXAMPLE_OWNER_ID,
-            redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
-            scope: &"legit needed andmore".parse().unwrap(),
-        });
+    fn with_client(client: Client) -> Self {
+        let mut registrar = ClientMap::new();
+        let authorizer = Storage::new(TestGenerator("AuthToken".to_string()));
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+        registrar.register_client(client);
 
-        let wrong_scope_token = issuer.issue(GrantRequest {
+        let issued = issuer.issue(GrantRequest {
             client_id: EXAMPLE_CLIENT_ID,
             owner_id: EXAMPLE_OWNER_ID,
             redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
-            scope: &"wrong needed".parse().unwrap(),
+            scope: &EXAMPLE_SCOPE.parse().unwrap(),
         });
 
-        let small_scope_token = issuer.issue(GrantRequest {
-            client_id: EXAMPLE_CLIENT_ID,
-            owner_id: EXAMPLE_OWNER_ID,
-            redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
-            scope: &"legit".parse().unwrap(),
-        });
+        let basic_authorization = base64::encode(&format!("{}:{}",
+            EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
 
-        ResourceSetup {
+        RefreshTokenSetup {
+            registrar,
+            authorizer,
             issuer,
-            authtoken: authtoken.token,
-            wrong_scope_token: wrong_scope_token.token,
-            small_scope_token: small_scope_token.token,
-            resource_scope: ["needed legit".parse().unwrap()],
+            refresh_token: issued.refresh,
+            basic_authorization,
         }
     }
 
-    fn test_access_error(&mut self, mut req: CraftedRequest) {
-        let prepared = AccessFlow::prepare(&mut req).expect("Failed access preparation");
-        match AccessFlow::handle(GuardRef::with(&mut self.issuer, &self.resource_scope), prepared) {
-            Ok(resp) => panic!("Expected an error instead of {:?}", resp),
-            Err(_) => (),
+    fn test_success(&mut self, mut req: CraftedRequest) -> HashMap<String, String> {
+        let prepared = GrantFlow::prepare(&mut req).expect("Failed during refresh request preparation");
+        match GrantFlow::handle(IssuerRef::with(&mut self.registrar, &mut self.authorizer, &mut self.issuer), prepared) {
+            Ok(CraftedResponse::Json(json)) => {
+                let parsed: HashMap<String, String> = serde_json::from_str(&json).unwrap();
+                assert!(parsed.get("error").is_none(), "Unexpected error in {:?}", parsed);
+                parsed
+            },
+            resp => panic!("Expected json response, got {:?}", resp),
+        }
+    }
+
+    fn test_simple_error(&mut self, mut req: CraftedRequest) {
+        let prepared = GrantFlow::prepare(&mut req).expect("Failed during refresh request preparation");
+        match GrantFlow::handle(IssuerRef::with(&mut self.registrar, &mut self.authorizer, &mut self.issuer), prepared) {
+            Ok(ref response) =>
+                AccessTokenSetup::assert_json_error_set(response),
+            resp => panic!("Expected non-error reponse, got {:?}", resp),
         }
     }
 }
 
 #[test]
-fn resource_no_authorization() {
-    // Does not have any authorization
-    let no_authorization = CraftedRequest {
+fn refresh_success() {
+    let mut setup = RefreshTokenSetup::private_client();
+    let refresh = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("refresh_token", &setup.refresh_token)]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    let parsed = setup.test_success(refresh);
+    let token = parsed.get("access_token").unwrap();
+
+    let mut accessrequest = CraftedRequest {
         query: None,
         urlbody: None,
-        auth: None
+        auth: Some("Bearer ".to_string() + token),
     };
 
-    ResourceSetup::new().test_access_error(no_authorization);
+    let prepared = AccessFlow::prepare(&mut accessrequest).expect("Failure during access preparation");
+    let scope: [Scope; 1] = [EXAMPLE_SCOPE.parse().unwrap()];
+    AccessFlow::handle(GuardRef::with(&mut setup.issuer, &scope), prepared).expect("Failed to authorize");
 }
 
 #[test]
-fn resource_invalid_token() {
-    // Does not have any authorization
-    let invalid_token = CraftedRequest {
+fn refresh_rotation() {
+    let mut setup = RefreshTokenSetup::rotating_public_client();
+    let refresh = |token: &str| CraftedRequest {
         query: None,
-        urlbody: None,
-        auth: Some("Bearer ThisisnotavalidtokenTooLong".to_string())
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("client_id", EXAMPLE_CLIENT_ID),
+                         ("refresh_token", token)]
+            .iter().as_single_value_query()),
+        auth: None,
     };
 
-    ResourceSetup::new().test_access_error(invalid_token);
+    let original = setup.refresh_token.clone();
+    let parsed = setup.test_success(refresh(&original));
+    let rotated = parsed.get("refresh_token").unwrap().clone();
+    let token = parsed.get("access_token").unwrap().clone();
+    assert_ne!(rotated, original);
+    assert!(setup.issuer.recover_refresh(&original).is_none());
+    assert!(setup.issuer.recover_token(&token).is_some());
+
+    // Reusing the replaced refresh token revokes the whole family
+    setup.test_simple_error(refresh(&original));
+    assert!(setup.issuer.recover_refresh(&rotated).is_none());
+    assert!(setup.issuer.recover_token(&token).is_none());
+    setup.test_simple_error(refresh(&rotated));
 }
 
 #[test]
-fn resource_wrong_method() {
-    let mut setup = ResourceSetup::new();
-    // Not indicating the `Bearer` authorization method
-    let wrong_method = CraftedRequest {
+fn refresh_without_rotation() {
+    let mut setup = RefreshTokenSetup::private_client();
+    let refresh = CraftedRequest {
         query: None,
-        urlbody: None,
-        auth: Some("NotBearer ".to_string() + &setup.authtoken),
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("refresh_token", &setup.refresh_token)]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
     };
 
-    setup.test_access_error(wrong_method);
+    let parsed = setup.test_success(refresh);
+    assert!(setup.issuer.recover_refresh(&setup.refresh_token).is_some());
+    assert_eq!(parsed.get("refresh_token"), Some(&setup.refresh_token));
+
+    // Revoking the original refresh token revokes the refreshed token as well
+    assert!(setup.issuer.revoke(&setup.refresh_token).is_ok());
+    assert!(setup.issuer.recover_token(parsed.get("access_token").unwrap()).is_none());
+    assert!(setup.issuer.recover_refresh(parsed.get("refresh_token").unwrap()).is_none());
 }
 
 #[test]
-fn resource_scope_too_small() {
-    let mut setup = ResourceSetup::new();
-    // Scope of used token is too small for access
-    let scope_too_small = CraftedRequest {
+fn refresh_rotation_self_contained() {
+    let mut registrar = ClientMap::new();
+    let mut authorizer = Storage::new(TestGenerator("AuthToken".to_string()));
+    let mut issuer = TokenSigner::new_from_passphrase("Some secret passphrase");
+    registrar.register_client(Client::public(EXAMPLE_CLIENT_ID,
+        EXAMPLE_REDIRECT_URL.parse().unwrap(),
+        EXAMPLE_SCOPE.parse().unwrap()).with_refresh_rotation());
+    let issued = issuer.issue(GrantRequest {
+        client_id: EXAMPLE_CLIENT_ID,
+        owner_id: EXAMPLE_OWNER_ID,
+        redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
+        scope: &EXAMPLE_SCOPE.parse().unwrap(),
+    });
+
+    let mut refresh = CraftedRequest {
         query: None,
-        urlbody: None,
-        auth: Some("Bearer ".to_string() + &setup.small_scope_token),
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("client_id", EXAMPLE_CLIENT_ID),
+                         ("refresh_token", &issued.refresh)]
+            .iter().as_single_value_query()),
+        auth: None,
     };
 
-    setup.test_access_error(scope_too_small);
+    // Signed refresh tokens can not be rotated, the presented one is kept instead of failing
+    let prepared = GrantFlow::prepare(&mut refresh).expect("Failed during refresh request preparation");
+    match GrantFlow::handle(IssuerRef::with(&mut registrar, &mut authorizer, &mut issuer), prepared) {
+        Ok(CraftedResponse::Json(json)) => {
+            let parsed: HashMap<String, String> = serde_json::from_str(&json).unwrap();
+            assert!(parsed.get("error").is_none(), "Unexpected error in {:?}", parsed);
+            assert_eq!(parsed.get("refresh_token"), Some(&issued.refresh));
+            assert!(issuer.recover_token(parsed.get("access_token").unwrap()).is_some());
+        },
+        resp => panic!("Expected json response, got {:?}", resp),
+    }
 }
 
 #[test]
-fn resource_wrong_scope() {
-    let mut setup = ResourceSetup::new();
-    // Scope of used token does not match the access
-    let wrong_scope = CraftedRequest {
+fn refresh_narrowed_scope() {
+    let mut setup = RefreshTokenSetup::private_client();
+    // Requesting only a part of the original scope
+    let narrowed = CraftedRequest {
         query: None,
-        urlbody: None,
-        auth: Some("Bearer ".to_string() + &setup.wrong_scope_token),
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("refresh_token", &setup.refresh_token),
+                         ("scope", "example")]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
     };
 
-    setup.test_access_error(wrong_scope);
+    let parsed = setup.test_success(narrowed);
+    assert_eq!(parsed.get("scope").unwrap(), "example");
+}
+
+#[test]
+fn refresh_wider_scope() {
+    let mut setup = RefreshTokenSetup::private_client();
+    // Requesting a scope exceeding the original grant
+    let wider = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("refresh_token", &setup.refresh_token),
+                         ("scope", "example default admin")]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    setup.test_simple_error(wider);
+}
+
+#[test]
+fn refresh_unknown_token() {
+    let mut setup = RefreshTokenSetup::private_client();
+    // Trying to refresh with a token that was never issued
+    let unknown = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("refresh_token", "NotARefreshToken")]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    setup.test_simple_error(unknown);
+}
+
+#[test]
+fn refresh_other_client() {
+    let mut setup = RefreshTokenSetup::private_client();
+    setup.registrar.register_client(Client::public("OtherClient",
+        EXAMPLE_REDIRECT_URL.parse().unwrap(), EXAMPLE_SCOPE.parse().unwrap()));
+    // Another client trying to use the refresh token
+    let other_client = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("client_id", "OtherClient"),
+                         ("refresh_token", &setup.refresh_token)]
+            .iter().as_single_value_query()),
+        auth: None,
+    };
+
+    setup.test_simple_error(other_client);
+}
+
+#[test]
+fn client_credentials_success() {
+    let mut setup = RefreshTokenSetup::private_client();
+    let credentials = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "client_credentials")]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    let parsed = setup.test_success(credentials);
+    assert!(parsed.get("refresh_token").is_none(), "Unexpected refresh token in {:?}", parsed);
+    assert_eq!(parsed.get("scope").unwrap().parse::<Scope>().unwrap(),
+        EXAMPLE_SCOPE.parse::<Scope>().unwrap());
+
+    use primitives::issuer::Issuer;
+    let grant = setup.issuer.recover_token(parsed.get("access_token").unwrap())
+        .expect("Issued token should be recoverable");
+    assert_eq!(grant.owner_id, EXAMPLE_CLIENT_ID);
+    assert_eq!(grant.client_id, EXAMPLE_CLIENT_ID);
+}
+
+#[test]
+fn client_credentials_narrowed_scope() {
+    let mut setup = RefreshTokenSetup::private_client();
+    let credentials = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "client_credentials"),
+                         ("scope", "example")]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    let parsed = setup.test_success(credentials);
+    assert_eq!(parsed.get("scope").map(String::as_str), Some("example"));
+}
+
+#[test]
+fn client_credentials_invalid_scope() {
+    let mut setup = RefreshTokenSetup::private_client();
+    let credentials = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "client_credentials"),
+                         ("scope", "admin")]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    setup.test_simple_error(credentials);
+}
+
+#[test]
+fn client_credentials_public_client() {
+    let mut setup = RefreshTokenSetup::private_client();
+    setup.registrar.register_client(Client::public("OtherClient",
+        EXAMPLE_REDIRECT_URL.parse().unwrap(), EXAMPLE_SCOPE.parse().unwrap()));
+    let credentials = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "client_credentials"),
+                         ("client_id", "OtherClient")]
+            .iter().as_single_value_query()),
+        auth: None,
+    };
+
+    setup.test_simple_error(credentials);
+}
+
+#[test]
+fn unregistered_grant_types() {
+    let mut registration = ClientRegistration::new(0);
+    registration.grant_types = vec!["authorization_code".to_string()];
+    registration.response_types = vec!["code".to_string()];
+    let mut setup = RefreshTokenSetup::with_client(Client::confidential(EXAMPLE_CLIENT_ID,
+        EXAMPLE_REDIRECT_URL.parse().unwrap(),
+        EXAMPLE_SCOPE.parse().unwrap(),
+        EXAMPLE_PASSPHRASE.as_bytes()).with_registration(registration));
+
+    let credentials = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "client_credentials")]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+    let refresh = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "refresh_token"),
+                         ("refresh_token", &setup.refresh_token)]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    for mut request in vec![credentials, refresh] {
+        let prepared = GrantFlow::prepare(&mut request).expect("Failed during access request preparation");
+        let response = GrantFlow::handle(IssuerRef::with(&mut setup.registrar, &mut setup.authorizer,
+            &mut setup.issuer), prepared).expect("Failed during access request handling");
+        DeviceSetup::assert_error(&response, "unauthorized_client");
+    }
+
+    use primitives::issuer::Issuer;
+    assert!(setup.issuer.recover_refresh(&setup.refresh_token).is_some(),
+        "Refresh token should not be consumed");
+}
+
+#[test]
+fn client_credentials_rehashes_client() {
+    use primitives::registrar::Registrar;
+    use ring::digest;
+    let mut legacy = digest::Context::new(&digest::SHA256);
+    legacy.update(EXAMPLE_CLIENT_ID.as_bytes());
+    legacy.update(EXAMPLE_PASSPHRASE.as_bytes());
+    let legacy = legacy.finish().as_ref().to_vec();
+    let mut setup = RefreshTokenSetup::with_client(Client::confidential_from_passdata(EXAMPLE_CLIENT_ID,
+        EXAMPLE_REDIRECT_URL.parse().unwrap(),
+        EXAMPLE_SCOPE.parse().unwrap(),
+        &legacy));
+    let credentials = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "client_credentials")]
+            .iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    setup.test_success(credentials);
+    let client = setup.registrar.client(EXAMPLE_CLIENT_ID).unwrap();
+    assert_eq!(client.password_policy().unwrap(), "argon2id");
+    assert!(client.check_authentication(Some(EXAMPLE_PASSPHRASE.as_bytes())).is_ok());
+}
+
+const EXAMPLE_OWNER_PASSWORD: &str = "correct horse battery staple";
+
+struct TestOwners;
+
+impl OwnerVerifier for TestOwners {
+    fn verify_owner(&self, username: &str, password: &str) -> Option<String> {
+        if username == EXAMPLE_OWNER_ID && password == EXAMPLE_OWNER_PASSWORD {
+            Some(EXAMPLE_OWNER_ID.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+struct PasswordSetup {
+    registrar: ClientMap,
+    authorizer: Storage<TestGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+    basic_authorization: String,
+}
+
+impl PasswordSetup {
+    fn new() -> PasswordSetup {
+        let mut registrar = ClientMap::new();
+        let client = Client::confidential(EXAMPLE_CLIENT_ID,
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes()).with_password_grant();
+        registrar.register_client(client);
+        registrar.register_client(Client::public("OtherClient",
+            EXAMPLE_REDIRECT_URL.parse().unwrap(), EXAMPLE_SCOPE.parse().unwrap()));
+
+        PasswordSetup {
+            registrar,
+            authorizer: Storage::new(TestGenerator("AuthToken".to_string())),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            basic_authorization: base64::encode(&format!("{}:{}",
+                EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE)),
+        }
+    }
+
+    fn request(&self, password: &str) -> CraftedRequest {
+        CraftedRequest {
+            query: None,
+            urlbody: Some(vec![("grant_type", "password"),
+                             ("username", EXAMPLE_OWNER_ID),
+                             ("password", password)]
+                .iter().as_single_value_query()),
+            auth: Some("Basic ".to_string() + &self.basic_authorization),
+        }
+    }
+
+    fn token(&mut self, mut request: CraftedRequest, verifier: Option<&OwnerVerifier>) -> CraftedResponse {
+        let prepared = GrantFlow::prepare(&mut request).expect("Failed during password request preparation");
+        let issuer = IssuerRef::with(&mut self.registrar, &mut self.authorizer, &mut self.issuer);
+        let issuer = match verifier {
+            Some(verifier) => issuer.with_owner_verifier(verifier),
+            None => issuer,
+        };
+        GrantFlow::handle(issuer, prepared).expect("Expected a response")
+    }
+}
+
+#[test]
+fn password_success() {
+    let mut setup = PasswordSetup::new();
+    let request = setup.request(EXAMPLE_OWNER_PASSWORD);
+    let parsed: HashMap<String, String> = match setup.token(request, Some(&TestOwners)) {
+        CraftedResponse::Json(json) => serde_json::from_str(&json).unwrap(),
+        resp => panic!("Expected json response, got {:?}", resp),
+    };
+    assert!(parsed.get("error").is_none(), "Unexpected error in {:?}", parsed);
+    assert!(parsed.get("refresh_token").is_some());
+
+    use primitives::issuer::Issuer;
+    let grant = setup.issuer.recover_token(parsed.get("access_token").unwrap())
+        .expect("Issued token should be recoverable");
+    assert_eq!(grant.owner_id, EXAMPLE_OWNER_ID);
+    assert_eq!(grant.client_id, EXAMPLE_CLIENT_ID);
+}
+
+#[test]
+fn password_wrong_credentials() {
+    let mut setup = PasswordSetup::new();
+    let request = setup.request("guessed");
+    let response = setup.token(request, Some(&TestOwners));
+    AccessTokenSetup::assert_json_error_set(&response);
+}
+
+#[test]
+fn password_untrusted_client() {
+    let mut setup = PasswordSetup::new();
+    let request = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("grant_type", "password"),
+                         ("client_id", "OtherClient"),
+                         ("username", EXAMPLE_OWNER_ID),
+                         ("password", EXAMPLE_OWNER_PASSWORD)]
+            .iter().as_single_value_query()),
+        auth: None,
+    };
+    let response = setup.token(request, Some(&TestOwners));
+    AccessTokenSetup::assert_json_error_set(&response);
+}
+
+#[test]
+fn password_without_verifier() {
+    let mut setup = PasswordSetup::new();
+    let request = setup.request(EXAMPLE_OWNER_PASSWORD);
+    let response = setup.token(request, None);
+    AccessTokenSetup::assert_json_error_set(&response);
+}
+
+struct ImplicitSetup {
+    registrar: ClientMap,
+    authorizer: Storage<TestGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+}
+
+impl ImplicitSetup {
+    fn new() -> ImplicitSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(EXAMPLE_CLIENT_ID,
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap()).with_implicit_grant());
+        registrar.register_client(Client::public("OtherClient",
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap()));
+
+        ImplicitSetup {
+            registrar,
+            authorizer: Storage::new(TestGenerator("AuthToken".to_string())),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+        }
+    }
+
+    fn request(client_id: &str) -> CraftedRequest {
+        CraftedRequest {
+            query: Some(vec![("response_type", "token"),
+                             ("client_id", client_id),
+                             ("redirect_url", EXAMPLE_REDIRECT_URL),
+                             ("state", "ExampleState")]
+                .iter().as_single_value_query()),
+            urlbody: None,
+            auth: None,
+        }
+    }
+
+    fn authorize(&mut self, mut request: CraftedRequest, pagehandler: &OwnerAuthorizer<Request=CraftedRequest>)
+    -> CraftedResponse {
+        let prepared = AuthorizationFlow::prepare(&mut request).expect("Failure during authorization preparation");
+        let code = CodeRef::with_issuer(&self.registrar, &mut self.authorizer, &mut self.issuer);
+        AuthorizationFlow::handle(code, prepared, pagehandler).expect("Failure during authorization handling")
+    }
+
+    fn fragment(url: &Url) -> HashMap<String, String> {
+        assert!(url.query().is_none(), "Unexpected query in {}", url);
+        let fragment = url.fragment().expect("Expected parameters in the fragment");
+        url::form_urlencoded::parse(fragment.as_bytes()).into_owned().collect()
+    }
+
+    fn assert_fragment_error(response: &CraftedResponse, error: &str) {
+        match *response {
+            CraftedResponse::RedirectFromError(ref url) => {
+                let fragment = ImplicitSetup::fragment(url);
+                assert_eq!(fragment.get("error").map(String::as_str), Some(error));
+                assert_eq!(fragment.get("state").map(String::as_str), Some("ExampleState"));
+            },
+            ref resp => panic!("Expected redirect with error set: {:?}", resp),
+        }
+    }
+}
+
+#[test]
+fn implicit_success() {
+    let mut setup = ImplicitSetup::new();
+    let request = ImplicitSetup::request(EXAMPLE_CLIENT_ID);
+    let fragment = match setup.authorize(request, &Allow(EXAMPLE_OWNER_ID.to_string())) {
+        CraftedResponse::Redirect(ref url) => ImplicitSetup::fragment(url),
+        resp => panic!("Expected redirect, got {:?}", resp),
+    };
+
+    assert_eq!(fragment.get("token_type").map(String::as_str), Some("bearer"));
+    assert_eq!(fragment.get("state").map(String::as_str), Some("ExampleState"));
+    assert!(fragment.get("expires_in").unwrap().parse::<i64>().unwrap() > 0);
+    assert!(fragment.get("refresh_token").is_none());
+    assert!(fragment.get("code").is_none());
+
+    let mut accessrequest = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + fragment.get("access_token").unwrap()),
+    };
+
+    let prepared = AccessFlow::prepare(&mut accessrequest).expect("Failure during access preparation");
+    let scope: [Scope; 1] = [fragment.get("scope").unwrap().parse().unwrap()];
+    AccessFlow::handle(GuardRef::with(&mut setup.issuer, &scope), prepared).expect("Failed to authorize");
+}
+
+#[test]
+fn implicit_denied() {
+    let mut setup = ImplicitSetup::new();
+    let request = ImplicitSetup::request(EXAMPLE_CLIENT_ID);
+    let response = setup.authorize(request, &Deny);
+    ImplicitSetup::assert_fragment_error(&response, "access_denied");
+}
+
+#[test]
+fn implicit_not_enabled_for_client() {
+    let mut setup = ImplicitSetup::new();
+    let request = ImplicitSetup::request("OtherClient");
+    let response = setup.authorize(request, &Allow(EXAMPLE_OWNER_ID.to_string()));
+    ImplicitSetup::assert_fragment_error(&response, "unauthorized_client");
+}
+
+#[test]
+fn implicit_without_issuer() {
+    let mut setup = ImplicitSetup::new();
+    let mut request = ImplicitSetup::request(EXAMPLE_CLIENT_ID);
+    let prepared = AuthorizationFlow::prepare(&mut request).expect("Failure during authorization preparation");
+    let response = AuthorizationFlow::handle(CodeRef::with(&setup.registrar, &mut setup.authorizer),
+        prepared, &Allow(EXAMPLE_OWNER_ID.to_string())).expect("Failure during authorization handling");
+    ImplicitSetup::assert_fragment_error(&response, "unsupported_response_type");
+}
+
+const EXAMPLE_VERIFICATION_URI: &str = "https://server.example/device";
+
+struct DeviceSetup {
+    registrar: ClientMap,
+    authorizer: Storage<TestGenerator>,
+    issuer: TokenMap<RandomGenerator>,
+    devices: DeviceStorage,
+}
+
+impl DeviceSetup {
+    fn new() -> DeviceSetup {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public(EXAMPLE_CLIENT_ID,
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap()));
+        registrar.register_client(Client::public("OtherClient",
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap()));
+
+        DeviceSetup {
+            registrar,
+            authorizer: Storage::new(TestGenerator("AuthToken".to_string())),
+            issuer: TokenMap::new(RandomGenerator::new(16)),
+            devices: DeviceStorage::new(),
+        }
+    }
+
+    /// Start a device authorization, returning the device code and user code.
+    fn start(&mut self) -> (String, String) {
+        let mut request = CraftedRequest {
+            query: None,
+            urlbody: Some(vec![("client_id", EXAMPLE_CLIENT_ID)]
+                .iter().as_single_value_query()),
+            auth: None,
+        };
+
+        let prepared = DeviceFlow::prepare(&mut request).expect("Failure during device preparation");
+        let verification_uri = EXAMPLE_VERIFICATION_URI.parse().unwrap();
+        let response = DeviceFlow::handle(DeviceRef::with(&self.registrar, &mut self.devices),
+            prepared, &verification_uri).expect("Failure during device handling");
+        let parsed: serde_json::Value = match response {
+            CraftedResponse::Json(json) => serde_json::from_str(&json).unwrap(),
+            resp => panic!("Expected json response, got {:?}", resp),
+        };
+
+        assert_eq!(parsed["verification_uri"].as_str(), Some(EXAMPLE_VERIFICATION_URI));
+        assert!(parsed["expires_in"].as_i64().unwrap() > 0);
+        assert!(parsed["interval"].as_i64().unwrap() > 0);
+        (parsed["device_code"].as_str().unwrap().to_string(),
+            parsed["user_code"].as_str().unwrap().to_string())
+    }
+
+    fn verify(&mut self, user_code: &str, pagehandler: &OwnerAuthorizer<Request=CraftedRequest>)
+    -> Result<CraftedResponse, OAuthError> {
+        let mut request = CraftedRequest {
+            query: Some(vec![("user_code", user_code)].iter().as_single_value_query()),
+            urlbody: None,
+            auth: None,
+        };
+
+        let prepared = VerificationFlow::prepare(&mut request).expect("Failure during verification preparation");
+        VerificationFlow::handle(VerificationRef::with(&mut self.devices), prepared, pagehandler)
+    }
+
+    fn poll(&mut self, device_code: &str) -> CraftedResponse {
+        self.poll_as(EXAMPLE_CLIENT_ID, device_code)
+    }
+
+    fn poll_as(&mut self, client_id: &str, device_code: &str) -> CraftedResponse {
+        let mut request = CraftedRequest {
+            query: None,
+            urlbody: Some(vec![("grant_type", DEVICE_CODE_GRANT_TYPE),
+                             ("client_id", client_id),
+                             ("device_code", device_code)]
+                .iter().as_single_value_query()),
+            auth: None,
+        };
+
+        let prepared = GrantFlow::prepare(&mut request).expect("Failure during polling preparation");
+        let issuer = IssuerRef::with(&mut self.registrar, &mut self.authorizer, &mut self.issuer)
+            .with_devices(&mut self.devices);
+        GrantFlow::handle(issuer, prepared).expect("Failure during polling")
+    }
+
+    fn assert_error(response: &CraftedResponse, error: &str) {
+        match *response {
+            CraftedResponse::ClientError(ref inner) => match **inner {
+                CraftedResponse::Json(ref json) => {
+                    let content: HashMap<String, String> = serde_json::from_str(json).unwrap();
+                    assert_eq!(content.get("error").map(String::as_str), Some(error));
+                },
+                ref resp => panic!("Expected json encoded body, got {:?}", resp),
+            },
+            ref resp => panic!("Expected client error, got {:?}", resp),
+        }
+    }
+}
+
+#[test]
+fn device_approved() {
+    let mut setup = DeviceSetup::new();
+    let (device_code, user_code) = setup.start();
+
+    let pending = setup.poll(&device_code);
+    DeviceSetup::assert_error(&pending, "authorization_pending");
+
+    setup.verify(&user_code, &Allow(EXAMPLE_OWNER_ID.to_string()))
+        .expect("Verification should succeed");
+
+    let parsed: HashMap<String, String> = match setup.poll(&device_code) {
+        CraftedResponse::Json(json) => serde_json::from_str(&json).unwrap(),
+        resp => panic!("Expected json response, got {:?}", resp),
+    };
+    assert!(parsed.get("error").is_none(), "Unexpected error in {:?}", parsed);
+
+    use primitives::issuer::Issuer;
+    let grant = setup.issuer.recover_token(parsed.get("access_token").unwrap())
+        .expect("Issued token should be recoverable");
+    assert_eq!(grant.owner_id, EXAMPLE_OWNER_ID);
+
+    // The device code is consumed by the successful poll
+    let used = setup.poll(&device_code);
+    DeviceSetup::assert_error(&used, "invalid_grant");
+}
+
+#[test]
+fn device_other_client() {
+    let mut setup = DeviceSetup::new();
+    let (device_code, user_code) = setup.start();
+    setup.verify(&user_code, &Allow(EXAMPLE_OWNER_ID.to_string()))
+        .expect("Verification should succeed");
+
+    // Another client can neither redeem nor consume the approved authorization
+    let stolen = setup.poll_as("OtherClient", &device_code);
+    DeviceSetup::assert_error(&stolen, "invalid_grant");
+
+    match setup.poll(&device_code) {
+        CraftedResponse::Json(_) => (),
+        resp => panic!("Expected json response, got {:?}", resp),
+    }
+}
+
+#[test]
+fn device_denied() {
+    let mut setup = DeviceSetup::new();
+    let (device_code, user_code) = setup.start();
+
+    setup.verify(&user_code, &Deny).expect("Verification should respond");
+    let denied = setup.poll(&device_code);
+    DeviceSetup::assert_error(&denied, "access_denied");
+}
+
+#[test]
+fn device_slow_down() {
+    let mut setup = DeviceSetup::new();
+    let (device_code, _) = setup.start();
+
+    let pending = setup.poll(&device_code);
+    DeviceSetup::assert_error(&pending, "authorization_pending");
+    let too_fast = setup.poll(&device_code);
+    DeviceSetup::assert_error(&too_fast, "slow_down");
+}
+
+#[test]
+fn device_unknown_user_code() {
+    let mut setup = DeviceSetup::new();
+    setup.start();
+
+    assert!(setup.verify("BCDF-GHJK-LMNP", &Allow(EXAMPLE_OWNER_ID.to_string())).is_err());
+}
+
+const EXAMPLE_CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+const EXAMPLE_CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+struct PkceSetup {
+    registrar: ClientMap,
+    authorizer: Storage<TestGenerator>,
+    issuer: TokenMap<TestGenerator>,
+}
+
+impl PkceSetup {
+    fn new() -> PkceSetup {
+        let mut registrar = ClientMap::new();
+        registrar.require_pkce_for_public(true);
+        registrar.register_client(Client::public(EXAMPLE_CLIENT_ID,
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap()));
+
+        PkceSetup {
+            registrar,
+            authorizer: Storage::new(TestGenerator("AuthToken".to_string())),
+            issuer: TokenMap::new(TestGenerator("AccessToken".to_string())),
+        }
+    }
+
+    fn authorize(&mut self, query: Vec<(&str, &str)>) -> CraftedResponse {
+        let mut request = CraftedRequest {
+            query: Some(query.iter().as_single_value_query()),
+            urlbody: None,
+            auth: None,
+        };
+
+        let prepared = AuthorizationFlow::prepare(&mut request).expect("Failure during authorization preparation");
+        let pagehandler = Allow(EXAMPLE_OWNER_ID.to_string());
+        AuthorizationFlow::handle(CodeRef::with(&self.registrar, &mut self.authorizer), prepared, &pagehandler)
+            .expect("Failure during authorization handling")
+    }
+
+    fn token(&mut self, verifier: Option<&str>) -> CraftedResponse {
+        let mut urlbody = vec![("grant_type", "authorization_code"),
+                               ("client_id", EXAMPLE_CLIENT_ID),
+                               ("code", "AuthToken"),
+                               ("redirect_url", EXAMPLE_REDIRECT_URL)];
+        urlbody.extend(verifier.map(|verifier| ("code_verifier", verifier)));
+        let mut request = CraftedRequest {
+            query: None,
+            urlbody: Some(urlbody.iter().as_single_value_query()),
+            auth: None,
+        };
+
+        let prepared = GrantFlow::prepare(&mut request).expect("Failure during access token preparation");
+        GrantFlow::handle(IssuerRef::with(&mut self.registrar, &mut self.authorizer, &mut self.issuer), prepared)
+            .expect("Failure during access token handling")
+    }
+
+    fn authorize_with_challenge(&mut self) {
+        match self.authorize(vec![("response_type", "code"),
+                                  ("client_id", EXAMPLE_CLIENT_ID),
+                                  ("redirect_url", EXAMPLE_REDIRECT_URL),
+                                  ("code_challenge", EXAMPLE_CODE_CHALLENGE),
+                                  ("code_challenge_method", "S256")]) {
+            CraftedResponse::Redirect(_) => (),
+            resp => panic!("Expected redirect with code, got {:?}", resp),
+        }
+    }
+}
+
+#[test]
+fn pkce_correct_verifier() {
+    let mut setup = PkceSetup::new();
+    setup.authorize_with_challenge();
+    match setup.token(Some(EXAMPLE_CODE_VERIFIER)) {
+        CraftedResponse::Json(json) => {
+            let parsed: HashMap<String, String> = serde_json::from_str(&json).unwrap();
+            assert!(parsed.get("error").is_none());
+            assert!(parsed.get("access_token").is_some());
+        },
+        resp => panic!("Expected token response, got {:?}", resp),
+    }
+}
+
+#[test]
+fn pkce_wrong_verifier() {
+    let mut setup = PkceSetup::new();
+    setup.authorize_with_challenge();
+    // A verifier not matching the challenge, as an attacker intercepting the code would send
+    let response = setup.token(Some("AnotherVerifierOfTheRequiredMinimumLength0123"));
+    AccessTokenSetup::assert_json_error_set(&response);
+}
+
+#[test]
+fn pkce_missing_verifier() {
+    let mut setup = PkceSetup::new();
+    setup.authorize_with_challenge();
+    // Not sending any verifier even though a challenge was committed to
+    let response = setup.token(None);
+    AccessTokenSetup::assert_json_error_set(&response);
+}
+
+#[test]
+fn pkce_required_challenge() {
+    let mut setup = PkceSetup::new();
+    // Public client sending no challenge although the registrar requires it
+    match setup.authorize(vec![("response_type", "code"),
+                               ("client_id", EXAMPLE_CLIENT_ID),
+                               ("redirect_url", EXAMPLE_REDIRECT_URL)]) {
+        CraftedResponse::RedirectFromError(ref url)
+        if url.query_pairs().collect::<HashMap<_, _>>().get("error").is_some()
+            => (),
+        resp => panic!("Expected redirect with error set: {:?}", resp),
+    }
+}
+
+/// An authorizer which does not support code challenges.
+struct UnchallengedAuthorizer(Storage<TestGenerator>);
+
+impl Authorizer for UnchallengedAuthorizer {
+    fn authorize(&mut self, req: GrantRequest) -> String {
+        self.0.authorize(req)
+    }
+
+    fn extract<'a>(&mut self, code: &'a str) -> Option<GrantRef<'a>> {
+        self.0.extract(code)
+    }
+}
+
+#[test]
+fn pkce_unsupported_authorizer() {
+    let setup = PkceSetup::new();
+    let mut authorizer = UnchallengedAuthorizer(Storage::new(TestGenerator("AuthToken".to_string())));
+    let query = vec![("response_type", "code"),
+                     ("client_id", EXAMPLE_CLIENT_ID),
+                     ("redirect_url", EXAMPLE_REDIRECT_URL),
+                     ("code_challenge", EXAMPLE_CODE_CHALLENGE),
+                     ("code_challenge_method", "S256")];
+    let mut request = CraftedRequest {
+        query: Some(query.iter().as_single_value_query()),
+        urlbody: None,
+        auth: None,
+    };
+
+    // The challenge must not be dropped silently, the code would be redeemable without a verifier
+    let prepared = AuthorizationFlow::prepare(&mut request).expect("Failure during authorization preparation");
+    let pagehandler = Allow(EXAMPLE_OWNER_ID.to_string());
+    match AuthorizationFlow::handle(CodeRef::with(&setup.registrar, &mut authorizer), prepared, &pagehandler) {
+        Ok(CraftedResponse::RedirectFromError(ref url))
+        if url.query_pairs().collect::<HashMap<_, _>>().get("error").map(|e| e.as_ref()) == Some("invalid_request")
+            => (),
+        resp => panic!("Expected redirect with error set: {:?}", resp),
+    }
+    assert!(authorizer.0.extract("AuthToken").is_none());
+}
+
+struct RevocationSetup {
+    registrar: ClientMap,
+    issuer: TokenMap<RandomGenerator>,
+    issued: IssuedToken,
+    basic_authorization: String,
+}
+
+impl RevocationSetup {
+    fn new() -> RevocationSetup {
+        use primitives::issuer::Issuer;
+        let mut registrar = ClientMap::new();
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+
+        registrar.register_client(Client::confidential(EXAMPLE_CLIENT_ID,
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes()));
+        registrar.register_client(Client::public("OtherClient",
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap()));
+        registrar.register_client(Client::confidential("ResourceServer",
+            EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            EXAMPLE_SCOPE.parse().unwrap(),
+            EXAMPLE_PASSPHRASE.as_bytes()).with_introspection());
+
+        let issued = issuer.issue(GrantRequest {
+            client_id: EXAMPLE_CLIENT_ID,
+            owner_id: EXAMPLE_OWNER_ID,
+            redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            scope: &EXAMPLE_SCOPE.parse().unwrap(),
+        });
+
+        let basic_authorization = base64::encode(&format!("{}:{}",
+            EXAMPLE_CLIENT_ID, EXAMPLE_PASSPHRASE));
+
+        RevocationSetup { registrar, issuer, issued, basic_authorization }
+    }
+
+    fn revoke(&mut self, urlbody: Vec<(&str, &str)>, auth: Option<String>) -> CraftedResponse {
+        let mut request = CraftedRequest {
+            query: None,
+            urlbody: Some(urlbody.iter().as_single_value_query()),
+            auth,
+        };
+
+        let prepared = RevocationFlow::prepare(&mut request).expect("Failure during revocation preparation");
+        RevocationFlow::handle(RevocationRef::with(&self.registrar, &mut self.issuer), prepared)
+            .expect("Failure during revocation handling")
+    }
+
+    fn assert_token_valid(&mut self, valid: bool) {
+        use primitives::issuer::Issuer;
+        assert_eq!(self.issuer.recover_token(&self.issued.token).is_some(), valid);
+        assert_eq!(self.issuer.recover_refresh(&self.issued.refresh).is_some(), valid);
+    }
+}
+
+#[test]
+fn revoke_access_token() {
+    let mut setup = RevocationSetup::new();
+    let token = setup.issued.token.clone();
+    let auth = Some("Basic ".to_string() + &setup.basic_authorization);
+    match setup.revoke(vec![("token", &token)], auth) {
+        CraftedResponse::Text(ref text) if text.is_empty() => (),
+        resp => panic!("Expected empty response, got {:?}", resp),
+    }
+
+    setup.assert_token_valid(false);
+}
+
+#[test]
+fn revoke_refresh_token() {
+    let mut setup = RevocationSetup::new();
+    let refresh = setup.issued.refresh.clone();
+    let auth = Some("Basic ".to_string() + &setup.basic_authorization);
+    match setup.revoke(vec![("token", &refresh), ("token_type_hint", "refresh_token")], auth) {
+        CraftedResponse::Text(_) => (),
+        resp => panic!("Expected empty response, got {:?}", resp),
+    }
+
+    setup.assert_token_valid(false);
+}
+
+#[test]
+fn revoke_unknown_token() {
+    let mut setup = RevocationSetup::new();
+    // Unknown tokens are silently accepted
+    let auth = Some("Basic ".to_string() + &setup.basic_authorization);
+    match setup.revoke(vec![("token", "NotAToken")], auth) {
+        CraftedResponse::Text(_) => (),
+        resp => panic!("Expected empty response, got {:?}", resp),
+    }
+
+    setup.assert_token_valid(true);
+}
+
+#[test]
+fn revoke_other_client() {
+    let mut setup = RevocationSetup::new();
+    // Another client trying to revoke the token
+    let token = setup.issued.token.clone();
+    let response = setup.revoke(vec![("token", &token), ("client_id", "OtherClient")], None);
+    AccessTokenSetup::assert_json_error_set(&response);
+    setup.assert_token_valid(true);
+}
+
+#[test]
+fn revoke_wrong_password() {
+    let mut setup = RevocationSetup::new();
+    let token = setup.issued.token.clone();
+    let auth = Some("Basic ".to_string() + &base64::encode(&format!("{}:{}",
+        EXAMPLE_CLIENT_ID, "NotTheRightPassphrase")));
+    let response = setup.revoke(vec![("token", &token)], auth);
+    AccessTokenSetup::assert_json_error_set(&response);
+    setup.assert_token_valid(true);
+}
+
+#[test]
+fn revoke_unsupported() {
+    use primitives::issuer::Issuer;
+    let setup = RevocationSetup::new();
+    // Signed tokens can not be revoked
+    let mut issuer = TokenSigner::new_from_passphrase(EXAMPLE_PASSPHRASE);
+    let issued = issuer.issue(GrantRequest {
+        client_id: EXAMPLE_CLIENT_ID,
+        owner_id: EXAMPLE_OWNER_ID,
+        redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
+        scope: &EXAMPLE_SCOPE.parse().unwrap(),
+    });
+
+    let mut request = CraftedRequest {
+        query: None,
+        urlbody: Some(vec![("token", issued.token.as_str())].iter().as_single_value_query()),
+        auth: Some("Basic ".to_string() + &setup.basic_authorization),
+    };
+
+    let prepared = RevocationFlow::prepare(&mut request).expect("Failure during revocation preparation");
+    let response = RevocationFlow::handle(RevocationRef::with(&setup.registrar, &mut issuer), prepared)
+        .expect("Failure during revocation handling");
+    AccessTokenSetup::assert_json_error_set(&response);
+}
+
+impl RevocationSetup {
+    fn introspect(&mut self, urlbody: Vec<(&str, &str)>, auth: Option<String>) -> CraftedResponse {
+        let mut request = CraftedRequest {
+            query: None,
+            urlbody: Some(urlbody.iter().as_single_value_query()),
+            auth,
+        };
+
+        let prepared = IntrospectionFlow::prepare(&mut request).expect("Failure during introspection preparation");
+        IntrospectionFlow::handle(IntrospectionRef::with(&self.registrar, &self.issuer), prepared)
+            .expect("Failure during introspection handling")
+    }
+
+    fn introspect_json(&mut self, urlbody: Vec<(&str, &str)>) -> serde_json::Value {
+        let auth = Some("Basic ".to_string() + &base64::encode(&format!("{}:{}",
+            "ResourceServer", EXAMPLE_PASSPHRASE)));
+        match self.introspect(urlbody, auth) {
+            CraftedResponse::Json(json) => serde_json::from_str(&json).unwrap(),
+            resp => panic!("Expected json response, got {:?}", resp),
+        }
+    }
+}
+
+#[test]
+fn introspect_access_token() {
+    let mut setup = RevocationSetup::new();
+    let token = setup.issued.token.clone();
+    let introspection = setup.introspect_json(vec![("token", &token)]);
+    assert_eq!(introspection["active"], true);
+    assert_eq!(introspection["client_id"], EXAMPLE_CLIENT_ID);
+    assert_eq!(introspection["sub"], EXAMPLE_OWNER_ID);
+    assert_eq!(introspection["exp"], setup.issued.until.timestamp());
+    let scope = introspection["scope"].as_str().unwrap().parse::<Scope>().unwrap();
+    assert_eq!(scope, EXAMPLE_SCOPE.parse().unwrap());
+}
+
+#[test]
+fn introspect_refresh_token() {
+    let mut setup = RevocationSetup::new();
+    let refresh = setup.issued.refresh.clone();
+    let introspection = setup.introspect_json(vec![("token", &refresh), ("token_type_hint", "refresh_token")]);
+    assert_eq!(introspection["active"], true);
+    assert_eq!(introspection["client_id"], EXAMPLE_CLIENT_ID);
+}
+
+#[test]
+fn introspect_revoked_token() {
+    use primitives::issuer::Issuer;
+    let mut setup = RevocationSetup::new();
+    let token = setup.issued.token.clone();
+    setup.issuer.revoke(&token).unwrap();
+    let introspection = setup.introspect_json(vec![("token", &token)]);
+    assert_eq!(introspection["active"], false);
+    assert!(introspection.get("client_id").is_none());
+}
+
+#[test]
+fn introspect_unauthenticated() {
+    let mut setup = RevocationSetup::new();
+    let token = setup.issued.token.clone();
+    // Public clients can not authenticate and are not allowed to introspect
+    let response = setup.introspect(vec![("token", &token), ("client_id", "OtherClient")], None);
+    AccessTokenSetup::assert_json_error_set(&response);
+}
+
+#[test]
+fn introspect_ordinary_client() {
+    let mut setup = RevocationSetup::new();
+    let token = setup.issued.token.clone();
+    // Confidential clients which are not resource servers may not learn about tokens
+    let auth = Some("Basic ".to_string() + &setup.basic_authorization);
+    let response = setup.introspect(vec![("token", &token)], auth);
+    AccessTokenSetup::assert_json_error_set(&response);
+}
+
+struct ResourceSetup {
+    issuer: TokenMap<RandomGenerator>,
+    authtoken: String,
+    wrong_scope_token: String,
+    small_scope_token: String,
+    resource_scope: [Scope; 1],
+}
+
+impl ResourceSetup {
+    fn new() -> ResourceSetup {
+        use primitives::issuer::Issuer;
+
+        // Ensure that valid tokens are 16 bytes long, so we can craft an invalid one
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+
+        let authtoken = issuer.issue(GrantRequest {
+            client_id: EXAMPLE_CLIENT_ID,
+            owner_id: EXAMPLE_OWNER_ID,
+            redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            scope: &"legit needed andmore".parse().unwrap(),
+        });
+
+        let wrong_scope_token = issuer.issue(GrantRequest {
+            client_id: EXAMPLE_CLIENT_ID,
+            owner_id: EXAMPLE_OWNER_ID,
+            redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            scope: &"wrong needed".parse().unwrap(),
+        });
+
+        let small_scope_token = issuer.issue(GrantRequest {
+            client_id: EXAMPLE_CLIENT_ID,
+            owner_id: EXAMPLE_OWNER_ID,
+            redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
+            scope: &"legit".parse().unwrap(),
+        });
+
+        ResourceSetup {
+            issuer,
+            authtoken: authtoken.token,
+            wrong_scope_token: wrong_scope_token.token,
+            small_scope_token: small_scope_token.token,
+            resource_scope: ["needed legit".parse().unwrap()],
+        }
+    }
+
+    fn test_access_error(&mut self, mut req: CraftedRequest) {
+        let prepared = AccessFlow::prepare(&mut req).expect("Failed access preparation");
+        match AccessFlow::handle(GuardRef::with(&mut self.issuer, &self.resource_scope), prepared) {
+            Ok(resp) => panic!("Expected an error instead of {:?}", resp),
+            Err(_) => (),
+        }
+    }
+}
+
+#[test]
+fn resource_no_authorization() {
+    // Does not have any authorization
+    let no_authorization = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: None
+    };
+
+    ResourceSetup::new().test_access_error(no_authorization);
+}
+
+#[test]
+fn resource_invalid_token() {
+    // Does not have any authorization
+    let invalid_token = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ThisisnotavalidtokenTooLong".to_string())
+    };
+
+    ResourceSetup::new().test_access_error(invalid_token);
+}
+
+#[test]
+fn resource_wrong_method() {
+    let mut setup = ResourceSetup::new();
+    // Not indicating the `Bearer` authorization method
+    let wrong_method = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("NotBearer ".to_string() + &setup.authtoken),
+    };
+
+    setup.test_access_error(wrong_method);
+}
+
+#[test]
+fn resource_scope_too_small() {
+    let mut setup = ResourceSetup::new();
+    // Scope of used token is too small for access
+    let scope_too_small = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &setup.small_scope_token),
+    };
+
+    setup.test_access_error(scope_too_small);
+}
+
+#[test]
+fn resource_wrong_scope() {
+    let mut setup = ResourceSetup::new();
+    // Scope of used token does not match the access
+    let wrong_scope = CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &setup.wrong_scope_token),
+    };
+
+    setup.test_access_error(wrong_scope);
+}
+
+#[test]
+fn resource_expired_token() {
+    use chrono::{Duration, Utc};
+    let clock = MockClock::new(Utc::now());
+    let mut issuer = TokenMap::new(RandomGenerator::new(16)).with_clock(Arc::new(clock.clone()));
+    let token = issuer.issue(GrantRequest {
+        client_id: EXAMPLE_CLIENT_ID,
+        owner_id: EXAMPLE_OWNER_ID,
+        redirect_url: &EXAMPLE_REDIRECT_URL.parse().unwrap(),
+        scope: &EXAMPLE_SCOPE.parse().unwrap(),
+    }).token;
+    let request = || CraftedRequest {
+        query: None,
+        urlbody: None,
+        auth: Some("Bearer ".to_string() + &token),
+    };
+    let scope: [Scope; 1] = [EXAMPLE_SCOPE.parse().unwrap()];
+
+    let mut valid = request();
+    let prepared = AccessFlow::prepare(&mut valid).expect("Failed access preparation");
+    AccessFlow::handle(GuardRef::with(&mut issuer, &scope).with_clock(&clock), prepared)
+        .expect("Failed to authorize");
+
+    clock.advance(Duration::hours(2));
+    let mut expired = request();
+    let prepared = AccessFlow::prepare(&mut expired).expect("Failed access preparation");
+    assert!(AccessFlow::handle(GuardRef::with(&mut issuer, &scope).with_clock(&clock), prepared).is_err());
+}
+
+#[test]
+fn metadata_document() {
+    use super::metadata::Metadata;
+
+    let issuer: Url = "https://server.example".parse().unwrap();
+    let metadata = Metadata::new(issuer.clone())
+        .with_authorization_endpoint(issuer.join("/authorize").unwrap())
+        .with_token_endpoint(issuer.join("/token").unwrap())
+        .with_response_type("code")
+        .with_grant_type("authorization_code")
+        .with_grant_type("authorization_code")
+        .with_scope(&EXAMPLE_SCOPE.parse().unwrap());
+    let parsed: serde_json::Value = serde_json::from_str(&metadata.to_json()).unwrap();
+
+    assert_eq!(parsed["issuer"].as_str(), Some("https://server.example/"));
+    assert_eq!(parsed["authorization_endpoint"].as_str(), Some("https://server.example/authorize"));
+    assert_eq!(parsed["token_endpoint"].as_str(), Some("https://server.example/token"));
+    assert_eq!(parsed["response_types_supported"], serde_json::Value::from(vec!["code"]));
+    assert_eq!(parsed["grant_types_supported"], serde_json::Value::from(vec!["authorization_code"]));
+    assert_eq!(parsed["scopes_supported"].as_array().unwrap().len(), 2);
+    assert!(parsed.get("revocation_endpoint").is_none());
+    assert!(parsed.get("code_challenge_methods_supported").is_none());
+}
+
+struct RegistrationSetup {
+    registrar: ClientMap,
+    generator: RandomGenerator,
+    scope: Scope,
+    management_uri: Url,
+}
+
+impl RegistrationSetup {
+    fn new() -> RegistrationSetup {
+        RegistrationSetup {
+            registrar: ClientMap::new(),
+            generator: RandomGenerator::new(16),
+            scope: EXAMPLE_SCOPE.parse().unwrap(),
+            management_uri: "https://server.example/register/client".parse().unwrap(),
+        }
+    }
+
+    fn register(&mut self, body: &str) -> CraftedResponse {
+        let mut request = CraftedBodyRequest { query: HashMap::new(), auth: None, body: body.to_string() };
+        let prepared = RegistrationFlow::prepare(&mut request).expect("Failure during registration preparation");
+        let registration = RegistrationRef::with(&mut self.registrar, &self.generator, &self.scope)
+            .with_management(&self.management_uri);
+        RegistrationFlow::handle(registration, prepared)
+            .expect("Failure during registration handling")
+    }
+
+    fn manage(&mut self, method: &str, client_id: &str, token: &str, body: &str) -> CraftedResponse {
+        let mut query = HashMap::new();
+        query.insert("client_id".to_string(), vec![client_id.to_string()]);
+        let mut request = CraftedBodyRequest {
+            query,
+            auth: Some("Bearer ".to_string() + token),
+            body: body.to_string(),
+        };
+        let prepared = ClientManagementFlow::prepare(&mut request, method == "PUT")
+            .expect("Failure during management preparation");
+        let management = ManagementRef::with(&mut self.registrar, &self.scope, &self.management_uri);
+        match method {
+            "GET" => ClientManagementFlow::handle_read(management, prepared),
+            "PUT" => ClientManagementFlow::handle_update(management, prepared),
+            "DELETE" => ClientManagementFlow::handle_delete(management, prepared),
+            _ => panic!("Unexpected method {}", method),
+        }.expect("Failure during management handling")
+    }
+
+    fn assert_unauthorized(response: &CraftedResponse) {
+        match *response {
+            CraftedResponse::Authorization(ref inner, ref kind) => {
+                assert_eq!(kind, "Bearer");
+                match **inner {
+                    CraftedResponse::Unauthorized(_) => (),
+                    ref resp => panic!("Expected unauthorized response, got {:?}", resp),
+                }
+            },
+            ref resp => panic!("Expected authorization header, got {:?}", resp),
+        }
+    }
+
+    fn register_success(&mut self, body: &str) -> serde_json::Value {
+        match self.register(body) {
+            CraftedResponse::Created(inner) => match *inner {
+                CraftedResponse::Json(json) => serde_json::from_str(&json).unwrap(),
+                resp => panic!("Expected json response, got {:?}", resp),
+            },
+            resp => panic!("Expected created response, got {:?}", resp),
+        }
+    }
+
+    fn register_error(&mut self, body: &str, error: &str) {
+        let response = self.register(body);
+        DeviceSetup::assert_error(&response, error);
+    }
+}
+
+#[test]
+fn register_confidential() {
+    let mut setup = RegistrationSetup::new();
+    let parsed = setup.register_success(r#"{
+        "redirect_uris": ["https://client.example/endpoint"],
+        "client_name": "Example",
+        "scope": "example"
+    }"#);
+
+    let client_id = parsed["client_id"].as_str().unwrap();
+    let secret = parsed["client_secret"].as_str().unwrap();
+    assert_eq!(parsed["token_endpoint_auth_method"].as_str(), Some("client_secret_basic"));
+    assert_eq!(parsed["scope"].as_str(), Some("example"));
+    assert_eq!(parsed["client_name"].as_str(), Some("Example"));
+
+    use primitives::registrar::Registrar;
+    let client = setup.registrar.client(client_id).expect("Client should be registered");
+    assert!(client.check_authentication(Some(secret.as_bytes())).is_ok());
+    assert!(client.check_authentication(None).is_err());
+}
+
+#[test]
+fn register_public() {
+    let mut setup = RegistrationSetup::new();
+    let parsed = setup.register_success(r#"{
+        "redirect_uris": ["http://localhost:8080/callback"],
+        "token_endpoint_auth_method": "none"
+    }"#);
+
+    assert!(parsed.get("client_secret").is_none());
+    assert_eq!(parsed["grant_types"], serde_json::Value::from(vec!["authorization_code"]));
+
+    let native = setup.register_success(r#"{
+        "redirect_uris": ["com.example.app:/callback"],
+        "token_endpoint_auth_method": "none"
+    }"#);
+    assert_eq!(native["redirect_uris"], serde_json::Value::from(vec!["com.example.app:/callback"]));
+
+    use primitives::registrar::Registrar;
+    let client = setup.registrar.client(parsed["client_id"].as_str().unwrap())
+        .expect("Client should be registered");
+    assert!(client.check_authentication(None).is_ok());
+}
+
+#[test]
+fn register_invalid_metadata() {
+    let mut setup = RegistrationSetup::new();
+    setup.register_error("not json", "invalid_client_metadata");
+    setup.register_error(r#"{"redirect_uris": []}"#, "invalid_redirect_uri");
+    setup.register_error(r#"{"redirect_uris": ["http://client.example/endpoint"]}"#,
+        "invalid_redirect_uri");
+    setup.register_error(r#"{"redirect_uris": ["https://client.example/endpoint#fragment"]}"#,
+        "invalid_redirect_uri");
+    setup.register_error(r#"{"redirect_uris": ["javascript:alert(1)"]}"#, "invalid_redirect_uri");
+    setup.register_error(r#"{"redirect_uris": ["data:text/html,code"]}"#, "invalid_redirect_uri");
+    setup.register_error(r#"{"redirect_uris": ["file:///etc/passwd"]}"#, "invalid_redirect_uri");
+    setup.register_error(r#"{"redirect_uris": ["vbscript:msgbox"]}"#, "invalid_redirect_uri");
+    setup.register_error(r#"{"redirect_uris": ["https://client.example/endpoint"], "scope": "admin"}"#,
+        "invalid_client_metadata");
+    setup.register_error(r#"{"redirect_uris": ["https://client.example/endpoint"],
+        "grant_types": ["password"]}"#, "invalid_client_metadata");
+    setup.register_error(r#"{"redirect_uris": ["https://client.example/endpoint"],
+        "token_endpoint_auth_method": "none", "grant_types": ["client_credentials"]}"#,
+        "invalid_client_metadata");
+}
+
+#[test]
+fn manage_read_and_update() {
+    let mut setup = RegistrationSetup::new();
+    let parsed = setup.register_success(r#"{
+        "redirect_uris": ["https://client.example/endpoint"],
+        "client_name": "Example"
+    }"#);
+
+    let client_id = parsed["client_id"].as_str().unwrap().to_string();
+    let secret = parsed["client_secret"].as_str().unwrap().to_string();
+    let token = parsed["registration_access_token"].as_str().unwrap().to_string();
+    let client_uri: Url = parsed["registration_client_uri"].as_str().unwrap().parse().unwrap();
+    assert_eq!(client_uri.path(), "/register/client");
+    assert_eq!(client_uri.query_pairs().collect::<Vec<_>>(),
+        vec![(Cow::Borrowed("client_id"), Cow::Borrowed(client_id.as_str()))]);
+
+    let read: serde_json::Value = match setup.manage("GET", &client_id, &token, "") {
+        CraftedResponse::Json(json) => serde_json::from_str(&json).unwrap(),
+        resp => panic!("Expected json response, got {:?}", resp),
+    };
+    assert_eq!(read["client_name"].as_str(), Some("Example"));
+    assert!(read.get("client_secret").is_none());
+
+    let update = format!(r#"{{
+        "client_id": "{}",
+        "redirect_uris": ["https://client.example/other"],
+        "client_name": "Renamed"
+    }}"#, client_id);
+    let updated: serde_json::Value = match setup.manage("PUT", &client_id, &token, &update) {
+        CraftedResponse::Json(json) => serde_json::from_str(&json).unwrap(),
+        resp => panic!("Expected json response, got {:?}", resp),
+    };
+    assert_eq!(updated["client_name"].as_str(), Some("Renamed"));
+    assert_eq!(updated["redirect_uris"], serde_json::Value::from(vec!["https://client.example/other"]));
+
+    use primitives::registrar::Registrar;
+    let client = setup.registrar.client(&client_id).expect("Client should still be registered");
+    assert_eq!(client.redirect_url().as_str(), "https://client.example/other");
+    assert!(client.check_authentication(Some(secret.as_bytes())).is_ok());
+
+    let public = format!(r#"{{
+        "client_id": "{}",
+        "redirect_uris": ["https://client.example/other"],
+        "token_endpoint_auth_method": "none"
+    }}"#, client_id);
+    DeviceSetup::assert_error(&setup.manage("PUT", &client_id, &token, &public), "invalid_client_metadata");
+    let other_id = r#"{"client_id": "Other", "redirect_uris": ["https://client.example/other"]}"#;
+    DeviceSetup::assert_error(&setup.manage("PUT", &client_id, &token, other_id), "invalid_client_metadata");
+}
+
+#[test]
+fn manage_delete() {
+    let mut setup = RegistrationSetup::new();
+    let parsed = setup.register_success(r#"{"redirect_uris": ["https://client.example/endpoint"]}"#);
+    let client_id = parsed["client_id"].as_str().unwrap().to_string();
+    let token = parsed["registration_access_token"].as_str().unwrap().to_string();
+
+    match setup.manage("DELETE", &client_id, &token, "") {
+        CraftedResponse::NoContent(_) => (),
+        resp => panic!("Expected no content response, got {:?}", resp),
+    }
+
+    use primitives::registrar::Registrar;
+    assert!(setup.registrar.client(&client_id).is_none());
+    RegistrationSetup::assert_unauthorized(&setup.manage("GET", &client_id, &token, ""));
+}
+
+#[test]
+fn manage_unauthorized() {
+    let mut setup = RegistrationSetup::new();
+    let first = setup.register_success(r#"{"redirect_uris": ["https://client.example/endpoint"]}"#);
+    let second = setup.register_success(r#"{"redirect_uris": ["https://client.example/endpoint"]}"#);
+    let client_id = first["client_id"].as_str().unwrap();
+    let token = first["registration_access_token"].as_str().unwrap();
+    let other_token = second["registration_access_token"].as_str().unwrap();
+
+    RegistrationSetup::assert_unauthorized(&setup.manage("GET", client_id, "NotAToken", ""));
+    RegistrationSetup::assert_unauthorized(&setup.manage("GET", client_id, other_token, ""));
+    RegistrationSetup::assert_unauthorized(&setup.manage("DELETE", "Unknown", token, ""));
 }
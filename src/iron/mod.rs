@@ -5,9 +5,13 @@
 //! ```no_run
 //! # extern crate oxide_auth;
 //! # extern crate iron;
+//! # extern crate ring;
 //! extern crate router;
 //! use oxide_auth::iron::prelude::*;
+//! use oxide_auth::primitives::generator::{Assertion, AssertionKey};
 //! use iron::prelude::*;
+//! use ring::rand::SystemRandom;
+//! use ring::signature::Ed25519KeyPair;
 //!
 //! use std::thread;
 //! use iron::modifier::Modifier;
@@ -15,7 +19,12 @@
 //!
 //! /// Example of a main function of a iron server supporting oauth.
 //! pub fn main() {
-//!     let passphrase = "This is a super secret phrase";
+//!     // Bearer tokens are JWTs signed with an Ed25519 key, whose public key is published for
+//!     // resource servers. A real server would load a persisted key instead.
+//!     let key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+//!     let key = AssertionKey::ed25519_from_pkcs8(&key).unwrap();
+//!     let issuer = JwtIssuer::from_assertion(Assertion::from_key("first", key),
+//!         "http://localhost:8020", "http://localhost:8020").unwrap();
 //!
 //!     // Create the main token instance, a code_granter with an iron frontend.
 //!     let ohandler = IronGranter::new(
@@ -23,8 +32,9 @@
 //!         ClientMap::new(),
 //!         // Authorization tokens are 16 byte random keys to a memory hash map.
 //!         Storage::new(RandomGenerator::new(16)),
-//!         // Bearer tokens are signed (but not encrypted) using a passphrase.
-//!         TokenSigner::new_from_passphrase(passphrase));
+//!         issuer)
+//!         // Devices direct their owners to the verification page to approve them.
+//!         .with_device_grant("http://localhost:8020/device/verify".parse().unwrap());
 //!
 //!     // Register a dummy client instance
 //!     let client = Client::public("LocalClient", // Client id
@@ -32,12 +42,38 @@
 //!         "default".parse().unwrap()); // Allowed client scope
 //!     ohandler.registrar().unwrap().register_client(client);
 //!
+//!     // Drop expired codes and tokens every minute
+//!     ohandler.spawn_sweeper(std::time::Duration::from_secs(60));
+//!
 //!     // Create a router and bind the relevant pages
 //!     let mut router = Router::new();
 //!     router.get("/authorize", ohandler.authorize(handle_get), "authorize");
 //!     router.post("/authorize", ohandler.authorize(IronOwnerAuthorizer(handle_post)),
 //!         "authorize");
 //!     router.post("/token", ohandler.token(), "token");
+//!     router.post("/revoke", ohandler.revoke(), "revoke");
+//!     router.post("/introspect", ohandler.introspect(), "introspect");
+//!     router.post("/device", ohandler.device(), "device");
+//!     router.get("/device/verify", ohandler.verify_device(handle_get), "verify");
+//!     let client_uri: Url = "http://localhost:8020/register/client".parse().unwrap();
+//!     router.post("/register", ohandler.register(RandomGenerator::new(16),
+//!         "default".parse().unwrap()).with_management(client_uri.clone()), "register");
+//!     router.any("/register/client", ohandler.manage_clients(
+//!         "default".parse().unwrap(), client_uri), "client");
+//!
+//!     // Publish the endpoints for discovery by clients
+//!     let base: Url = "http://localhost:8020".parse().unwrap();
+//!     let metadata = ohandler.metadata(base.clone())
+//!         .with_authorization_endpoint(base.join("/authorize").unwrap())
+//!         .with_token_endpoint(base.join("/token").unwrap())
+//!         .with_revocation_endpoint(base.join("/revoke").unwrap())
+//!         .with_introspection_endpoint(base.join("/introspect").unwrap())
+//!         .with_device_authorization_endpoint(base.join("/device").unwrap())
+//!         .with_registration_endpoint(base.join("/register").unwrap())
+//!         .with_jwks_uri(base.join("/jwks").unwrap())
+//!         .with_scope(&"default".parse().unwrap());
+//!     router.get(WELL_KNOWN_PATH, ohandler.discovery(metadata), "metadata");
+//!     router.get("/jwks", ohandler.jwks(), "jwks");
 //!
 //!     let mut protected = iron::Chain::new(|_: &mut Request| {
 //!         Ok(Response::with((iron::status::Ok, "Hello World!")))
@@ -92,17 +128,26 @@ extern crate iron;
 extern crate urlencoded;
 
 use super::code_grant::prelude::*;
-use super::code_grant::frontend::{AccessFlow, AuthorizationFlow, GrantFlow, OwnerAuthorizer, WebRequest, WebResponse};
+use super::code_grant::frontend::{AccessFlow, AuthorizationFlow, GrantFlow, IntrospectionFlow, OwnerAuthorizer, RevocationFlow, WebRequest, WebResponse};
+use super::code_grant::frontend::{ClientManagementFlow, DeviceFlow, RegistrationFlow, VerificationFlow};
+use super::code_grant::backend::{ManagementRef, RegistrationRef};
+use super::code_grant::backend::DEVICE_CODE_GRANT_TYPE;
 pub use super::code_grant::frontend::{Authentication, OAuthError};
+pub use super::code_grant::backend::{OwnerVerifier, ReplayReporter};
+pub use super::code_grant::metadata::{Metadata, WELL_KNOWN_PATH};
 pub use super::code_grant::Scope;
 pub use super::code_grant::prelude::PreGrant;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex, LockResult, MutexGuard};
-use std::ops::DerefMut;
+use std::io::Read;
+use std::thread;
+use std::time;
+use std::ops::{Deref, DerefMut};
 use std::marker::PhantomData;
 use self::iron::prelude::*;
 use self::iron::headers::{Authorization as AuthHeader};
+use self::iron::method::Method;
 use self::iron::modifiers::Redirect;
 use self::urlencoded::{UrlEncodedBody, UrlEncodedQuery};
 use url::Url;
@@ -120,20 +165,35 @@ pub struct IronGranter<R, A, I> where
     registrar: Arc<Mutex<R>>,
     authorizer: Arc<Mutex<A>>,
     issuer: Arc<Mutex<I>>,
+    devices: Arc<Mutex<DeviceStorage>>,
+    clock: Arc<Clock>,
+    grants: Grants,
+}
+
+/// The optional grants offered by the endpoints of a granter, which also determine its metadata.
+#[derive(Default)]
+struct Grants {
+    implicit: bool,
+    owner_verifier: Option<Arc<OwnerVerifier + Send + Sync>>,
+    verification_uri: Option<Url>,
 }
 
 /// Handles authorization requests from user-agents directed by clients.
 ///
 /// Only holds handles to authorization relevant objects. An additional external handler is used
-/// to communicate with the owner authorization process.
-pub struct IronAuthorizer<PH, R, A> where
+/// to communicate with the owner authorization process. The issuer is used for clients which have
+/// opted into the implicit grant, if the granter offers it.
+pub struct IronAuthorizer<PH, R, A, I> where
     PH: GenericOwnerAuthorizer + Send + Sync,
     R: Registrar + Send + 'static,
     A: Authorizer + Send + 'static,
+    I: Issuer + Send + 'static,
 {
     page_handler: Box<PH>,
     registrar: Arc<Mutex<R>>,
     authorizer: Arc<Mutex<A>>,
+    issuer: Option<Arc<Mutex<I>>>,
+    clock: Arc<Clock>,
 }
 
 /// Handles token requests from clients.
@@ -145,6 +205,83 @@ pub struct IronTokenRequest<R, A, I> where
     registrar: Arc<Mutex<R>>,
     authorizer: Arc<Mutex<A>>,
     issuer: Arc<Mutex<I>>,
+    devices: Option<Arc<Mutex<DeviceStorage>>>,
+    owner_verifier: Option<Arc<OwnerVerifier + Send + Sync>>,
+    replay_reporter: Option<Arc<ReplayReporter + Send + Sync>>,
+    clock: Arc<Clock>,
+}
+
+/// Handles device authorization requests from clients.
+pub struct IronDeviceRequest<R> where
+    R: Registrar + Send + 'static,
+{
+    registrar: Arc<Mutex<R>>,
+    devices: Arc<Mutex<DeviceStorage>>,
+    verification_uri: Url,
+    clock: Arc<Clock>,
+}
+
+/// Handles the verification of device authorizations by resource owners.
+///
+/// Uses an additional external handler to communicate with the owner, just like the authorization
+/// endpoint.
+pub struct IronVerification<PH> where
+    PH: GenericOwnerAuthorizer + Send + Sync,
+{
+    page_handler: Box<PH>,
+    devices: Arc<Mutex<DeviceStorage>>,
+}
+
+/// Handles dynamic registration requests of new clients.
+pub struct IronRegistration<R, G> where
+    R: Registrar + Send + 'static,
+    G: SecretGenerator + Send + Sync + 'static,
+{
+    registrar: Arc<Mutex<R>>,
+    generator: G,
+    scope: Scope,
+    management_uri: Option<Url>,
+    clock: Arc<Clock>,
+}
+
+/// Lets registered clients read, update and delete their configuration.
+pub struct IronClientManagement<R> where
+    R: Registrar + Send + 'static,
+{
+    registrar: Arc<Mutex<R>>,
+    scope: Scope,
+    management_uri: Url,
+}
+
+/// Serves the authorization server metadata document.
+pub struct IronMetadata {
+    document: String,
+}
+
+/// Serves the public keys of the issuer as a JSON Web Key Set.
+pub struct IronJwks<I> where
+    I: Issuer + PublicKeys + Send + 'static
+{
+    issuer: Arc<Mutex<I>>,
+}
+
+/// Handles token revocation requests from clients.
+pub struct IronRevocationRequest<R, I> where
+    R: Registrar + Send + 'static,
+    I: Issuer + Send + 'static
+{
+    registrar: Arc<Mutex<R>>,
+    issuer: Arc<Mutex<I>>,
+}
+
+/// Handles token introspection requests from resource servers.
+pub struct IronIntrospectionRequest<R, I> where
+    R: Registrar + Send + 'static,
+    I: Issuer + Send + 'static
+{
+    registrar: Arc<Mutex<R>>,
+    issuer: Arc<Mutex<I>>,
+    clock: Arc<Clock>,
 }
 
 /// Protects a resource as an AroundMiddleware
@@ -153,6 +290,7 @@ pub struct IronGuard<I> where
 {
     scopes: Vec<Scope>,
     issuer: Arc<Mutex<I>>,
+    clock: Arc<Clock>,
 }
 
 impl iron::typemap::Key for PreGrant<'static> { type Value = PreGrant<'static>; }
@@ -278,6 +416,12 @@ impl<'a, 'b> WebRequest for iron::Request<'a, 'b> {
             Some(hdr) => Ok(Some(Cow::Borrowed(&hdr))),
         }
     }
+
+    fn body(&mut self) -> Result<Vec<u8>, ()> {
+        let mut body = Vec::new();
+        self.body.read_to_end(&mut body).map_err(|_| ())?;
+        Ok(body)
+    }
 }
 
 impl WebResponse for Response {
@@ -304,6 +448,16 @@ impl WebResponse for Response {
         )))
     }
 
+    fn as_created(mut self) -> Result<Self, IronError> {
+        self.status = Some(iron::status::Created);
+        Ok(self)
+    }
+
+    fn as_no_content(mut self) -> Result<Self, IronError> {
+        self.status = Some(iron::status::NoContent);
+        Ok(self)
+    }
+
     fn as_client_error(mut self) -> Result<Self, IronError> {
         self.status = Some(iron::status::BadRequest);
         Ok(self)
@@ -330,28 +484,196 @@ impl<R, A, I> IronGranter<R, A, I> where
         IronGranter {
             registrar: Arc::new(Mutex::new(registrar)),
             authorizer: Arc::new(Mutex::new(data)),
-            issuer: Arc::new(Mutex::new(issuer)) }
+            issuer: Arc::new(Mutex::new(issuer)),
+            devices: Arc::new(Mutex::new(DeviceStorage::new())),
+            clock: Arc::new(SystemClock),
+            grants: Grants::default() }
+    }
+
+    /// Read the time for all endpoints and the device authorizations from the clock.
+    ///
+    /// The authorizer and issuer are not affected and should be constructed with the same clock.
+    pub fn with_clock(self, clock: Arc<Clock>) -> Self {
+        let devices = Arc::new(Mutex::new(DeviceStorage::new().with_clock(clock.clone())));
+        IronGranter { devices, clock, .. self }
+    }
+
+    /// Offer the implicit grant to clients which opted into it with `Client::with_implicit_grant`.
+    ///
+    /// Authorization endpoints reject requests for the `token` response type otherwise.
+    pub fn with_implicit_grant(mut self) -> Self {
+        self.grants.implicit = true;
+        self
+    }
+
+    /// Offer the password grant at token endpoints, checking owner credentials with the verifier.
+    ///
+    /// Owner credentials are only accepted from clients which were registered with
+    /// `Client::with_password_grant`.
+    pub fn with_owner_verifier<V>(mut self, verifier: V) -> Self
+    where V: OwnerVerifier + Send + Sync + 'static {
+        self.grants.owner_verifier = Some(Arc::new(verifier));
+        self
+    }
+
+    /// Offer the device authorization grant.
+    ///
+    /// Devices are instructed to direct the resource owner to the verification uri, which should
+    /// be served by a handler created with `verify_device`.
+    pub fn with_device_grant(mut self, verification_uri: Url) -> Self {
+        self.grants.verification_uri = Some(verification_uri);
+        self
     }
 
     /// Create an authorization code endpoint.
-    pub fn authorize<H: GenericOwnerAuthorizer + Send + Sync>(&self, page_handler: H) -> IronAuthorizer<H, R, A> {
+    pub fn authorize<H: GenericOwnerAuthorizer + Send + Sync>(&self, page_handler: H) -> IronAuthorizer<H, R, A, I> {
         IronAuthorizer {
             authorizer: self.authorizer.clone(),
             page_handler: Box::new(page_handler),
-            registrar: self.registrar.clone() }
+            registrar: self.registrar.clone(),
+            issuer: if self.grants.implicit { Some(self.issuer.clone()) } else { None },
+            clock: self.clock.clone() }
     }
 
     /// Create an access token endpoint.
+    ///
+    /// Also supports the password and device grants, if the granter offers them.
     pub fn token(&self) -> IronTokenRequest<R, A, I> {
         IronTokenRequest {
             registrar: self.registrar.clone(),
             authorizer: self.authorizer.clone(),
+            issuer: self.issuer.clone(),
+            devices: self.grants.verification_uri.as_ref().map(|_| self.devices.clone()),
+            owner_verifier: self.grants.owner_verifier.clone(),
+            replay_reporter: None,
+            clock: self.clock.clone() }
+    }
+
+    /// Create a device authorization endpoint.
+    ///
+    /// Panics unless the granter offers the device grant, see `with_device_grant`.
+    pub fn device(&self) -> IronDeviceRequest<R> {
+        let verification_uri = self.grants.verification_uri.clone()
+            .expect("The device grant needs to be offered with `with_device_grant`");
+        IronDeviceRequest {
+            registrar: self.registrar.clone(),
+            devices: self.devices.clone(),
+            verification_uri,
+            clock: self.clock.clone() }
+    }
+
+    /// Create the verification endpoint where owners approve device authorizations.
+    pub fn verify_device<H: GenericOwnerAuthorizer + Send + Sync>(&self, page_handler: H) -> IronVerification<H> {
+        IronVerification {
+            page_handler: Box::new(page_handler),
+            devices: self.devices.clone() }
+    }
+
+    /// Create a token revocation endpoint.
+    pub fn revoke(&self) -> IronRevocationRequest<R, I> {
+        IronRevocationRequest {
+            registrar: self.registrar.clone(),
             issuer: self.issuer.clone() }
     }
 
+    /// Create a token introspection endpoint.
+    ///
+    /// Enables resource servers which do not share the issuer with this granter to validate
+    /// tokens. They need to be registered as confidential clients `with_introspection`.
+    pub fn introspect(&self) -> IronIntrospectionRequest<R, I> {
+        IronIntrospectionRequest {
+            registrar: self.registrar.clone(),
+            issuer: self.issuer.clone(),
+            clock: self.clock.clone() }
+    }
+
+    /// Create a dynamic client registration endpoint.
+    ///
+    /// Client identifiers and secrets are produced by the generator. Registered clients may
+    /// request at most the given scope.
+    pub fn register<G>(&self, generator: G, scope: Scope) -> IronRegistration<R, G>
+    where G: SecretGenerator + Send + Sync + 'static {
+        IronRegistration {
+            registrar: self.registrar.clone(),
+            generator,
+            scope,
+            management_uri: None,
+            clock: self.clock.clone() }
+    }
+
+    /// Create a client configuration endpoint, served at the given uri.
+    ///
+    /// Clients authenticate with the registration access token handed out by a registration
+    /// endpoint configured `with_management` for the same uri. Requests are dispatched on their
+    /// method, `GET` reads, `PUT` updates and `DELETE` removes the configuration.
+    pub fn manage_clients(&self, scope: Scope, management_uri: Url) -> IronClientManagement<R> {
+        IronClientManagement {
+            registrar: self.registrar.clone(),
+            scope,
+            management_uri }
+    }
+
+    /// Describe the capabilities of this granter in a metadata document.
+    ///
+    /// Optional grants are advertised if the granter offers them, such as the implicit grant after
+    /// `with_implicit_grant`. The endpoint urls depend on the routing of the server and the
+    /// supported scopes on the registered clients, both need to be added to the document by the
+    /// caller.
+    pub fn metadata(&self, issuer: Url) -> Metadata {
+        let mut metadata = Metadata::new(issuer)
+            .with_response_type("code")
+            .with_grant_type("authorization_code")
+            .with_grant_type("refresh_token")
+            .with_grant_type("client_credentials")
+            .with_token_endpoint_auth_method("client_secret_basic")
+            .with_token_endpoint_auth_method("none")
+            .with_code_challenge_method("plain")
+            .with_code_challenge_method("S256");
+
+        if self.grants.implicit {
+            metadata = metadata
+                .with_response_type("token")
+                .with_grant_type("implicit");
+        }
+
+        if self.grants.owner_verifier.is_some() {
+            metadata = metadata.with_grant_type("password");
+        }
+
+        if self.grants.verification_uri.is_some() {
+            metadata = metadata.with_grant_type(DEVICE_CODE_GRANT_TYPE);
+        }
+
+        metadata
+    }
+
+    /// Create an endpoint serving the metadata document, usually routed at `WELL_KNOWN_PATH`.
+    pub fn discovery(&self, metadata: Metadata) -> IronMetadata {
+        IronMetadata { document: metadata.to_json() }
+    }
+
     /// Create a BeforeMiddleware capable of guarding other resources.
     pub fn guard<T>(&self, scopes: T) -> IronGuard<I> where T: IntoIterator<Item=Scope> {
-        IronGuard { issuer: self.issuer.clone(), scopes: scopes.into_iter().collect() }
+        IronGuard { issuer: self.issuer.clone(), scopes: scopes.into_iter().collect(), clock: self.clock.clone() }
+    }
+
+    /// Regularly drop expired codes and tokens from the authorizer and issuer in a new thread.
+    ///
+    /// In-memory authorizers and issuers otherwise keep expired entries forever. The thread exits
+    /// once the granter and all endpoints created from it have been dropped.
+    pub fn spawn_sweeper(&self, interval: time::Duration) -> thread::JoinHandle<()> {
+        let authorizer = Arc::downgrade(&self.authorizer);
+        let issuer = Arc::downgrade(&self.issuer);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match (authorizer.upgrade(), issuer.upgrade()) {
+                (Some(authorizer), Some(issuer)) => {
+                    authorizer.lock().unwrap().purge_expired();
+                    issuer.lock().unwrap().purge_expired();
+                },
+                _ => return,
+            }
+        })
     }
 
     /// Thread-safely access the underlying registrar, which is responsible for client registrarion.
@@ -368,6 +690,25 @@ impl<R, A, I> IronGranter<R, A, I> where
     pub fn issuer(&self) -> LockResult<MutexGuard<I>> {
         self.issuer.lock()
     }
+
+    /// Thread-safely access the pending device authorizations.
+    pub fn devices(&self) -> LockResult<MutexGuard<DeviceStorage>> {
+        self.devices.lock()
+    }
+}
+
+impl<R, A, I> IronGranter<R, A, I> where
+    R: Registrar + Send + 'static,
+    A: Authorizer + Send + 'static,
+    I: Issuer + PublicKeys + Send + 'static
+{
+    /// Create an endpoint publishing the public keys with which resource servers verify tokens.
+    ///
+    /// The key set is built anew for each request, such that keys added to or retired from the
+    /// issuer are reflected immediately.
+    pub fn jwks(&self) -> IronJwks<I> {
+        IronJwks { issuer: self.issuer.clone() }
+    }
 }
 
 impl From<OAuthError> for IronError {
@@ -376,17 +717,27 @@ impl From<OAuthError> for IronError {
     }
 }
 
-impl<PH, R, A> iron::Handler for IronAuthorizer<PH, R, A> where
+impl<PH, R, A, I> iron::Handler for IronAuthorizer<PH, R, A, I> where
     PH: GenericOwnerAuthorizer + Send + Sync + 'static,
     R: Registrar + Send + 'static,
-    A: Authorizer + Send + 'static
+    A: Authorizer + Send + 'static,
+    I: Issuer + Send + 'static
 {
     fn handle<'a>(&'a self, req: &mut iron::Request) -> IronResult<Response> {
         let prepared = AuthorizationFlow::prepare(req)?;
 
         let mut locked_registrar = self.registrar.lock().unwrap();
         let mut locked_authorizer = self.authorizer.lock().unwrap();
-        let code = CodeRef::with(locked_registrar.deref_mut(), locked_authorizer.deref_mut());
+        let mut locked_issuer = self.issuer.as_ref().map(|issuer| issuer.lock().unwrap());
+        let code = match locked_issuer {
+            Some(ref mut issuer) => CodeRef::with_issuer(
+                locked_registrar.deref_mut(),
+                locked_authorizer.deref_mut(),
+                issuer.deref_mut()),
+            None => CodeRef::with(
+                locked_registrar.deref_mut(),
+                locked_authorizer.deref_mut()),
+        }.with_clock(self.clock.as_ref());
 
         let handler = SpecificOwnerAuthorizer(self.page_handler.as_ref(), PhantomData);
         AuthorizationFlow::handle(code, prepared, &handler)
@@ -405,15 +756,179 @@ impl<R, A, I> iron::Handler for IronTokenRequest<R, A, I> where
         let mut locked_registrar = self.registrar.lock().unwrap();
         let mut locked_authorizer = self.authorizer.lock().unwrap();
         let mut locked_issuer = self.issuer.lock().unwrap();
+        let mut locked_devices = self.devices.as_ref().map(|devices| devices.lock().unwrap());
         let issuer = IssuerRef::with(
             locked_registrar.deref_mut(),
             locked_authorizer.deref_mut(),
-            locked_issuer.deref_mut());
+            locked_issuer.deref_mut())
+            .with_clock(self.clock.as_ref());
+        let issuer = match locked_devices {
+            Some(ref mut devices) => issuer.with_devices(devices.deref_mut()),
+            None => issuer,
+        };
+        let issuer = match self.owner_verifier {
+            Some(ref verifier) => issuer.with_owner_verifier(verifier.as_ref()),
+            None => issuer,
+        };
+        let issuer = match self.replay_reporter {
+            Some(ref reporter) => issuer.with_replay_reporter(reporter.as_ref()),
+            None => issuer,
+        };
 
         GrantFlow::handle(issuer, prepared)
     }
 }
 
+impl<R, A, I> IronTokenRequest<R, A, I> where
+    R: Registrar + Send + 'static,
+    A: Authorizer + Send + 'static,
+    I: Issuer + Send + 'static
+{
+    /// Report authorization codes which are redeemed more than once to the reporter.
+    pub fn with_replay_reporter<V>(self, reporter: V) -> Self
+    where V: ReplayReporter + Send + Sync + 'static {
+        IronTokenRequest { replay_reporter: Some(Arc::new(reporter)), .. self }
+    }
+}
+
+impl<R, G> iron::Handler for IronRegistration<R, G> where
+    R: Registrar + Send + 'static,
+    G: SecretGenerator + Send + Sync + 'static,
+{
+    fn handle<'a>(&'a self, req: &mut iron::Request) -> IronResult<Response> {
+        let prepared = RegistrationFlow::prepare(req)?;
+
+        let mut locked_registrar = self.registrar.lock().unwrap();
+        let registration = RegistrationRef::with(
+            locked_registrar.deref_mut(),
+            &self.generator,
+            &self.scope)
+            .with_clock(self.clock.as_ref());
+        let registration = match self.management_uri {
+            Some(ref uri) => registration.with_management(uri),
+            None => registration,
+        };
+
+        RegistrationFlow::handle(registration, prepared)
+    }
+}
+
+impl<R, G> IronRegistration<R, G> where
+    R: Registrar + Send + 'static,
+    G: SecretGenerator + Send + Sync + 'static,
+{
+    /// Hand out registration access tokens for the client configuration endpoint at the uri.
+    pub fn with_management(self, management_uri: Url) -> Self {
+        IronRegistration { management_uri: Some(management_uri), .. self }
+    }
+}
+
+impl<R> iron::Handler for IronClientManagement<R> where
+    R: Registrar + Send + 'static,
+{
+    fn handle<'a>(&'a self, req: &mut iron::Request) -> IronResult<Response> {
+        let method = req.method.clone();
+        let prepared = match method {
+            Method::Get | Method::Delete => ClientManagementFlow::prepare(req, false)?,
+            Method::Put => ClientManagementFlow::prepare(req, true)?,
+            _ => return Ok(Response::with(iron::status::MethodNotAllowed)),
+        };
+
+        let mut locked_registrar = self.registrar.lock().unwrap();
+        let management = ManagementRef::with(
+            locked_registrar.deref_mut(),
+            &self.scope,
+            &self.management_uri);
+
+        match method {
+            Method::Put => ClientManagementFlow::handle_update(management, prepared),
+            Method::Delete => ClientManagementFlow::handle_delete(management, prepared),
+            _ => ClientManagementFlow::handle_read(management, prepared),
+        }
+    }
+}
+
+impl iron::Handler for IronMetadata {
+    fn handle<'a>(&'a self, _: &mut iron::Request) -> IronResult<Response> {
+        Response::json(&self.document)
+    }
+}
+
+impl<I> iron::Handler for IronJwks<I> where
+    I: Issuer + PublicKeys + Send + 'static
+{
+    fn handle<'a>(&'a self, _: &mut iron::Request) -> IronResult<Response> {
+        let key_set = self.issuer.lock().unwrap().key_set();
+        Response::json(&key_set.to_json())
+    }
+}
+
+impl<R> iron::Handler for IronDeviceRequest<R> where
+    R: Registrar + Send + 'static,
+{
+    fn handle<'a>(&'a self, req: &mut iron::Request) -> IronResult<Response> {
+        let prepared = DeviceFlow::prepare(req)?;
+
+        let locked_registrar = self.registrar.lock().unwrap();
+        let mut locked_devices = self.devices.lock().unwrap();
+        let device = DeviceRef::with(
+            locked_registrar.deref(),
+            locked_devices.deref_mut())
+            .with_clock(self.clock.as_ref());
+
+        DeviceFlow::handle(device, prepared, &self.verification_uri)
+    }
+}
+
+impl<PH> iron::Handler for IronVerification<PH> where
+    PH: GenericOwnerAuthorizer + Send + Sync + 'static,
+{
+    fn handle<'a>(&'a self, req: &mut iron::Request) -> IronResult<Response> {
+        let prepared = VerificationFlow::prepare(req)?;
+
+        let mut locked_devices = self.devices.lock().unwrap();
+        let verifier = VerificationRef::with(locked_devices.deref_mut());
+
+        let handler = SpecificOwnerAuthorizer(self.page_handler.as_ref(), PhantomData);
+        VerificationFlow::handle(verifier, prepared, &handler)
+    }
+}
+
+impl<R, I> iron::Handler for IronRevocationRequest<R, I> where
+    R: Registrar + Send + 'static,
+    I: Issuer + Send + 'static
+{
+    fn handle<'a>(&'a self, req: &mut iron::Request) -> IronResult<Response> {
+        let prepared = RevocationFlow::prepare(req)?;
+
+        let locked_registrar = self.registrar.lock().unwrap();
+        let mut locked_issuer = self.issuer.lock().unwrap();
+        let revoker = RevocationRef::with(
+            locked_registrar.deref(),
+            locked_issuer.deref_mut());
+
+        RevocationFlow::handle(revoker, prepared)
+    }
+}
+
+impl<R, I> iron::Handler for IronIntrospectionRequest<R, I> where
+    R: Registrar + Send + 'static,
+    I: Issuer + Send + 'static
+{
+    fn handle<'a>(&'a self, req: &mut iron::Request) -> IronResult<Response> {
+        let prepared = IntrospectionFlow::prepare(req)?;
+
+        let locked_registrar = self.registrar.lock().unwrap();
+        let locked_issuer = self.issuer.lock().unwrap();
+        let introspector = IntrospectionRef::with(
+            locked_registrar.deref(),
+            locked_issuer.deref())
+            .with_clock(self.clock.as_ref());
+
+        IntrospectionFlow::handle(introspector, prepared)
+    }
+}
+
 impl<I> iron::BeforeMiddleware for IronGuard<I> where
     I: Issuer + Send + 'static
 {
@@ -421,7 +936,8 @@ impl<I> iron::BeforeMiddleware for IronGuard<I> where
         let prepared = AccessFlow::prepare(request)?;
 
         let mut locked_issuer = self.issuer.lock().unwrap();
-        let guard = GuardRef::with(locked_issuer.deref_mut(), &self.scopes);
+        let guard = GuardRef::with(locked_issuer.deref_mut(), &self.scopes)
+            .with_clock(self.clock.as_ref());
 
         let ok = AccessFlow::handle(guard, prepared)?;
         Ok(ok)
@@ -433,4 +949,5 @@ pub mod prelude {
     pub use url::Url;
     pub use code_grant::prelude::*;
     pub use super::{IronGranter, IronOwnerAuthorizer, PreGrant, Authentication, OAuthError};
+    pub use super::{Metadata, WELL_KNOWN_PATH};
 }
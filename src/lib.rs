@@ -14,6 +14,7 @@
 //! internally without any network connections. The interface those two methods use is exactly the
 //! same, guaranteeing responses to be the same in both cases.
 
+extern crate argon2;
 extern crate base64;
 extern crate chrono;
 extern crate url;
@@ -24,6 +25,7 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate untrusted;
 
 #[warn(missing_docs)]
 pub mod primitives;
@@ -5,11 +5,23 @@
 //! to client according to parameters given by the resource owner and the registrar. Upon a client
 //! side request, it will then check the given parameters to determine the authorization of such
 //! clients.
+use std::borrow::Cow;
 use std::collections::HashMap;
-use chrono::{Duration, Utc};
+use std::sync::Arc;
+use base64;
+use chrono::{TimeZone, Utc};
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use rmp_serde;
+use url::Url;
 
-use super::grant::{Grant, GrantRef, GrantRequest};
-use super::generator::TokenGenerator;
+use super::Time;
+use super::clock::{Clock, SystemClock};
+use super::expiry::ExpiryIndex;
+use super::grant::{CodeChallenge, Grant, GrantRef, GrantRequest};
+use super::generator::{Assertion, TokenGenerator};
+use super::issuer::IssuedToken;
+use super::lifetime::LifetimePolicy;
 
 /// Authorizers create and manage authorization codes.
 ///
@@ -19,9 +31,63 @@ pub trait Authorizer {
     fn authorize(&mut self, GrantRequest) -> String;
 
     /// Retrieve the parameters associated with a token, invalidating the code in the process. In
-    /// particular, a code should not be usable twice (there is no stateless implementation of an
-    /// authorizer for this reason).
+    /// particular, a code should not be usable twice (there is no fully stateless implementation
+    /// of an authorizer for this reason).
+    ///
+    /// Codes bound to a code challenge should only be returned by `extract_challenged`, as the
+    /// challenge would go unchecked otherwise.
     fn extract<'a>(&mut self, &'a str) -> Option<GrantRef<'a>>;
+
+    /// Create a code bound to a PKCE code challenge, which is returned when extracting the code.
+    ///
+    /// The default implementation does not support code challenges and fails, such that requests
+    /// with a challenge are rejected instead of losing the challenge.
+    fn authorize_challenged(&mut self, _req: GrantRequest, _challenge: &CodeChallenge) -> Result<String, ()> {
+        Err(())
+    }
+
+    /// Retrieve the parameters and the code challenge associated with a code, invalidating it.
+    ///
+    /// The default implementation extracts codes without a challenge.
+    fn extract_challenged<'a>(&mut self, code: &'a str) -> Option<(GrantRef<'a>, Option<CodeChallenge>)> {
+        self.extract(code).map(|grant| (grant, None))
+    }
+
+    /// Remember that a code was redeemed for the tokens, until the code would have expired.
+    ///
+    /// The default implementation forgets redeemed codes, so that a replay is indistinguishable
+    /// from an invalid code.
+    fn redeemed(&mut self, _code: &str, _grant: &GrantRef, _token: &IssuedToken) { }
+
+    /// Retrieve the record of a code which was redeemed before and is not yet expired.
+    ///
+    /// Called when extracting the code failed, such that the tokens issued for the code can be
+    /// revoked as recommended by RFC 6749 section 4.1.2.
+    fn replayed(&mut self, _code: &str) -> Option<RedeemedCode> {
+        None
+    }
+
+    /// Drop the codes which have expired, and the records of redeemed codes.
+    ///
+    /// In-memory authorizers should be purged regularly, as codes which are never redeemed would
+    /// otherwise be kept forever. The default implementation does nothing.
+    fn purge_expired(&mut self) { }
+}
+
+/// A code which was redeemed, and the tokens issued for it.
+#[derive(Clone, Debug)]
+pub struct RedeemedCode {
+    /// The client which redeemed the code.
+    pub client_id: String,
+
+    /// The owner who authorized the client.
+    pub owner_id: String,
+
+    /// The time at which the code expires and is forgotten.
+    pub until: Time,
+
+    /// The access and refresh token issued for the code.
+    pub tokens: Vec<String>,
 }
 
 /// An in-memory hash map.
@@ -29,34 +95,513 @@ pub trait Authorizer {
 /// This authorizer saves a mapping of generated strings to their associated grants. The generator
 /// is itself trait based and can be chosen during construction. It is assumed to not be possible
 /// for two different grants to generate the same token in the issuer.
+///
+/// Redeemed codes are remembered until they expire, to detect their replay. Expired codes are
+/// only dropped by `purge_expired`.
 pub struct Storage<I: TokenGenerator> {
     issuer: I,
-    tokens: HashMap<String, Grant>
+    tokens: HashMap<String, (Grant, Option<CodeChallenge>)>,
+    redeemed: HashMap<String, RedeemedCode>,
+    token_expiry: ExpiryIndex,
+    redeemed_expiry: ExpiryIndex,
+    lifetimes: LifetimePolicy,
+    clock: Arc<Clock>,
 }
 
 
 impl<I: TokenGenerator> Storage<I> {
     /// Create a hash map authorizer with the given issuer as a backend.
     pub fn new(issuer: I) -> Storage<I> {
-        Storage {issuer: issuer, tokens: HashMap::new()}
+        Storage {issuer: issuer, tokens: HashMap::new(), redeemed: HashMap::new(),
+            token_expiry: ExpiryIndex::default(), redeemed_expiry: ExpiryIndex::default(),
+            lifetimes: LifetimePolicy::new(), clock: Arc::new(SystemClock)}
     }
-}
 
-impl<I: TokenGenerator> Authorizer for Storage<I> {
-    fn authorize(&mut self, req: GrantRequest) -> String {
+    /// Choose the lifetime of codes with the policy.
+    pub fn with_lifetimes(self, lifetimes: LifetimePolicy) -> Storage<I> {
+        Storage { lifetimes, .. self }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: Arc<Clock>) -> Storage<I> {
+        Storage { clock, .. self }
+    }
+
+    fn store(&mut self, req: GrantRequest, challenge: Option<CodeChallenge>) -> String {
         let owner_id = req.owner_id.to_string();
         let client_id = req.client_id.to_string();
         let scope = req.scope.clone();
         let redirect_url = req.redirect_url.clone();
-        let until = Utc::now() + Duration::minutes(10);
+        let until = self.clock.now() + self.lifetimes.code(req.client_id, req.scope);
         let grant = Grant {owner_id, client_id, scope, redirect_url, until };
 
         let token = self.issuer.generate(&(&grant).into());
-        self.tokens.insert(token.clone(), grant);
+        self.token_expiry.insert(until, token.clone());
+        self.tokens.insert(token.clone(), (grant, challenge));
         token
     }
 
+    fn purge_redeemed(&mut self, now: Time) {
+        for code in self.redeemed_expiry.expired(now) {
+            let expired = self.redeemed.get(&code).map_or(false, |redeemed| redeemed.until < now);
+            if expired {
+                self.redeemed.remove(&code);
+            }
+        }
+    }
+}
+
+impl<I: TokenGenerator> Authorizer for Storage<I> {
+    fn authorize(&mut self, req: GrantRequest) -> String {
+        self.store(req, None)
+    }
+
     fn extract<'a>(&mut self, grant: &'a str) -> Option<GrantRef<'a>> {
-        self.tokens.remove(grant).map(|v| v.into())
+        match self.extract_challenged(grant) {
+            Some((grant, None)) => Some(grant),
+            _ => None,
+        }
+    }
+
+    fn authorize_challenged(&mut self, req: GrantRequest, challenge: &CodeChallenge) -> Result<String, ()> {
+        Ok(self.store(req, Some(challenge.clone())))
+    }
+
+    fn extract_challenged<'a>(&mut self, grant: &'a str) -> Option<(GrantRef<'a>, Option<CodeChallenge>)> {
+        self.tokens.remove(grant).map(|(grant, challenge)| (grant.into(), challenge))
+    }
+
+    fn redeemed(&mut self, code: &str, grant: &GrantRef, token: &IssuedToken) {
+        let now = self.clock.now();
+        self.purge_redeemed(now);
+        let redeemed = RedeemedCode::new(grant, token);
+        self.redeemed_expiry.insert(redeemed.until, code.to_string());
+        self.redeemed.insert(code.to_string(), redeemed);
+    }
+
+    fn replayed(&mut self, code: &str) -> Option<RedeemedCode> {
+        match self.redeemed.get(code) {
+            Some(redeemed) if redeemed.until >= self.clock.now() => Some(redeemed.clone()),
+            _ => None,
+        }
+    }
+
+    fn purge_expired(&mut self) {
+        let now = self.clock.now();
+        for code in self.token_expiry.expired(now) {
+            let expired = self.tokens.get(&code).map_or(false, |&(ref grant, _)| grant.until < now);
+            if expired {
+                self.tokens.remove(&code);
+            }
+        }
+
+        self.purge_redeemed(now);
+    }
+}
+
+/// Signs grants into short-lived codes instead of storing them.
+///
+/// Any instance with the same keys can redeem a code, so the authorization endpoint and the token
+/// endpoint need not share memory. The codes used so far are remembered in a `ReplayCache` until
+/// they expire, such that each code is redeemable only once. When several token endpoints are
+/// deployed, all requests for a code need to reach the same one for this to hold.
+pub struct SignedCodes {
+    assertion: Assertion,
+    used: ReplayCache,
+    lifetimes: LifetimePolicy,
+    clock: Arc<Clock>,
+}
+
+/// The signed content of a code, the grant, the code challenge and a random nonce.
+///
+/// The grant is nested, such that the message can not be mistaken for a token signed by the same
+/// keys. The nonce identifies the code in the replay cache, since codes for the same grant would
+/// be indistinguishable otherwise.
+#[derive(Serialize, Deserialize)]
+struct CodeMessage<'a>(
+    #[serde(borrow)] (&'a str, &'a str, &'a str, &'a str, (i64, u32)),
+    #[serde(borrow)] Option<(&'a str, &'a str)>,
+    &'a str);
+
+/// Remembers used codes until they expire.
+///
+/// Only a truncated digest is stored per code, which keeps the cache compact regardless of the
+/// length of the codes. Expired entries are pruned from time to time while inserting. The tokens
+/// issued for a code can be recorded alongside, to revoke them when the code is replayed.
+pub struct ReplayCache {
+    seen: HashMap<[u8; 16], (Time, Vec<String>)>,
+    expiry: ExpiryIndex<[u8; 16]>,
+    next_sweep: usize,
+    clock: Arc<Clock>,
+}
+
+/// The minimum number of entries before the cache is pruned.
+const SWEEP_THRESHOLD: usize = 64;
+
+impl SignedCodes {
+    /// Create an authorizer signing codes with the assertion.
+    pub fn new(assertion: Assertion) -> SignedCodes {
+        SignedCodes { assertion, used: ReplayCache::new(), lifetimes: LifetimePolicy::new(),
+            clock: Arc::new(SystemClock) }
+    }
+
+    /// Choose the lifetime of codes with the policy.
+    ///
+    /// Codes are remembered until they expire, so long lifetimes increase the size of the cache.
+    pub fn with_lifetimes(self, lifetimes: LifetimePolicy) -> SignedCodes {
+        SignedCodes { lifetimes, .. self }
+    }
+
+    /// Read the time from the clock instead of the system, also for the replay cache.
+    pub fn with_clock(self, clock: Arc<Clock>) -> SignedCodes {
+        SignedCodes { used: self.used.with_clock(clock.clone()), clock, .. self }
+    }
+
+    /// The keyring of the authorizer.
+    pub fn assertion(&self) -> &Assertion {
+        &self.assertion
+    }
+
+    /// Modify the keyring of the authorizer, for example to rotate keys at runtime.
+    pub fn assertion_mut(&mut self) -> &mut Assertion {
+        &mut self.assertion
+    }
+
+    fn sign(&self, req: GrantRequest, challenge: Option<&CodeChallenge>) -> String {
+        let until = self.clock.now() + self.lifetimes.code(req.client_id, req.scope);
+        let scope = req.scope.to_string();
+        let mut nonce = [0; 16];
+        SystemRandom::new().fill(&mut nonce).expect("Failed to generate a nonce");
+        let nonce = base64::encode(&nonce);
+        let message = CodeMessage(
+            (req.owner_id, req.client_id, req.redirect_url.as_str(), &scope,
+                (until.timestamp(), until.timestamp_subsec_nanos())),
+            challenge.map(|challenge| (challenge.method(), challenge.challenge())),
+            &nonce);
+        self.assertion.sign(rmp_serde::to_vec(&message).unwrap())
+    }
+
+    /// Verify a code, returning its grant, code challenge and nonce.
+    fn decode<'a>(&self, code: &str) -> Option<(GrantRef<'a>, Option<CodeChallenge>, String)> {
+        let message = self.assertion.verify(code).ok()?;
+        let CodeMessage((owner_id, client_id, redirect_url, scope, (ts, tsnanos)), challenge, nonce) =
+            rmp_serde::from_slice(&message).ok()?;
+
+        let challenge = match challenge {
+            None => None,
+            Some((method, challenge)) => Some(CodeChallenge::from_parameters(challenge, Some(method)).ok()?),
+        };
+        let grant = GrantRef {
+            owner_id: Cow::Owned(owner_id.to_string()),
+            client_id: Cow::Owned(client_id.to_string()),
+            scope: Cow::Owned(scope.parse().ok()?),
+            redirect_url: Cow::Owned(Url::parse(redirect_url).ok()?),
+            until: Cow::Owned(Utc.timestamp(ts, tsnanos)),
+        };
+        Some((grant, challenge, nonce.to_string()))
+    }
+}
+
+impl Authorizer for SignedCodes {
+    fn authorize(&mut self, req: GrantRequest) -> String {
+        self.sign(req, None)
+    }
+
+    fn extract<'a>(&mut self, code: &'a str) -> Option<GrantRef<'a>> {
+        match self.extract_challenged(code) {
+            Some((grant, None)) => Some(grant),
+            _ => None,
+        }
+    }
+
+    fn authorize_challenged(&mut self, req: GrantRequest, challenge: &CodeChallenge) -> Result<String, ()> {
+        Ok(self.sign(req, Some(challenge)))
+    }
+
+    fn extract_challenged<'a>(&mut self, code: &'a str) -> Option<(GrantRef<'a>, Option<CodeChallenge>)> {
+        let (grant, challenge, nonce) = self.decode(code)?;
+
+        // Expired codes need not be remembered, they are never accepted again.
+        if *grant.until.as_ref() < self.clock.now() {
+            return None
+        }
+
+        // The encoding of a code is not unique, so its signed nonce identifies it instead.
+        if !self.used.insert(&nonce, *grant.until.as_ref()) {
+            return None
+        }
+
+        Some((grant, challenge))
+    }
+
+    fn redeemed(&mut self, code: &str, grant: &GrantRef, token: &IssuedToken) {
+        if let Some((_, _, nonce)) = self.decode(code) {
+            self.used.record(&nonce, RedeemedCode::new(grant, token).tokens);
+        }
+    }
+
+    fn replayed(&mut self, code: &str) -> Option<RedeemedCode> {
+        let (grant, _, nonce) = self.decode(code)?;
+        let tokens = self.used.tokens(&nonce)?.to_vec();
+
+        // Codes which were extracted but never redeemed for a token are merely invalid.
+        if tokens.is_empty() {
+            return None
+        }
+
+        Some(RedeemedCode {
+            client_id: grant.client_id.into_owned(),
+            owner_id: grant.owner_id.into_owned(),
+            until: grant.until.into_owned(),
+            tokens,
+        })
+    }
+
+    fn purge_expired(&mut self) {
+        self.used.purge_expired();
+    }
+}
+
+impl RedeemedCode {
+    fn new(grant: &GrantRef, token: &IssuedToken) -> RedeemedCode {
+        RedeemedCode {
+            client_id: grant.client_id.to_string(),
+            owner_id: grant.owner_id.to_string(),
+            until: *grant.until.as_ref(),
+            tokens: vec![token.token.clone(), token.refresh.clone()].into_iter()
+                .filter(|token| !token.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl ReplayCache {
+    /// Create an empty cache.
+    pub fn new() -> ReplayCache {
+        ReplayCache { seen: HashMap::new(), expiry: ExpiryIndex::default(), next_sweep: SWEEP_THRESHOLD,
+            clock: Arc::new(SystemClock) }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: Arc<Clock>) -> ReplayCache {
+        ReplayCache { clock, .. self }
+    }
+
+    /// Remember a code until the given time.
+    ///
+    /// Returns `false` if the code was already used and is not yet expired.
+    pub fn insert(&mut self, code: &str, until: Time) -> bool {
+        if self.seen.len() >= self.next_sweep {
+            self.purge_expired();
+        }
+
+        let now = self.clock.now();
+        let key = ReplayCache::key(code);
+        match self.seen.get(&key) {
+            Some(&(previous, _)) if previous >= now => return false,
+            _ => (),
+        }
+
+        self.seen.insert(key, (until, Vec::new()));
+        self.expiry.insert(until, key);
+        true
+    }
+
+    /// Forget all codes which have expired.
+    pub fn purge_expired(&mut self) {
+        let now = self.clock.now();
+        for key in self.expiry.expired(now) {
+            let expired = self.seen.get(&key).map_or(false, |&(until, _)| until < now);
+            if expired {
+                self.seen.remove(&key);
+            }
+        }
+        self.next_sweep = SWEEP_THRESHOLD.max(2*self.seen.len());
+    }
+
+    /// Record the tokens issued for a used code.
+    ///
+    /// Has no effect if the code is not remembered.
+    pub fn record(&mut self, code: &str, tokens: Vec<String>) {
+        if let Some(entry) = self.seen.get_mut(&ReplayCache::key(code)) {
+            entry.1 = tokens;
+        }
+    }
+
+    /// Whether the code was used and is not yet expired.
+    pub fn contains(&self, code: &str) -> bool {
+        self.tokens(code).is_some()
+    }
+
+    /// The tokens recorded for a code which was used and is not yet expired.
+    pub fn tokens(&self, code: &str) -> Option<&[String]> {
+        match self.seen.get(&ReplayCache::key(code)) {
+            Some(&(until, ref tokens)) if until >= self.clock.now() => Some(tokens),
+            _ => None,
+        }
+    }
+
+    /// The number of remembered codes, including expired ones not yet pruned.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Whether no codes are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    fn key(code: &str) -> [u8; 16] {
+        let mut key = [0; 16];
+        key.copy_from_slice(&digest(&SHA256, code.as_bytes()).as_ref()[..16]);
+        key
+    }
+}
+
+impl Default for ReplayCache {
+    fn default() -> ReplayCache {
+        ReplayCache::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use ring::hmac::SigningKey;
+
+    fn request<'a>(redirect_url: &'a ::url::Url, scope: &'a super::super::scope::Scope) -> GrantRequest<'a> {
+        GrantRequest {
+            client_id: "Client".into(),
+            owner_id: "Owner".into(),
+            redirect_url,
+            scope,
+        }
+    }
+
+    #[test]
+    fn signed_codes_single_use() {
+        let key = || Assertion::new(SigningKey::new(&SHA256, b"Shared secret"));
+        let mut authorization_node = SignedCodes::new(key());
+        let mut token_node = SignedCodes::new(key());
+        let redirect_url = "https://example.com".parse().unwrap();
+        let scope = "default".parse().unwrap();
+
+        let code = authorization_node.authorize(request(&redirect_url, &scope));
+        let other = authorization_node.authorize(request(&redirect_url, &scope));
+        assert_ne!(code, other);
+
+        let grant = token_node.extract(&code).unwrap();
+        assert_eq!(grant.owner_id, "Owner");
+        assert_eq!(grant.client_id, "Client");
+        assert!(token_node.replayed(&code).is_none());
+        token_node.redeemed(&code, &grant, &IssuedToken {
+            token: "access".to_string(),
+            refresh: "refresh".to_string(),
+            until: Utc::now(),
+        });
+        assert!(token_node.extract(&code).is_none());
+        assert_eq!(token_node.replayed(&code).unwrap().tokens, vec!["access", "refresh"]);
+        assert!(token_node.replayed(&other).is_none());
+        assert!(token_node.extract(&other).is_some());
+
+        let foreign = SignedCodes::new(Assertion::new(SigningKey::new(&SHA256, b"Other secret")))
+            .authorize(request(&redirect_url, &scope));
+        assert!(token_node.extract(&foreign).is_none());
+        assert!(token_node.extract("invalid code").is_none());
+    }
+
+    #[test]
+    fn signed_codes_same_grant() {
+        use super::super::clock::MockClock;
+        let clock = MockClock::new(Utc::now());
+        let mut authorizer = SignedCodes::new(Assertion::new(SigningKey::new(&SHA256, b"Shared secret")))
+            .with_clock(Arc::new(clock));
+        let redirect_url = "https://example.com".parse().unwrap();
+        let scope = "default".parse().unwrap();
+
+        // Both codes expire at exactly the same time, only their nonce differs.
+        let code = authorizer.authorize(request(&redirect_url, &scope));
+        let other = authorizer.authorize(request(&redirect_url, &scope));
+        assert_ne!(code, other);
+
+        assert!(authorizer.extract(&code).is_some());
+        assert!(authorizer.extract(&other).is_some());
+        assert!(authorizer.extract(&code).is_none());
+        assert!(authorizer.extract(&other).is_none());
+    }
+
+    #[test]
+    fn codes_keep_challenge() {
+        use super::super::generator::RandomGenerator;
+        let redirect_url = "https://example.com".parse().unwrap();
+        let scope = "default".parse().unwrap();
+        let challenge = CodeChallenge::from_parameters(
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM", Some("S256")).unwrap();
+        let key = Assertion::new(SigningKey::new(&SHA256, b"Shared secret"));
+        let authorizers: Vec<Box<Authorizer>> = vec![
+            Box::new(Storage::new(RandomGenerator::new(16))),
+            Box::new(SignedCodes::new(key)),
+        ];
+
+        for mut authorizer in authorizers {
+            let code = authorizer.authorize_challenged(request(&redirect_url, &scope), &challenge).unwrap();
+            let (grant, extracted) = authorizer.extract_challenged(&code).unwrap();
+            assert_eq!(grant.client_id, "Client");
+            assert_eq!(extracted, Some(challenge.clone()));
+
+            // Extracting without the challenge consumes the code without returning it.
+            let code = authorizer.authorize_challenged(request(&redirect_url, &scope), &challenge).unwrap();
+            assert!(authorizer.extract(&code).is_none());
+            assert!(authorizer.extract_challenged(&code).is_none());
+
+            let code = authorizer.authorize(request(&redirect_url, &scope));
+            assert_eq!(authorizer.extract_challenged(&code).unwrap().1, None);
+        }
+    }
+
+    #[test]
+    fn storage_purges_expired() {
+        use super::super::clock::MockClock;
+        use super::super::generator::RandomGenerator;
+        let clock = MockClock::new(Utc::now());
+        let mut storage = Storage::new(RandomGenerator::new(16)).with_clock(Arc::new(clock.clone()));
+        let redirect_url = "https://example.com".parse().unwrap();
+        let scope = "default".parse().unwrap();
+
+        let unused = storage.authorize(request(&redirect_url, &scope));
+        let redeemed = storage.authorize(request(&redirect_url, &scope));
+        let grant = storage.extract(&redeemed).unwrap();
+        storage.redeemed(&redeemed, &grant, &IssuedToken {
+            token: "access".to_string(),
+            refresh: "refresh".to_string(),
+            until: Utc::now(),
+        });
+        clock.advance(Duration::minutes(5));
+        let fresh = storage.authorize(request(&redirect_url, &scope));
+
+        storage.purge_expired();
+        assert_eq!(storage.tokens.len(), 2);
+        assert_eq!(storage.redeemed.len(), 1);
+
+        clock.advance(Duration::minutes(6));
+        storage.purge_expired();
+        assert!(!storage.tokens.contains_key(&unused));
+        assert!(storage.tokens.contains_key(&fresh));
+        assert!(storage.redeemed.is_empty());
+    }
+
+    #[test]
+    fn replay_cache_prunes_expired() {
+        let mut cache = ReplayCache::new();
+        let expired = Utc::now() - Duration::minutes(1);
+        for i in 0..SWEEP_THRESHOLD {
+            assert!(cache.insert(&i.to_string(), expired));
+        }
+        assert!(!cache.contains("0"));
+        assert!(cache.insert("0", Utc::now() + Duration::minutes(1)));
+        assert!(cache.contains("0"));
+        assert!(!cache.insert("0", Utc::now() + Duration::minutes(1)));
+        assert_eq!(cache.len(), 1);
     }
 }
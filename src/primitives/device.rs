@@ -0,0 +1,350 @@
+//! Device authorizations for clients on input constrained devices.
+//!
+//! Instead of redirecting a user-agent, the device displays a short user code which the resource
+//! owner enters on a separate device with a browser. Meanwhile, the client polls the token
+//! endpoint with a device code until the owner has completed the verification. The process is
+//! specified in [RFC 8628](https://tools.ietf.org/html/rfc8628).
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::Duration;
+use rand::{thread_rng, Rng};
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use url::Url;
+
+use super::Time;
+use super::clock::{Clock, SystemClock};
+use super::grant::Grant;
+use super::registrar::PreGrant;
+use super::scope::Scope;
+
+/// Device authorizers keep track of pending device authorizations.
+///
+/// Each authorization is identified by two codes. The device code is a secret known only to the
+/// client while the user code is short enough to be typed by the resource owner.
+pub trait DeviceAuthorizer {
+    /// Start a new authorization for the negotiated parameters.
+    fn start(&mut self, &PreGrant) -> DeviceCodes;
+
+    /// Get the parameters of a pending authorization, to present them to the resource owner.
+    fn pending(&self, user_code: &str) -> Option<PreGrant<'static>>;
+
+    /// Record the consent of the resource owner for a pending authorization.
+    fn approve(&mut self, user_code: &str, owner_id: &str) -> Result<(), ()>;
+
+    /// Record that the resource owner denied a pending authorization.
+    fn deny(&mut self, user_code: &str) -> Result<(), ()>;
+
+    /// Poll the state of an authorization with the device code, on behalf of the client.
+    ///
+    /// A completed authorization is removed in the process, such that the grant is returned only
+    /// once. Authorizations started by another client are reported as unknown and left untouched.
+    fn poll(&mut self, client_id: &str, device_code: &str) -> DevicePoll;
+}
+
+/// Codes returned to the device when starting an authorization.
+#[derive(Clone, Debug)]
+pub struct DeviceCodes {
+    /// The secret code used by the client for polling.
+    pub device_code: String,
+
+    /// The code the resource owner enters during verification.
+    pub user_code: String,
+
+    /// Expiration timestamp of both codes.
+    pub until: Time,
+
+    /// Minimum amount of seconds the client should wait between polling requests.
+    pub interval: i64,
+}
+
+/// The state of a device authorization.
+pub enum DevicePoll {
+    /// The owner has not yet approved or denied the authorization.
+    Pending,
+
+    /// The client polls faster than the requested interval, which has been increased.
+    SlowDown,
+
+    /// The owner approved the authorization, the grant can be used to issue a token.
+    Approved(Grant),
+
+    /// The owner denied the authorization.
+    Denied,
+
+    /// The authorization expired before the owner approved it.
+    Expired,
+
+    /// The device code is not known, or belongs to another client.
+    Unknown,
+}
+
+enum DeviceState {
+    Pending,
+    Approved(String),
+    Denied,
+}
+
+struct Device {
+    client_id: String,
+    redirect_url: Url,
+    scope: Scope,
+    user_code: String,
+    until: Time,
+    interval: Duration,
+    last_poll: Option<Time>,
+    state: DeviceState,
+}
+
+/// An in-memory device authorizer.
+///
+/// Device codes are random tokens. User codes consist of eight characters from an alphabet
+/// without vowels, to avoid accidental words, and are matched without regard to case and dashes.
+pub struct DeviceStorage {
+    lifetime: Duration,
+    interval: Duration,
+    devices: HashMap<String, Device>,
+    user_codes: HashMap<String, String>,
+    clock: Arc<Clock>,
+}
+
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+impl DeviceStorage {
+    /// Create a device authorizer with codes valid for ten minutes and a polling interval of five
+    /// seconds.
+    pub fn new() -> DeviceStorage {
+        DeviceStorage {
+            lifetime: Duration::minutes(10),
+            interval: Duration::seconds(5),
+            devices: HashMap::new(),
+            user_codes: HashMap::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: Arc<Clock>) -> DeviceStorage {
+        DeviceStorage { clock, .. self }
+    }
+
+    fn generate_user_code() -> String {
+        let mut rng = thread_rng();
+        let code = (0..8)
+            .map(|_| USER_CODE_ALPHABET[rng.gen_range(0, USER_CODE_ALPHABET.len())] as char)
+            .collect::<String>();
+        format!("{}-{}", &code[..4], &code[4..])
+    }
+
+    fn normalize(user_code: &str) -> String {
+        user_code.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(|c| c.to_uppercase())
+            .collect()
+    }
+
+    fn pending_device(&self, user_code: &str) -> Option<&String> {
+        let device_code = self.user_codes.get(&DeviceStorage::normalize(user_code))?;
+        match self.devices.get(device_code) {
+            Some(device) => match device.state {
+                DeviceState::Pending if device.until > self.clock.now() => Some(device_code),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    fn remove(&mut self, device_code: &str) -> Option<Device> {
+        let device = self.devices.remove(device_code)?;
+        self.user_codes.remove(&DeviceStorage::normalize(&device.user_code));
+        Some(device)
+    }
+}
+
+impl Default for DeviceStorage {
+    fn default() -> DeviceStorage {
+        DeviceStorage::new()
+    }
+}
+
+impl DeviceAuthorizer for DeviceStorage {
+    fn start(&mut self, pre_grant: &PreGrant) -> DeviceCodes {
+        let device_code = encode_config(
+            &thread_rng().gen_iter::<u8>().take(32).collect::<Vec<u8>>(), URL_SAFE_NO_PAD);
+        let mut user_code = DeviceStorage::generate_user_code();
+        while self.user_codes.contains_key(&DeviceStorage::normalize(&user_code)) {
+            user_code = DeviceStorage::generate_user_code();
+        }
+
+        let until = self.clock.now() + self.lifetime;
+        self.user_codes.insert(DeviceStorage::normalize(&user_code), device_code.clone());
+        self.devices.insert(device_code.clone(), Device {
+            client_id: pre_grant.client_id.to_string(),
+            redirect_url: pre_grant.redirect_url.as_ref().clone(),
+            scope: pre_grant.scope.as_ref().clone(),
+            user_code: user_code.clone(),
+            until,
+            interval: self.interval,
+            last_poll: None,
+            state: DeviceState::Pending,
+        });
+
+        DeviceCodes {
+            device_code,
+            user_code,
+            until,
+            interval: self.interval.num_seconds(),
+        }
+    }
+
+    fn pending(&self, user_code: &str) -> Option<PreGrant<'static>> {
+        let device = &self.devices[self.pending_device(user_code)?];
+        Some(PreGrant {
+            client_id: Cow::Owned(device.client_id.clone()),
+            redirect_url: Cow::Owned(device.redirect_url.clone()),
+            scope: Cow::Owned(device.scope.clone()),
+        })
+    }
+
+    fn approve(&mut self, user_code: &str, owner_id: &str) -> Result<(), ()> {
+        let device_code = self.pending_device(user_code).ok_or(())?.clone();
+        self.devices.get_mut(&device_code).ok_or(())?.state = DeviceState::Approved(owner_id.to_string());
+        Ok(())
+    }
+
+    fn deny(&mut self, user_code: &str) -> Result<(), ()> {
+        let device_code = self.pending_device(user_code).ok_or(())?.clone();
+        self.devices.get_mut(&device_code).ok_or(())?.state = DeviceState::Denied;
+        Ok(())
+    }
+
+    fn poll(&mut self, client_id: &str, device_code: &str) -> DevicePoll {
+        let now = self.clock.now();
+        let expired = match self.devices.get_mut(device_code) {
+            None => return DevicePoll::Unknown,
+            Some(ref device) if device.client_id != client_id => return DevicePoll::Unknown,
+            Some(device) => {
+                if device.until <= now {
+                    true
+                } else if let DeviceState::Pending = device.state {
+                    let too_fast = device.last_poll.map_or(false, |last| now < last + device.interval);
+                    device.last_poll = Some(now);
+                    if too_fast {
+                        device.interval = device.interval + Duration::seconds(5);
+                        return DevicePoll::SlowDown
+                    }
+                    return DevicePoll::Pending
+                } else {
+                    false
+                }
+            },
+        };
+
+        let device = match self.remove(device_code) {
+            Some(device) => device,
+            None => return DevicePoll::Unknown,
+        };
+
+        if expired {
+            return DevicePoll::Expired
+        }
+
+        match device.state {
+            DeviceState::Approved(owner_id) => DevicePoll::Approved(Grant {
+                owner_id,
+                client_id: device.client_id,
+                redirect_url: device.redirect_url,
+                scope: device.scope,
+                until: device.until,
+            }),
+            DeviceState::Denied => DevicePoll::Denied,
+            DeviceState::Pending => DevicePoll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pre_grant() -> PreGrant<'static> {
+        PreGrant {
+            client_id: Cow::Owned("Client".to_string()),
+            redirect_url: Cow::Owned("https://client.example/endpoint".parse().unwrap()),
+            scope: Cow::Owned("default".parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn device_approval() {
+        let mut storage = DeviceStorage::new();
+        let codes = storage.start(&pre_grant());
+
+        match storage.poll("Client", &codes.device_code) {
+            DevicePoll::Pending => (),
+            _ => panic!("Expected pending authorization"),
+        }
+
+        let typed = codes.user_code.to_lowercase().replace("-", "");
+        assert!(storage.pending(&typed).is_some());
+        storage.approve(&typed, "Owner").expect("Pending code should be approvable");
+        assert!(storage.pending(&codes.user_code).is_none());
+
+        match storage.poll("Client", &codes.device_code) {
+            DevicePoll::Approved(grant) => {
+                assert_eq!(grant.owner_id, "Owner");
+                assert_eq!(grant.client_id, "Client");
+            },
+            _ => panic!("Expected approved authorization"),
+        }
+
+        match storage.poll("Client", &codes.device_code) {
+            DevicePoll::Unknown => (),
+            _ => panic!("Grant must only be returned once"),
+        }
+    }
+
+    #[test]
+    fn device_other_client() {
+        let mut storage = DeviceStorage::new();
+        let codes = storage.start(&pre_grant());
+        storage.approve(&codes.user_code, "Owner").expect("Pending code should be approvable");
+
+        match storage.poll("Other", &codes.device_code) {
+            DevicePoll::Unknown => (),
+            _ => panic!("Expected code of another client to be unknown"),
+        }
+        match storage.poll("Client", &codes.device_code) {
+            DevicePoll::Approved(_) => (),
+            _ => panic!("Expected approved authorization to be untouched"),
+        }
+    }
+
+    #[test]
+    fn device_slow_down() {
+        let mut storage = DeviceStorage::new();
+        let codes = storage.start(&pre_grant());
+
+        match storage.poll("Client", &codes.device_code) {
+            DevicePoll::Pending => (),
+            _ => panic!("Expected pending authorization"),
+        }
+        match storage.poll("Client", &codes.device_code) {
+            DevicePoll::SlowDown => (),
+            _ => panic!("Expected request to slow down"),
+        }
+    }
+
+    #[test]
+    fn device_denial() {
+        let mut storage = DeviceStorage::new();
+        let codes = storage.start(&pre_grant());
+        storage.deny(&codes.user_code).expect("Pending code should be deniable");
+        assert!(storage.approve(&codes.user_code, "Owner").is_err());
+
+        match storage.poll("Client", &codes.device_code) {
+            DevicePoll::Denied => (),
+            _ => panic!("Expected denied authorization"),
+        }
+    }
+}
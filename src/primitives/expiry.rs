@@ -0,0 +1,54 @@
+//! Keys of in-memory stores ordered by their expiry.
+//!
+//! Stores sweeping their expired entries take the keys which have expired from the index instead
+//! of scanning all entries.
+use std::collections::BTreeMap;
+use std::mem;
+
+use super::Time;
+
+/// Keys ordered by the time at which their entries expire.
+///
+/// Entries removed from a store before they expire stay in the index, and a key may be reused for
+/// another entry. The store therefore needs to check the expiry of its entry for each key taken
+/// from the index before removing it.
+#[derive(Default)]
+pub struct ExpiryIndex<K = String> {
+    keys: BTreeMap<Time, Vec<K>>,
+}
+
+impl<K> ExpiryIndex<K> {
+    /// Remember a key whose entry expires at the given time.
+    pub fn insert(&mut self, until: Time, key: K) {
+        self.keys.entry(until).or_insert_with(Vec::new).push(key);
+    }
+
+    /// Take all keys whose entries expired before `now`.
+    pub fn expired(&mut self, now: Time) -> Vec<K> {
+        let remaining = self.keys.split_off(&now);
+        mem::replace(&mut self.keys, remaining)
+            .into_iter()
+            .flat_map(|(_, keys)| keys)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn expired_in_order() {
+        let now = Utc::now();
+        let mut index = ExpiryIndex::default();
+        index.insert(now + Duration::minutes(2), "later".to_string());
+        index.insert(now - Duration::minutes(1), "earlier".to_string());
+        index.insert(now, "now".to_string());
+        index.insert(now - Duration::minutes(2), "first".to_string());
+
+        assert_eq!(index.expired(now), vec!["first".to_string(), "earlier".to_string()]);
+        assert!(index.expired(now).is_empty());
+        assert_eq!(index.expired(now + Duration::minutes(3)), vec!["now".to_string(), "later".to_string()]);
+    }
+}
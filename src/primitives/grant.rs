@@ -3,6 +3,8 @@
 use super::{Url, Time};
 use super::scope::Scope;
 use std::borrow::Cow;
+use base64;
+use ring::{constant_time, digest};
 
 /// Owning copy of a grant.
 ///
@@ -23,7 +25,7 @@ pub struct Grant {
     pub redirect_url: Url,
 
     /// Expiration date of the grant (Utc).
-    pub until: Time
+    pub until: Time,
 }
 
 /// An optionally owning version of a grant.
@@ -79,7 +81,7 @@ impl<'a> Into<Grant> for GrantRef<'a> {
             client_id: self.client_id.into_owned(),
             scope: self.scope.into_owned(),
             redirect_url: self.redirect_url.into_owned(),
-            until: self.until.into_owned()
+            until: self.until.into_owned(),
         }
     }
 }
@@ -98,3 +100,110 @@ pub struct GrantRequest<'a> {
     /// The redirection url under which the client resides.
     pub redirect_url: &'a Url,
 }
+
+/// A code challenge as defined by the Proof Key for Code Exchange extension (RFC 7636).
+///
+/// Public clients can not authenticate at the token endpoint, so anyone intercepting their
+/// authorization code could redeem it. With PKCE, the client commits to a secret verifier when
+/// requesting the code and has to present that verifier when redeeming it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodeChallenge {
+    /// The challenge is the verifier itself.
+    Plain(String),
+
+    /// The challenge is the base64url encoded SHA-256 digest of the verifier.
+    Sha256(String),
+}
+
+impl CodeChallenge {
+    /// Interpret the `code_challenge` and `code_challenge_method` parameters of a request.
+    ///
+    /// The method defaults to `plain` if none is given. Fails for unknown methods and for
+    /// challenges which are not formatted according to the rfc.
+    pub fn from_parameters(challenge: &str, method: Option<&str>) -> Result<CodeChallenge, ()> {
+        if !CodeChallenge::valid_format(challenge) {
+            return Err(())
+        }
+
+        match method {
+            None | Some("plain") => Ok(CodeChallenge::Plain(challenge.to_string())),
+            Some("S256") => Ok(CodeChallenge::Sha256(challenge.to_string())),
+            Some(_) => Err(()),
+        }
+    }
+
+    /// The name of the transformation method, as used in the `code_challenge_method` parameter.
+    pub fn method(&self) -> &'static str {
+        match *self {
+            CodeChallenge::Plain(_) => "plain",
+            CodeChallenge::Sha256(_) => "S256",
+        }
+    }
+
+    /// The encoded challenge, as used in the `code_challenge` parameter.
+    pub fn challenge(&self) -> &str {
+        match *self {
+            CodeChallenge::Plain(ref challenge) => challenge,
+            CodeChallenge::Sha256(ref challenge) => challenge,
+        }
+    }
+
+    /// Check a `code_verifier` against the challenge, comparing in constant time.
+    pub fn verify(&self, verifier: &str) -> Result<(), ()> {
+        if !CodeChallenge::valid_format(verifier) {
+            return Err(())
+        }
+
+        let transformed = match *self {
+            CodeChallenge::Plain(_) => verifier.to_string(),
+            CodeChallenge::Sha256(_) => {
+                let digest = digest::digest(&digest::SHA256, verifier.as_bytes());
+                base64::encode_config(digest.as_ref(), base64::URL_SAFE_NO_PAD)
+            },
+        };
+
+        constant_time::verify_slices_are_equal(transformed.as_bytes(), self.challenge().as_bytes())
+            .map_err(|_| ())
+    }
+
+    /// Verifiers and challenges are 43 to 128 characters of the unreserved uri set.
+    fn valid_format(value: &str) -> bool {
+        value.len() >= 43 && value.len() <= 128 && value.chars().all(|ch| match ch {
+            '-' | '.' | '_' | '~' => true,
+            ch if ch >= 'A' && ch <= 'Z' => true,
+            ch if ch >= 'a' && ch <= 'z' => true,
+            ch if ch >= '0' && ch <= '9' => true,
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenge_sha256() {
+        // Example from appendix B of the rfc
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let challenge = CodeChallenge::from_parameters(
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM", Some("S256")).unwrap();
+        assert!(challenge.verify(verifier).is_ok());
+        assert!(challenge.verify("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXK").is_err());
+    }
+
+    #[test]
+    fn challenge_plain() {
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let challenge = CodeChallenge::from_parameters(verifier, None).unwrap();
+        assert!(challenge.verify(verifier).is_ok());
+        assert!(challenge.verify("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM").is_err());
+    }
+
+    #[test]
+    fn challenge_invalid() {
+        assert!(CodeChallenge::from_parameters("tooshort", None).is_err());
+        assert!(CodeChallenge::from_parameters(
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk", Some("S512")).is_err());
+    }
+}
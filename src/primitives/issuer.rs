@@ -2,14 +2,17 @@
 //!
 //! Internally similar to the authorization module, tokens generated here live longer and can be
 //! renewed. There exist two fundamental implementation as well, one utilizing in memory hash maps
-//! while the other uses cryptographic signing.
+//! while the other uses cryptographic signing or encryption.
 use std::collections::HashMap;
 use std::clone::Clone;
 use std::borrow::Cow;
-use chrono::{Utc, Duration};
+use std::sync::Arc;
 use super::Time;
+use super::clock::{Clock, SystemClock};
+use super::expiry::ExpiryIndex;
 use super::grant::{Grant, GrantRef, GrantRequest};
-use super::generator::{TokenGenerator, Assertion};
+use super::generator::{TokenGenerator, Assertion, Sealer};
+use super::lifetime::LifetimePolicy;
 use ring::digest::SHA256;
 use ring::hmac::SigningKey;
 
@@ -23,11 +26,57 @@ pub trait Issuer {
     /// Create a token authorizing the request parameters
     fn issue(&mut self, GrantRequest) -> IssuedToken;
 
+    /// Create an access token without a refresh token, for grants which never hand one out.
+    ///
+    /// The refresh token of the result is empty. The default implementation issues a pair and
+    /// discards the refresh token, which suffices for issuers that do not store their tokens.
+    fn issue_access_only(&mut self, req: GrantRequest) -> IssuedToken {
+        let mut token = self.issue(req);
+        token.refresh = String::new();
+        token
+    }
+
     /// Get the values corresponding to a bearer token
     fn recover_token<'a>(&'a self, &'a str) -> Option<GrantRef<'a>>;
 
     /// Get the values corresponding to a refresh token
     fn recover_refresh<'a>(&'a self, &'a str) -> Option<GrantRef<'a>>;
+
+    /// Invalidate an access or refresh token together with the tokens issued alongside it.
+    ///
+    /// Issuers tracking token families invalidate all tokens descending from the same grant.
+    /// Revoking an unknown token succeeds without any effect. An `Err` indicates that the issuer
+    /// is not capable of revoking its tokens at all, as is the case for the default implementation
+    /// and for self-contained tokens which stay valid until they expire.
+    fn revoke(&mut self, _token: &str) -> Result<(), ()> {
+        Err(())
+    }
+
+    /// Create a token for the grant of a refresh token, continuing its family.
+    ///
+    /// Without `rotate`, only a new access token is issued and the presented refresh token is
+    /// returned again. With `rotate`, the refresh token is invalidated and replaced by a new one.
+    /// Issuers which can not invalidate refresh tokens ignore `rotate`, as does the default
+    /// implementation which issues an unrelated access token.
+    fn refresh(&mut self, refresh: &str, req: GrantRequest, _rotate: bool) -> Result<IssuedToken, ()> {
+        let mut token = self.issue_access_only(req);
+        token.refresh = refresh.to_string();
+        Ok(token)
+    }
+
+    /// Detect the reuse of a refresh token which was replaced by rotation.
+    ///
+    /// A reused token was likely stolen, so the whole family of the token is revoked. Returns
+    /// whether the token was reused, the default implementation does not detect any reuse.
+    fn revoke_reused(&mut self, _refresh: &str) -> bool {
+        false
+    }
+
+    /// Drop the tokens which have expired.
+    ///
+    /// In-memory issuers should be purged regularly, as expired tokens would otherwise be kept
+    /// forever. The default implementation does nothing.
+    fn purge_expired(&mut self) { }
 }
 
 /// Token parameters returned to a client.
@@ -36,7 +85,7 @@ pub struct IssuedToken {
     /// The bearer token
     pub token: String,
 
-    /// The refresh token
+    /// The refresh token, empty if none was issued.
     pub refresh: String,
 
     /// Expiration timestamp (Utc).
@@ -50,10 +99,36 @@ pub struct IssuedToken {
 ///
 /// The generator is itself trait based and can be chosen during construction. It is assumed to not
 /// be possible for two different grants to generate the same token in the issuer.
+///
+/// Tokens refreshed from the same grant form a family, which is revoked as a whole. Refresh tokens
+/// replaced by rotation are remembered to detect their reuse, until all tokens of their family
+/// have expired. Expired tokens are only dropped by `purge_expired`.
 pub struct TokenMap<G: TokenGenerator> {
     generator: G,
-    access: HashMap<String, Grant>,
-    refresh: HashMap<String, Grant>,
+    access: HashMap<String, Arc<Token>>,
+    refresh: HashMap<String, Arc<Token>>,
+    access_expiry: ExpiryIndex,
+    refresh_expiry: ExpiryIndex,
+    rotated: HashMap<String, u64>,
+    families: HashMap<u64, Family>,
+    next_family: u64,
+    lifetimes: LifetimePolicy,
+    clock: Arc<Clock>,
+}
+
+/// A pair of access and refresh token, shared by both maps to find one from the other.
+struct Token {
+    access: String,
+    refresh: String,
+    grant: Grant,
+    family: u64,
+    refresh_until: Option<Time>,
+}
+
+/// All tokens issued for the same original grant.
+struct Family {
+    tokens: Vec<Arc<Token>>,
+    rotated: Vec<String>,
 }
 
 impl<G: TokenGenerator> TokenMap<G> {
@@ -63,37 +138,191 @@ impl<G: TokenGenerator> TokenMap<G> {
             generator: generator,
             access: HashMap::new(),
             refresh: HashMap::new(),
+            access_expiry: ExpiryIndex::default(),
+            refresh_expiry: ExpiryIndex::default(),
+            rotated: HashMap::new(),
+            families: HashMap::new(),
+            next_family: 0,
+            lifetimes: LifetimePolicy::new(),
+            clock: Arc::new(SystemClock),
         }
     }
-}
 
-impl<G: TokenGenerator> Issuer for TokenMap<G> {
-    fn issue(&mut self, req: GrantRequest) -> IssuedToken {
+    /// Choose the lifetimes of access and refresh tokens with the policy.
+    pub fn with_lifetimes(self, lifetimes: LifetimePolicy) -> Self {
+        TokenMap { lifetimes, .. self }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: Arc<Clock>) -> Self {
+        TokenMap { clock, .. self }
+    }
+
+    fn issue_in(&mut self, family: u64, req: GrantRequest, with_refresh: bool) -> IssuedToken {
+        let now = self.clock.now();
+        let refresh_until = if with_refresh {
+            Some(now + self.lifetimes.refresh(req.client_id, req.scope))
+        } else {
+            None
+        };
         let grant = Grant {
             owner_id: req.owner_id.to_string(),
             client_id: req.client_id.to_string(),
             scope: req.scope.clone(),
             redirect_url: req.redirect_url.clone(),
-            until: Utc::now() + Duration::hours(1),
+            until: now + self.lifetimes.access(req.client_id, req.scope),
         };
         let (token, refresh) = {
             let generator_grant = (&grant).into();
             let token = self.generator.generate(&generator_grant);
-            let refresh = self.generator.generate(&generator_grant);
+            let refresh = if with_refresh {
+                self.generator.generate(&generator_grant)
+            } else {
+                String::new()
+            };
             (token, refresh)
         };
         let until = grant.until.clone();
-        self.access.insert(token.clone(), grant.clone());
-        self.refresh.insert(refresh.clone(), grant);
+        let pair = Arc::new(Token { access: token.clone(), refresh: refresh.clone(), grant, family, refresh_until });
+        self.access_expiry.insert(until, token.clone());
+        if let Some(refresh_until) = refresh_until {
+            self.refresh_expiry.insert(refresh_until, refresh.clone());
+        }
+        self.access.insert(token.clone(), pair.clone());
+        if with_refresh {
+            self.refresh.insert(refresh.clone(), pair.clone());
+        }
+        self.families.entry(family)
+            .or_insert_with(|| Family { tokens: Vec::new(), rotated: Vec::new() })
+            .tokens.push(pair);
         IssuedToken { token, refresh, until }
     }
 
+    fn revoke_family(&mut self, family: u64) {
+        let family = match self.families.remove(&family) {
+            None => return,
+            Some(family) => family,
+        };
+
+        for pair in family.tokens.iter() {
+            self.access.remove(&pair.access);
+            self.refresh.remove(&pair.refresh);
+        }
+
+        for rotated in family.rotated.iter() {
+            self.rotated.remove(rotated);
+        }
+    }
+
+    /// Drop a pair from its family once neither of its tokens can be used anymore.
+    ///
+    /// The family itself is dropped with its last pair.
+    fn forget(&mut self, pair: &Arc<Token>) {
+        let in_use = self.access.get(&pair.access).map_or(false, |other| Arc::ptr_eq(other, pair))
+            || self.refresh.get(&pair.refresh).map_or(false, |other| Arc::ptr_eq(other, pair));
+        if in_use {
+            return
+        }
+
+        let empty = match self.families.get_mut(&pair.family) {
+            None => return,
+            Some(family) => {
+                family.tokens.retain(|other| !Arc::ptr_eq(other, pair));
+                family.tokens.is_empty()
+            },
+        };
+
+        if empty {
+            self.revoke_family(pair.family);
+        }
+    }
+}
+
+impl<G: TokenGenerator> Issuer for TokenMap<G> {
+    fn issue(&mut self, req: GrantRequest) -> IssuedToken {
+        let family = self.next_family;
+        self.next_family += 1;
+        self.issue_in(family, req, true)
+    }
+
+    fn issue_access_only(&mut self, req: GrantRequest) -> IssuedToken {
+        let family = self.next_family;
+        self.next_family += 1;
+        self.issue_in(family, req, false)
+    }
+
     fn recover_token<'a>(&'a self, token: &'a str) -> Option<GrantRef<'a>> {
-        self.access.get(token).map(|v| v.into())
+        self.access.get(token).map(|v| (&v.grant).into())
     }
 
     fn recover_refresh<'a>(&'a self, token: &'a str) -> Option<GrantRef<'a>> {
-        self.refresh.get(token).map(|v| v.into())
+        let now = self.clock.now();
+        self.refresh.get(token)
+            .filter(|v| v.refresh_until.map(|until| now < until).unwrap_or(true))
+            .map(|v| (&v.grant).into())
+    }
+
+    fn revoke(&mut self, token: &str) -> Result<(), ()> {
+        // Refresh tokens replaced by rotation still identify their family, for example when the
+        // tokens issued for a replayed authorization code are revoked.
+        let family = match self.access.get(token).or_else(|| self.refresh.get(token)) {
+            Some(pair) => pair.family,
+            None => match self.rotated.get(token) {
+                None => return Ok(()),
+                Some(&family) => family,
+            },
+        };
+
+        self.revoke_family(family);
+        Ok(())
+    }
+
+    fn refresh(&mut self, refresh: &str, req: GrantRequest, rotate: bool) -> Result<IssuedToken, ()> {
+        let family = self.refresh.get(refresh).ok_or(())?.family;
+        if !rotate {
+            let mut token = self.issue_in(family, req, false);
+            token.refresh = refresh.to_string();
+            return Ok(token)
+        }
+
+        self.refresh.remove(refresh);
+        self.rotated.insert(refresh.to_string(), family);
+        if let Some(family) = self.families.get_mut(&family) {
+            family.rotated.push(refresh.to_string());
+        }
+
+        Ok(self.issue_in(family, req, true))
+    }
+
+    fn revoke_reused(&mut self, refresh: &str) -> bool {
+        match self.rotated.get(refresh).cloned() {
+            None => false,
+            Some(family) => {
+                self.revoke_family(family);
+                true
+            },
+        }
+    }
+
+    fn purge_expired(&mut self) {
+        let now = self.clock.now();
+        for token in self.access_expiry.expired(now) {
+            let pair = match self.access.get(&token) {
+                Some(pair) if pair.grant.until < now => pair.clone(),
+                _ => continue,
+            };
+            self.access.remove(&token);
+            self.forget(&pair);
+        }
+
+        for token in self.refresh_expiry.expired(now) {
+            let pair = match self.refresh.get(&token) {
+                Some(pair) if pair.refresh_until.map_or(false, |until| until < now) => pair.clone(),
+                _ => continue,
+            };
+            self.refresh.remove(&token);
+            self.forget(&pair);
+        }
     }
 }
 
@@ -103,12 +332,14 @@ impl<G: TokenGenerator> Issuer for TokenMap<G> {
 /// issued, are harder to revoke.
 pub struct TokenSigner {
     signer: Assertion,
+    lifetimes: LifetimePolicy,
+    clock: Arc<Clock>,
 }
 
 impl TokenSigner {
     /// Construct a signing instance from a private signing key.
     pub fn new(key: SigningKey) -> TokenSigner {
-        TokenSigner { signer: Assertion::new(key) }
+        TokenSigner::from_assertion(Assertion::new(key))
     }
 
     /// Construct a signing instance from a passphrase, deriving a signing key in the process. This
@@ -116,22 +347,49 @@ impl TokenSigner {
     /// guarantee is given by the author.
     pub fn new_from_passphrase(passwd: &str) -> TokenSigner {
         let key = SigningKey::new(&SHA256, passwd.as_bytes());
-        TokenSigner { signer: Assertion::new(key) }
+        TokenSigner::from_assertion(Assertion::new(key))
+    }
+
+    /// Construct a signing instance from an assertion, for example one using an asymmetric key.
+    ///
+    /// Resource servers can use an assertion constructed from only the public key to recover
+    /// tokens. Such an instance panics when asked to issue tokens.
+    pub fn from_assertion(assertion: Assertion) -> TokenSigner {
+        TokenSigner { signer: assertion, lifetimes: LifetimePolicy::new(), clock: Arc::new(SystemClock) }
+    }
+
+    /// Choose the lifetimes of access and refresh tokens with the policy.
+    ///
+    /// Signed refresh tokens can not be revoked and stay valid for the whole lifetime chosen by the
+    /// policy.
+    pub fn with_lifetimes(self, lifetimes: LifetimePolicy) -> TokenSigner {
+        TokenSigner { lifetimes, .. self }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: Arc<Clock>) -> TokenSigner {
+        TokenSigner { clock, .. self }
+    }
+
+    /// The public key with which issued tokens can be verified, if signed asymmetrically.
+    pub fn public_key(&self) -> Option<&[u8]> {
+        self.signer.public_key()
+    }
+
+    /// The keyring of the signer.
+    pub fn assertion(&self) -> &Assertion {
+        &self.signer
+    }
+
+    /// Modify the keyring of the signer, for example to rotate keys at runtime.
+    pub fn assertion_mut(&mut self) -> &mut Assertion {
+        &mut self.signer
     }
 }
 
 impl Issuer for TokenSigner {
     fn issue(&mut self, req: GrantRequest) -> IssuedToken {
-        let grant = GrantRef {
-            owner_id: req.owner_id.into(),
-            client_id: req.client_id.into(),
-            scope: Cow::Borrowed(req.scope),
-            redirect_url: Cow::Borrowed(req.redirect_url),
-            until: Cow::Owned(Utc::now() + Duration::hours(1)),
-        };
-        let token = self.signer.tag("token").generate(&grant);
-        let refresh = self.signer.tag("refresh").generate(&grant);
-        IssuedToken {token, refresh, until: grant.until.into_owned() }
+        issue_self_contained(&self.lifetimes, self.clock.now(), req, self.signer.tag("token"), self.signer.tag("refresh"))
     }
 
     fn recover_token<'a>(&'a self, token: &'a str) -> Option<GrantRef<'a>> {
@@ -140,12 +398,88 @@ impl Issuer for TokenSigner {
 
     fn recover_refresh<'a>(&'a self, token: &'a str) -> Option<GrantRef<'a>> {
         self.signer.tag("refresh").extract(token).ok()
+            .filter(|grant| self.clock.now() < *grant.until)
+    }
+}
+
+/// Encrypts grants instead of storing them.
+///
+/// Like a `TokenSigner`, but the grant can not be read from the token by its holder, for example
+/// by the client. Consequently, only the sealer itself can recover tokens.
+pub struct TokenSealer {
+    sealer: Sealer,
+    lifetimes: LifetimePolicy,
+    clock: Arc<Clock>,
+}
+
+impl TokenSealer {
+    /// Construct an instance from a sealer, which may hold multiple keys for rotation.
+    pub fn new(sealer: Sealer) -> TokenSealer {
+        TokenSealer { sealer, lifetimes: LifetimePolicy::new(), clock: Arc::new(SystemClock) }
+    }
+
+    /// Choose the lifetimes of access and refresh tokens with the policy.
+    ///
+    /// Sealed refresh tokens can not be revoked and stay valid for the whole lifetime chosen by the
+    /// policy.
+    pub fn with_lifetimes(self, lifetimes: LifetimePolicy) -> TokenSealer {
+        TokenSealer { lifetimes, .. self }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: Arc<Clock>) -> TokenSealer {
+        TokenSealer { clock, .. self }
     }
+
+    /// The keyring of the sealer.
+    pub fn sealer(&self) -> &Sealer {
+        &self.sealer
+    }
+
+    /// Modify the keyring of the sealer, for example to rotate keys at runtime.
+    pub fn sealer_mut(&mut self) -> &mut Sealer {
+        &mut self.sealer
+    }
+}
+
+impl Issuer for TokenSealer {
+    fn issue(&mut self, req: GrantRequest) -> IssuedToken {
+        issue_self_contained(&self.lifetimes, self.clock.now(), req, self.sealer.tag("token"), self.sealer.tag("refresh"))
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Option<GrantRef<'a>> {
+        self.sealer.tag("token").extract(token).ok()
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Option<GrantRef<'a>> {
+        self.sealer.tag("refresh").extract(token).ok()
+            .filter(|grant| self.clock.now() < *grant.until)
+    }
+}
+
+/// Generate a self-contained token pair.
+///
+/// Both tokens carry their own expiry, the refresh token that of the refresh lifetime.
+fn issue_self_contained<G: TokenGenerator>(lifetimes: &LifetimePolicy, now: Time, req: GrantRequest, access: G, refresh: G)
+-> IssuedToken {
+    let mut grant = GrantRef {
+        owner_id: req.owner_id.into(),
+        client_id: req.client_id.into(),
+        scope: Cow::Borrowed(req.scope),
+        redirect_url: Cow::Borrowed(req.redirect_url),
+        until: Cow::Owned(now + lifetimes.access(req.client_id, req.scope)),
+    };
+    let until = grant.until.clone().into_owned();
+    let token = access.generate(&grant);
+    grant.until = Cow::Owned(now + lifetimes.refresh(req.client_id, req.scope));
+    let refresh = refresh.generate(&grant);
+    IssuedToken { token, refresh, until }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::Utc;
     #[test]
     fn token_signer_roundtrip() {
         let passwd = "Some secret password";
@@ -165,4 +499,233 @@ mod tests {
         assert_eq!(from_token.owner_id, "Owner");
         assert!(Utc::now() < *from_token.until.as_ref());
     }
+
+    #[test]
+    fn token_signer_public_key() {
+        use ring::rand::SystemRandom;
+        use ring::signature::Ed25519KeyPair;
+        use super::super::generator::AssertionKey;
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let mut issuer = TokenSigner::from_assertion(
+            Assertion::from_key("ed25519", AssertionKey::ed25519_from_pkcs8(&pkcs8).unwrap()));
+        let request = GrantRequest {
+            client_id: "Client".into(),
+            owner_id: "Owner".into(),
+            redirect_url: &"https://example.com".parse().unwrap(),
+            scope: &"default".parse().unwrap(),
+        };
+
+        let issued = issuer.issue(request);
+        let verifier = TokenSigner::from_assertion(
+            Assertion::from_key("ed25519", AssertionKey::ed25519_public_key(issuer.public_key().unwrap())));
+        assert_eq!(verifier.recover_token(&issued.token).unwrap().owner_id, "Owner");
+        assert!(verifier.recover_token(&issued.refresh).is_none());
+        assert!(verifier.recover_refresh(&issued.refresh).is_some());
+    }
+
+    #[test]
+    fn token_sealer_roundtrip() {
+        use ring::aead::AES_256_GCM;
+        use super::super::generator::SealKey;
+        let mut issuer = TokenSealer::new(Sealer::new(SealKey::generate(&AES_256_GCM)));
+        let request = GrantRequest {
+            client_id: "Client".into(),
+            owner_id: "Owner".into(),
+            redirect_url: &"https://example.com".parse().unwrap(),
+            scope: &"default".parse().unwrap(),
+        };
+
+        let issued = issuer.issue(request);
+        assert_eq!(issuer.recover_token(&issued.token).unwrap().owner_id, "Owner");
+        assert!(issuer.recover_token(&issued.refresh).is_none());
+        assert_eq!(issuer.recover_refresh(&issued.refresh).unwrap().client_id, "Client");
+        assert!(issuer.revoke(&issued.token).is_err());
+    }
+
+    #[test]
+    fn token_lifetimes() {
+        use chrono::Duration;
+        use super::super::generator::RandomGenerator;
+        use super::super::lifetime::Lifetimes;
+        let lifetimes = || LifetimePolicy::new()
+            .with_default(Lifetimes::new().with_refresh(Duration::days(1)))
+            .with_scope("admin", Lifetimes::new()
+                .with_access(Duration::minutes(5))
+                .with_refresh(Duration::seconds(-1)));
+        let mut map = TokenMap::new(RandomGenerator::new(16)).with_lifetimes(lifetimes());
+        let mut signer = TokenSigner::new_from_passphrase("Some secret password").with_lifetimes(lifetimes());
+
+        for issuer in [&mut map as &mut Issuer, &mut signer].iter_mut() {
+            let default = issuer.issue(GrantRequest {
+                client_id: "Client".into(),
+                owner_id: "Owner".into(),
+                redirect_url: &"https://example.com".parse().unwrap(),
+                scope: &"default".parse().unwrap(),
+            });
+            assert!(default.until > Utc::now() + Duration::minutes(59));
+            assert!(issuer.recover_refresh(&default.refresh).is_some());
+
+            // Admin tokens are short-lived and can not be refreshed
+            let admin = issuer.issue(GrantRequest {
+                client_id: "Client".into(),
+                owner_id: "Owner".into(),
+                redirect_url: &"https://example.com".parse().unwrap(),
+                scope: &"default admin".parse().unwrap(),
+            });
+            assert!(admin.until < Utc::now() + Duration::minutes(6));
+            assert!(issuer.recover_token(&admin.token).is_some());
+            assert!(issuer.recover_refresh(&admin.refresh).is_none());
+        }
+    }
+
+    #[test]
+    fn refresh_expires_with_clock() {
+        use chrono::Duration;
+        use super::super::clock::MockClock;
+        use super::super::generator::RandomGenerator;
+        use super::super::lifetime::Lifetimes;
+        let clock = MockClock::new(Utc::now());
+        let lifetimes = || LifetimePolicy::new()
+            .with_default(Lifetimes::new().with_refresh(Duration::days(1)));
+        let mut map = TokenMap::new(RandomGenerator::new(16))
+            .with_lifetimes(lifetimes())
+            .with_clock(Arc::new(clock.clone()));
+        let mut signer = TokenSigner::new_from_passphrase("Some secret password")
+            .with_lifetimes(lifetimes())
+            .with_clock(Arc::new(clock.clone()));
+
+        for issuer in [&mut map as &mut Issuer, &mut signer].iter_mut() {
+            let issued = issuer.issue(GrantRequest {
+                client_id: "Client".into(),
+                owner_id: "Owner".into(),
+                redirect_url: &"https://example.com".parse().unwrap(),
+                scope: &"default".parse().unwrap(),
+            });
+            assert_eq!(issued.until, clock.now() + Duration::hours(1));
+
+            clock.advance(Duration::hours(23));
+            assert!(issuer.recover_refresh(&issued.refresh).is_some());
+            clock.advance(Duration::hours(2));
+            assert!(issuer.recover_refresh(&issued.refresh).is_none());
+            clock.advance(Duration::hours(-25));
+        }
+    }
+
+    #[test]
+    fn token_map_purges_expired() {
+        use chrono::Duration;
+        use super::super::clock::MockClock;
+        use super::super::generator::RandomGenerator;
+        use super::super::lifetime::Lifetimes;
+        let clock = MockClock::new(Utc::now());
+        let mut map = TokenMap::new(RandomGenerator::new(16))
+            .with_lifetimes(LifetimePolicy::new()
+                .with_default(Lifetimes::new().with_refresh(Duration::days(1))))
+            .with_clock(Arc::new(clock.clone()));
+        let redirect_url = "https://example.com".parse().unwrap();
+        let scope = "default".parse().unwrap();
+        let request = || GrantRequest {
+            client_id: "Client".into(),
+            owner_id: "Owner".into(),
+            redirect_url: &redirect_url,
+            scope: &scope,
+        };
+
+        let first = map.issue(request());
+        let rotated = map.refresh(&first.refresh, request(), true).unwrap();
+
+        // The refresh token outlives the access tokens, and with it the record of rotation
+        clock.advance(Duration::hours(2));
+        map.purge_expired();
+        assert!(map.access.is_empty());
+        assert_eq!(map.refresh.len(), 1);
+        assert_eq!(map.families.values().map(|family| family.tokens.len()).sum::<usize>(), 1);
+        assert!(map.recover_refresh(&rotated.refresh).is_some());
+        assert!(map.rotated.contains_key(&first.refresh));
+
+        clock.advance(Duration::days(1));
+        map.purge_expired();
+        assert!(map.refresh.is_empty());
+        assert!(map.families.is_empty());
+        assert!(map.rotated.is_empty());
+    }
+
+    #[test]
+    fn token_map_refresh_keeps_refresh_token() {
+        use super::super::generator::RandomGenerator;
+        let mut map = TokenMap::new(RandomGenerator::new(16));
+        let redirect_url = "https://example.com".parse().unwrap();
+        let scope = "default".parse().unwrap();
+        let request = || GrantRequest {
+            client_id: "Client".into(),
+            owner_id: "Owner".into(),
+            redirect_url: &redirect_url,
+            scope: &scope,
+        };
+
+        let first = map.issue(request());
+        let second = map.refresh(&first.refresh, request(), false).unwrap();
+        let third = map.refresh(&first.refresh, request(), false).unwrap();
+        assert_eq!(second.refresh, first.refresh);
+        assert_eq!(third.refresh, first.refresh);
+        assert_eq!(map.refresh.len(), 1);
+        assert_eq!(map.access.len(), 3);
+
+        // All access tokens belong to the family of the refresh token
+        map.revoke(&first.refresh).unwrap();
+        assert!(map.access.is_empty());
+        assert!(map.families.is_empty());
+    }
+
+    #[test]
+    fn token_map_access_only() {
+        use super::super::generator::RandomGenerator;
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+        let issued = issuer.issue_access_only(GrantRequest {
+            client_id: "Client".into(),
+            owner_id: "Owner".into(),
+            redirect_url: &"https://example.com".parse().unwrap(),
+            scope: &"default".parse().unwrap(),
+        });
+
+        assert!(issued.refresh.is_empty());
+        assert!(issuer.recover_token(&issued.token).is_some());
+        assert!(issuer.refresh.is_empty());
+        issuer.revoke(&issued.token).unwrap();
+        assert!(issuer.recover_token(&issued.token).is_none());
+        assert!(issuer.families.is_empty());
+    }
+
+    #[test]
+    fn token_map_revoke() {
+        use super::super::generator::RandomGenerator;
+        let mut issuer = TokenMap::new(RandomGenerator::new(16));
+        let request = GrantRequest {
+            client_id: "Client".into(),
+            owner_id: "Owner".into(),
+            redirect_url: &"https://example.com".parse().unwrap(),
+            scope: &"default".parse().unwrap(),
+        };
+
+        let first = issuer.issue(request);
+        let request = GrantRequest {
+            client_id: "Client".into(),
+            owner_id: "Owner".into(),
+            redirect_url: &"https://example.com".parse().unwrap(),
+            scope: &"default".parse().unwrap(),
+        };
+        let second = issuer.issue(request);
+
+        // Revoking the access token also invalidates its refresh token
+        assert!(issuer.revoke(&first.token).is_ok());
+        assert!(issuer.recover_token(&first.token).is_none());
+        assert!(issuer.recover_refresh(&first.refresh).is_none());
+
+        // Revoking the refresh token also invalidates its access token
+        assert!(issuer.revoke(&second.refresh).is_ok());
+        assert!(issuer.recover_token(&second.token).is_none());
+        assert!(issuer.recover_refresh(&second.refresh).is_none());
+
+        assert!(issuer.revoke("unknown token").is_ok());
+    }
 }
@@ -0,0 +1,564 @@
+//! Access tokens in the JSON Web Token format.
+//!
+//! Tokens follow the profile of [RFC 9068](https://tools.ietf.org/html/rfc9068), such that
+//! resource servers can validate them with any JWT library instead of asking the issuer. Like
+//! other self-contained tokens, they can not be revoked before they expire. The public keys for
+//! verification are published as a JSON Web Key Set, as specified in
+//! [RFC 7517](https://tools.ietf.org/html/rfc7517).
+use std::borrow::Cow;
+use std::sync::Arc;
+use chrono::{TimeZone, Utc};
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use rand::{thread_rng, Rng};
+use ring::hmac;
+use serde_json;
+use url::Url;
+
+use super::clock::{Clock, SystemClock};
+use super::generator::{Assertion, AssertionKey, DEFAULT_KEY_ID};
+use super::grant::{GrantRef, GrantRequest};
+use super::issuer::{IssuedToken, Issuer};
+use super::lifetime::LifetimePolicy;
+use super::scope::Scope;
+
+/// Issues access tokens as JWTs.
+///
+/// Tokens are signed with the active key of a keyring and name its id in the `kid` header, such
+/// that keys can be rotated. Refresh tokens are encoded and signed the same way but marked with a
+/// different type, so that one can not be used in place of the other. As they can not be revoked,
+/// refresh tokens are only valid for the refresh lifetime of the policy.
+pub struct JwtIssuer {
+    verifier: JwtVerifier,
+    lifetimes: LifetimePolicy,
+}
+
+/// Recovers access tokens issued by a `JwtIssuer`, without being able to issue any.
+///
+/// Resource servers can construct a verifier from only the public keys of the issuer.
+pub struct JwtVerifier {
+    assertion: Assertion,
+    issuer: String,
+    audience: String,
+    clock: Arc<Clock>,
+}
+
+/// Issuers publishing the public keys with which their tokens can be verified.
+pub trait PublicKeys {
+    /// The public keys of all keys whose tokens are accepted, including those not yet active.
+    fn key_set(&self) -> JwkSet;
+}
+
+/// A JSON Web Key Set of public verification keys.
+///
+/// Secret keys are never included, so HMAC keys are silently skipped.
+#[derive(Serialize, Clone, Debug)]
+pub struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct Jwk {
+    kty: &'static str,
+    crv: &'static str,
+    x: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<String>,
+    kid: String,
+    #[serde(rename = "use")]
+    use_: &'static str,
+    alg: &'static str,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    typ: String,
+    alg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kid: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    iss: String,
+    sub: String,
+    aud: String,
+    client_id: String,
+    scope: String,
+    exp: i64,
+    iat: i64,
+    jti: String,
+    /// Not registered by RFC 9068, hence named by a collision-resistant uri.
+    #[serde(rename = "https://github.com/HeroicKatora/oxide-auth/redirect_uri")]
+    redirect_uri: String,
+}
+
+const ACCESS_TYPE: &str = "at+jwt";
+const REFRESH_TYPE: &str = "rt+jwt";
+
+impl JwtIssuer {
+    /// Construct an issuer signing with the HMAC key.
+    ///
+    /// The `issuer` is the identifier of the authorization server and the `audience` identifies
+    /// the resource servers for which the tokens are intended. Both are checked when recovering
+    /// a token.
+    ///
+    /// Fails if the key does not use SHA-256, SHA-384 or SHA-512.
+    pub fn new(key: hmac::SigningKey, issuer: &str, audience: &str) -> Result<JwtIssuer, ()> {
+        JwtIssuer::from_assertion(Assertion::new(key), issuer, audience)
+    }
+
+    /// Construct an issuer signing with the keyring of the assertion.
+    ///
+    /// Fails if the active key has no JWS algorithm or is only a public key. Resource servers
+    /// which only know the public keys should construct a `JwtVerifier` instead.
+    pub fn from_assertion(assertion: Assertion, issuer: &str, audience: &str) -> Result<JwtIssuer, ()> {
+        if !assertion.key(assertion.active_key_id()).unwrap().can_sign() {
+            return Err(())
+        }
+
+        Ok(JwtIssuer {
+            verifier: JwtVerifier::new(assertion, issuer, audience)?,
+            lifetimes: LifetimePolicy::new(),
+        })
+    }
+
+    /// Choose the lifetimes of access and refresh tokens with the policy.
+    pub fn with_lifetimes(self, lifetimes: LifetimePolicy) -> JwtIssuer {
+        JwtIssuer { lifetimes, .. self }
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: Arc<Clock>) -> JwtIssuer {
+        JwtIssuer { verifier: self.verifier.with_clock(clock), .. self }
+    }
+
+    /// The keyring of the issuer.
+    pub fn assertion(&self) -> &Assertion {
+        &self.verifier.assertion
+    }
+
+    /// Modify the keyring of the issuer, for example to rotate keys at runtime.
+    ///
+    /// The active key should always be able to sign, issuing tokens panics otherwise.
+    pub fn assertion_mut(&mut self) -> &mut Assertion {
+        &mut self.verifier.assertion
+    }
+
+    fn encode(&self, typ: &str, claims: &Claims) -> String {
+        let assertion = &self.verifier.assertion;
+        let key_id = assertion.active_key_id();
+        let key = assertion.key(key_id).unwrap();
+        let header = Header {
+            typ: typ.to_string(),
+            alg: key.jws_algorithm().expect("Unsupported key for JWT signatures").to_string(),
+            kid: Some(key_id.to_string()),
+        };
+        let mut token = encode_config(&serde_json::to_vec(&header).unwrap(), URL_SAFE_NO_PAD);
+        token.push('.');
+        token.push_str(&encode_config(&serde_json::to_vec(claims).unwrap(), URL_SAFE_NO_PAD));
+
+        let signature = key.sign(token.as_bytes());
+        token.push('.');
+        token.push_str(&encode_config(&signature, URL_SAFE_NO_PAD));
+        token
+    }
+}
+
+impl JwtVerifier {
+    /// Construct a verifier accepting the tokens of all keys of the assertion.
+    ///
+    /// The `issuer` and `audience` need to match those of the `JwtIssuer`. Fails if the active key
+    /// has no JWS algorithm.
+    pub fn new(assertion: Assertion, issuer: &str, audience: &str) -> Result<JwtVerifier, ()> {
+        if assertion.key(assertion.active_key_id()).unwrap().jws_algorithm().is_none() {
+            return Err(())
+        }
+
+        Ok(JwtVerifier {
+            assertion,
+            issuer: issuer.to_string(),
+            audience: audience.to_string(),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Read the time from the clock instead of the system.
+    pub fn with_clock(self, clock: Arc<Clock>) -> JwtVerifier {
+        JwtVerifier { clock, .. self }
+    }
+
+    /// The keyring of the verifier.
+    pub fn assertion(&self) -> &Assertion {
+        &self.assertion
+    }
+
+    /// Modify the keyring of the verifier, to follow the key rotation of the issuer.
+    pub fn assertion_mut(&mut self) -> &mut Assertion {
+        &mut self.assertion
+    }
+
+    /// Get the grant of an access token, if it is valid and not expired.
+    pub fn recover_token<'a>(&self, token: &str) -> Option<GrantRef<'a>> {
+        self.decode(ACCESS_TYPE, token)
+    }
+
+    fn decode<'a>(&self, typ: &str, token: &str) -> Option<GrantRef<'a>> {
+        let mut parts = token.rsplitn(2, '.');
+        let signature = decode_config(parts.next()?, URL_SAFE_NO_PAD).ok()?;
+        let message = parts.next()?;
+
+        let mut parts = message.splitn(2, '.');
+        let header = decode_config(parts.next()?, URL_SAFE_NO_PAD).ok()?;
+        let header: Header = serde_json::from_slice(&header).ok()?;
+
+        // The algorithm is determined by the key, never by the token.
+        let key_id = header.kid.as_ref().map(String::as_str).unwrap_or(DEFAULT_KEY_ID);
+        let key = self.assertion.key(key_id)?;
+        if key.jws_algorithm() != Some(header.alg.as_str()) {
+            return None
+        }
+        key.verify(message.as_bytes(), &signature).ok()?;
+
+        let claims = decode_config(parts.next()?, URL_SAFE_NO_PAD).ok()?;
+        let claims: Claims = serde_json::from_slice(&claims).ok()?;
+
+        // RFC 9068 permits the explicit media type as well.
+        let typ_matches = header.typ.eq_ignore_ascii_case(typ)
+            || header.typ.eq_ignore_ascii_case(&format!("application/{}", typ));
+        if !typ_matches {
+            return None
+        }
+
+        if claims.iss != self.issuer || claims.aud != self.audience {
+            return None
+        }
+
+        let until = Utc.timestamp(claims.exp, 0);
+        if until <= self.clock.now() {
+            return None
+        }
+
+        Some(GrantRef {
+            owner_id: Cow::Owned(claims.sub),
+            client_id: Cow::Owned(claims.client_id),
+            scope: Cow::Owned(claims.scope.parse::<Scope>().ok()?),
+            redirect_url: Cow::Owned(claims.redirect_uri.parse::<Url>().ok()?),
+            until: Cow::Owned(until),
+        })
+    }
+}
+
+impl Issuer for JwtIssuer {
+    fn issue(&mut self, req: GrantRequest) -> IssuedToken {
+        let now = self.verifier.clock.now();
+        let until = Utc.timestamp((now + self.lifetimes.access(req.client_id, req.scope)).timestamp(), 0);
+        let mut claims = Claims {
+            iss: self.verifier.issuer.clone(),
+            sub: req.owner_id.to_string(),
+            aud: self.verifier.audience.clone(),
+            client_id: req.client_id.to_string(),
+            scope: req.scope.to_string(),
+            exp: until.timestamp(),
+            iat: now.timestamp(),
+            jti: encode_config(&thread_rng().gen_iter::<u8>().take(16).collect::<Vec<u8>>(), URL_SAFE_NO_PAD),
+            redirect_uri: req.redirect_url.as_str().to_string(),
+        };
+
+        let token = self.encode(ACCESS_TYPE, &claims);
+        claims.jti = encode_config(&thread_rng().gen_iter::<u8>().take(16).collect::<Vec<u8>>(), URL_SAFE_NO_PAD);
+        claims.exp = (now + self.lifetimes.refresh(req.client_id, req.scope)).timestamp();
+        let refresh = self.encode(REFRESH_TYPE, &claims);
+        IssuedToken { token, refresh, until }
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Option<GrantRef<'a>> {
+        self.verifier.decode(ACCESS_TYPE, token)
+    }
+
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Option<GrantRef<'a>> {
+        self.verifier.decode(REFRESH_TYPE, token)
+    }
+}
+
+impl PublicKeys for JwtIssuer {
+    fn key_set(&self) -> JwkSet {
+        JwkSet::new().with_keyring(self.assertion())
+    }
+}
+
+impl JwkSet {
+    /// Start an empty key set.
+    pub fn new() -> JwkSet {
+        JwkSet { keys: Vec::new() }
+    }
+
+    /// Add the public key under its id, unless the key is secret or malformed.
+    pub fn with_key(mut self, key_id: &str, key: &AssertionKey) -> JwkSet {
+        let (public_key, alg) = match (key.public_key(), key.jws_algorithm()) {
+            (Some(public_key), Some(alg)) => (public_key, alg),
+            _ => return self,
+        };
+
+        let jwk = match alg {
+            // An uncompressed point, tagged by a leading byte.
+            "ES256" if public_key.len() == 65 && public_key[0] == 0x04 => Jwk {
+                kty: "EC",
+                crv: "P-256",
+                x: encode_config(&public_key[1..33], URL_SAFE_NO_PAD),
+                y: Some(encode_config(&public_key[33..], URL_SAFE_NO_PAD)),
+                kid: key_id.to_string(),
+                use_: "sig",
+                alg,
+            },
+            "EdDSA" if public_key.len() == 32 => Jwk {
+                kty: "OKP",
+                crv: "Ed25519",
+                x: encode_config(public_key, URL_SAFE_NO_PAD),
+                y: None,
+                kid: key_id.to_string(),
+                use_: "sig",
+                alg,
+            },
+            _ => return self,
+        };
+
+        self.keys.retain(|present| present.kid != jwk.kid);
+        self.keys.push(jwk);
+        self
+    }
+
+    /// Add the public keys of a keyring, including keys not yet active.
+    ///
+    /// Retired keys are not included, as their tokens are no longer accepted.
+    pub fn with_keyring(self, assertion: &Assertion) -> JwkSet {
+        assertion.key_ids().into_iter()
+            .fold(self, |set, key_id| set.with_key(key_id, assertion.key(key_id).unwrap()))
+    }
+
+    /// Convert the key set into a json string, viable for being sent over a network with
+    /// `application/json` encoding.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl Default for JwkSet {
+    fn default() -> JwkSet {
+        JwkSet::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::digest;
+    use ring::rand::SystemRandom;
+    use ring::signature::{ECDSAKeyPair, Ed25519KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::Value;
+    use chrono::Duration;
+
+    fn issuer() -> JwtIssuer {
+        let key = hmac::SigningKey::new(&digest::SHA256, b"Some secret key");
+        JwtIssuer::new(key, "https://server.example", "https://resource.example").unwrap()
+    }
+
+    fn issue(issuer: &mut JwtIssuer) -> IssuedToken {
+        issuer.issue(GrantRequest {
+            client_id: "Client".into(),
+            owner_id: "Owner".into(),
+            redirect_url: &"https://client.example/endpoint".parse().unwrap(),
+            scope: &"default".parse().unwrap(),
+        })
+    }
+
+    fn decode_part(token: &str, index: usize) -> Value {
+        let part = token.split('.').nth(index).unwrap();
+        serde_json::from_slice(&decode_config(part, URL_SAFE_NO_PAD).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn jwt_roundtrip() {
+        let mut issuer = issuer();
+        let issued = issue(&mut issuer);
+
+        let header = decode_part(&issued.token, 0);
+        assert_eq!(header["typ"].as_str(), Some("at+jwt"));
+        assert_eq!(header["alg"].as_str(), Some("HS256"));
+        assert_eq!(header["kid"].as_str(), Some("default"));
+
+        let claims = decode_part(&issued.token, 1);
+        assert_eq!(claims["iss"].as_str(), Some("https://server.example"));
+        assert_eq!(claims["sub"].as_str(), Some("Owner"));
+        assert_eq!(claims["aud"].as_str(), Some("https://resource.example"));
+        assert_eq!(claims["client_id"].as_str(), Some("Client"));
+        assert_eq!(claims["scope"].as_str(), Some("default"));
+        assert_eq!(claims["exp"].as_i64(), Some(issued.until.timestamp()));
+        assert!(claims["iat"].as_i64().is_some());
+        assert!(claims["jti"].as_str().is_some());
+        assert!(claims.get("redirect_uri").is_none());
+
+        let grant = issuer.recover_token(&issued.token).unwrap();
+        assert_eq!(grant.owner_id, "Owner");
+        assert_eq!(grant.client_id, "Client");
+        assert_eq!(grant.scope.as_ref(), &"default".parse().unwrap());
+        assert!(issuer.recover_refresh(&issued.refresh).is_some());
+    }
+
+    #[test]
+    fn jwt_refresh_outlives_access() {
+        use super::super::clock::MockClock;
+        use super::super::grant::Grant;
+        use super::super::lifetime::Lifetimes;
+
+        let clock = MockClock::new(Utc::now());
+        let mut issuer = issuer().with_clock(Arc::new(clock.clone()));
+        let issued = issue(&mut issuer);
+
+        clock.advance(Duration::hours(2));
+        assert!(issuer.recover_token(&issued.token).is_none());
+        let grant: Grant = issuer.recover_refresh(&issued.refresh)
+            .expect("Refresh token expired with access token").into();
+        assert_eq!(grant.client_id, "Client");
+        let refreshed = issuer.refresh(&issued.refresh, GrantRequest {
+            client_id: &grant.client_id,
+            owner_id: &grant.owner_id,
+            redirect_url: &grant.redirect_url,
+            scope: &grant.scope,
+        }, false).unwrap();
+        assert!(issuer.recover_token(&refreshed.token).is_some());
+
+        clock.advance(Duration::days(30));
+        assert!(issuer.recover_refresh(&issued.refresh).is_none());
+
+        // An explicit lifetime takes precedence over the default
+        let mut issuer = issuer.with_lifetimes(LifetimePolicy::new()
+            .with_default(Lifetimes::new().with_refresh(Duration::hours(3))));
+        let issued = issue(&mut issuer);
+        clock.advance(Duration::hours(2));
+        assert!(issuer.recover_refresh(&issued.refresh).is_some());
+        clock.advance(Duration::hours(2));
+        assert!(issuer.recover_refresh(&issued.refresh).is_none());
+    }
+
+    #[test]
+    fn jwt_rejects_forgeries() {
+        let mut issuer = issuer();
+        let issued = issue(&mut issuer);
+
+        // Access and refresh tokens are not interchangeable
+        assert!(issuer.recover_token(&issued.refresh).is_none());
+        assert!(issuer.recover_refresh(&issued.token).is_none());
+
+        // Modified claims invalidate the signature
+        let mut parts: Vec<String> = issued.token.split('.').map(String::from).collect();
+        let mut claims = decode_part(&issued.token, 1);
+        claims["sub"] = Value::from("Admin");
+        parts[1] = encode_config(&serde_json::to_vec(&claims).unwrap(), URL_SAFE_NO_PAD);
+        assert!(issuer.recover_token(&parts.join(".")).is_none());
+
+        // Tokens of a different key or issuer are not accepted
+        let other_key = hmac::SigningKey::new(&digest::SHA256, b"Another secret key");
+        let other = JwtIssuer::new(other_key, "https://server.example", "https://resource.example").unwrap();
+        assert!(other.recover_token(&issued.token).is_none());
+        let key = hmac::SigningKey::new(&digest::SHA256, b"Some secret key");
+        let other = JwtIssuer::new(key, "https://other.example", "https://resource.example").unwrap();
+        assert!(other.recover_token(&issued.token).is_none());
+    }
+
+    #[test]
+    fn jwt_rejects_unsupported_keys() {
+        let key = hmac::SigningKey::new(&digest::SHA1, b"Some secret key");
+        assert!(JwtIssuer::new(key, "https://server.example", "https://resource.example").is_err());
+
+        let rng = SystemRandom::new();
+        let pair = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let public = AssertionKey::ed25519_from_pkcs8(&pair).unwrap().public_key().unwrap().to_vec();
+        let public = Assertion::from_key("first", AssertionKey::ed25519_public_key(&public));
+        assert!(JwtIssuer::from_assertion(public, "https://server.example", "https://resource.example").is_err());
+    }
+
+    #[test]
+    fn jwt_key_rotation() {
+        let rng = SystemRandom::new();
+        let first = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let second = ECDSAKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let assertion = Assertion::from_key("first", AssertionKey::ed25519_from_pkcs8(&first).unwrap());
+        let mut issuer = JwtIssuer::from_assertion(assertion, "https://server.example", "https://resource.example")
+            .unwrap();
+
+        let old = issue(&mut issuer);
+        assert_eq!(decode_part(&old.token, 0)["alg"].as_str(), Some("EdDSA"));
+        issuer.assertion_mut().add_key("second",
+            AssertionKey::ecdsa_p256_from_pkcs8(second.as_ref()).unwrap()).unwrap();
+        issuer.assertion_mut().activate("second").unwrap();
+
+        let new = issue(&mut issuer);
+        let header = decode_part(&new.token, 0);
+        assert_eq!(header["alg"].as_str(), Some("ES256"));
+        assert_eq!(header["kid"].as_str(), Some("second"));
+        assert!(issuer.recover_token(&old.token).is_some());
+        assert!(issuer.recover_token(&new.token).is_some());
+
+        // A verifier only knowing the public key accepts the token
+        let public = issuer.assertion().public_key().unwrap().to_vec();
+        let verifier = JwtVerifier::new(Assertion::from_key("second",
+            AssertionKey::ecdsa_p256_public_key(&public).unwrap()), "https://server.example", "https://resource.example")
+            .unwrap();
+        assert!(verifier.recover_token(&new.token).is_some());
+        assert!(verifier.recover_token(&old.token).is_none());
+        assert!(verifier.recover_token(&new.refresh).is_none());
+
+        issuer.assertion_mut().retire("first").unwrap();
+        assert!(issuer.recover_token(&old.token).is_none());
+        assert!(issuer.recover_token(&new.token).is_some());
+    }
+
+    #[test]
+    fn jwk_set() {
+        let rng = SystemRandom::new();
+        let ed25519 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let ecdsa = ECDSAKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let other = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let mut assertion = Assertion::from_key("retired", AssertionKey::ed25519_from_pkcs8(&other).unwrap());
+        assertion.add_key("active", AssertionKey::ecdsa_p256_from_pkcs8(ecdsa.as_ref()).unwrap()).unwrap();
+        assertion.add_key("next", AssertionKey::ed25519_from_pkcs8(&ed25519).unwrap()).unwrap();
+        assertion.add_key("secret", AssertionKey::hmac(hmac::SigningKey::new(&digest::SHA256, b"Secret"))).unwrap();
+        assertion.activate("active").unwrap();
+        assertion.retire("retired").unwrap();
+
+        let set: Value = serde_json::from_str(&JwkSet::new().with_keyring(&assertion).to_json()).unwrap();
+        let keys = set["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys.iter().all(|key| key["kid"] != "retired"));
+
+        let next = keys.iter().find(|key| key["kid"] == "next").unwrap();
+        assert_eq!(next["kty"].as_str(), Some("OKP"));
+        assert_eq!(next["crv"].as_str(), Some("Ed25519"));
+        assert_eq!(next["alg"].as_str(), Some("EdDSA"));
+        assert_eq!(decode_config(next["x"].as_str().unwrap(), URL_SAFE_NO_PAD).unwrap().len(), 32);
+
+        let active = keys.iter().find(|key| key["kid"] == "active").unwrap();
+        assert_eq!(active["kty"].as_str(), Some("EC"));
+        assert_eq!(active["crv"].as_str(), Some("P-256"));
+        assert_eq!(active["use"].as_str(), Some("sig"));
+        assert_eq!(decode_config(active["x"].as_str().unwrap(), URL_SAFE_NO_PAD).unwrap().len(), 32);
+        assert_eq!(decode_config(active["y"].as_str().unwrap(), URL_SAFE_NO_PAD).unwrap().len(), 32);
+    }
+
+    #[test]
+    fn jwk_set_skips_malformed_keys() {
+        let mut compressed = vec![0x02];
+        compressed.extend_from_slice(&[0x11; 32]);
+        assert!(AssertionKey::ecdsa_p256_public_key(&compressed).is_err());
+        assert!(AssertionKey::ecdsa_p256_public_key(&[0x04; 33]).is_err());
+        assert!(AssertionKey::ecdsa_p256_public_key(&[]).is_err());
+
+        let set = JwkSet::new()
+            .with_key("short", &AssertionKey::ed25519_public_key(&[0x11; 16]))
+            .with_key("valid", &AssertionKey::ed25519_public_key(&[0x11; 32]));
+        let set: Value = serde_json::from_str(&set.to_json()).unwrap();
+        let keys = set["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0]["kid"].as_str(), Some("valid"));
+    }
+}
@@ -0,0 +1,159 @@
+//! Lifetimes of authorization codes, access tokens and refresh tokens.
+//!
+//! Authorizers and issuers consult a `LifetimePolicy` when creating a grant. The policy holds
+//! lifetimes for all grants, which can be overridden for individual clients and shortened for
+//! individual scope-tokens, for example to hand out short-lived tokens for an `admin` scope.
+use std::cmp;
+use std::collections::HashMap;
+use chrono::Duration;
+
+use super::scope::Scope;
+
+/// A set of lifetimes, each of which may be left unspecified.
+///
+/// Unspecified lifetimes are inherited from the defaults of the policy, or from the client for
+/// scope overrides.
+#[derive(Clone, Debug, Default)]
+pub struct Lifetimes {
+    code: Option<Duration>,
+    access: Option<Duration>,
+    refresh: Option<Duration>,
+}
+
+/// Chooses the lifetimes of a grant based on its client and scope.
+///
+/// By default, authorization codes are valid for ten minutes, access tokens for an hour and
+/// refresh tokens for 30 days. The lifetimes configured for a client replace the
+/// defaults, while the lifetimes configured for any scope-token of the grant can only shorten
+/// them.
+#[derive(Clone, Debug)]
+pub struct LifetimePolicy {
+    code: Duration,
+    access: Duration,
+    refresh: Duration,
+    clients: HashMap<String, Lifetimes>,
+    scopes: HashMap<String, Lifetimes>,
+}
+
+impl Lifetimes {
+    /// Start with all lifetimes unspecified.
+    pub fn new() -> Lifetimes {
+        Lifetimes::default()
+    }
+
+    /// Set the lifetime of authorization codes.
+    pub fn with_code(mut self, lifetime: Duration) -> Lifetimes {
+        self.code = Some(lifetime);
+        self
+    }
+
+    /// Set the lifetime of access tokens.
+    pub fn with_access(mut self, lifetime: Duration) -> Lifetimes {
+        self.access = Some(lifetime);
+        self
+    }
+
+    /// Set the lifetime of refresh tokens.
+    pub fn with_refresh(mut self, lifetime: Duration) -> Lifetimes {
+        self.refresh = Some(lifetime);
+        self
+    }
+}
+
+impl LifetimePolicy {
+    /// A policy with the default lifetimes for all grants.
+    pub fn new() -> LifetimePolicy {
+        LifetimePolicy {
+            code: Duration::minutes(10),
+            access: Duration::hours(1),
+            refresh: Duration::days(30),
+            clients: HashMap::new(),
+            scopes: HashMap::new(),
+        }
+    }
+
+    /// Change the lifetimes of all grants.
+    ///
+    /// Unspecified lifetimes keep their previous value.
+    pub fn with_default(mut self, lifetimes: Lifetimes) -> LifetimePolicy {
+        self.code = lifetimes.code.unwrap_or(self.code);
+        self.access = lifetimes.access.unwrap_or(self.access);
+        self.refresh = lifetimes.refresh.unwrap_or(self.refresh);
+        self
+    }
+
+    /// Override the lifetimes of grants for a client.
+    pub fn with_client(mut self, client_id: &str, lifetimes: Lifetimes) -> LifetimePolicy {
+        self.clients.insert(client_id.to_string(), lifetimes);
+        self
+    }
+
+    /// Limit the lifetimes of grants including the scope-token.
+    pub fn with_scope(mut self, scope_token: &str, lifetimes: Lifetimes) -> LifetimePolicy {
+        self.scopes.insert(scope_token.to_string(), lifetimes);
+        self
+    }
+
+    /// The lifetime of an authorization code.
+    pub fn code(&self, client_id: &str, scope: &Scope) -> Duration {
+        self.resolve(client_id, scope, self.code, |lifetimes| lifetimes.code)
+    }
+
+    /// The lifetime of an access token.
+    pub fn access(&self, client_id: &str, scope: &Scope) -> Duration {
+        self.resolve(client_id, scope, self.access, |lifetimes| lifetimes.access)
+    }
+
+    /// The lifetime of a refresh token.
+    pub fn refresh(&self, client_id: &str, scope: &Scope) -> Duration {
+        self.resolve(client_id, scope, self.refresh, |lifetimes| lifetimes.refresh)
+    }
+
+    fn resolve<F>(&self, client_id: &str, scope: &Scope, default: Duration, field: F) -> Duration
+    where F: Fn(&Lifetimes) -> Option<Duration> {
+        let base = self.clients.get(client_id)
+            .and_then(&field)
+            .unwrap_or(default);
+
+        scope.iter()
+            .filter_map(|token| self.scopes.get(token))
+            .filter_map(&field)
+            .fold(base, cmp::min)
+    }
+}
+
+impl Default for LifetimePolicy {
+    fn default() -> LifetimePolicy {
+        LifetimePolicy::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lifetime_overrides() {
+        let policy = LifetimePolicy::new()
+            .with_default(Lifetimes::new().with_access(Duration::minutes(30)))
+            .with_client("LongLived", Lifetimes::new()
+                .with_access(Duration::hours(8))
+                .with_refresh(Duration::days(90)))
+            .with_scope("admin", Lifetimes::new().with_access(Duration::minutes(5)));
+        let default = "default".parse().unwrap();
+        let admin = "default admin".parse().unwrap();
+
+        assert_eq!(policy.code("Client", &default), Duration::minutes(10));
+        assert_eq!(policy.access("Client", &default), Duration::minutes(30));
+        assert_eq!(policy.refresh("Client", &default), Duration::days(30));
+
+        assert_eq!(policy.access("LongLived", &default), Duration::hours(8));
+        assert_eq!(policy.refresh("LongLived", &default), Duration::days(90));
+
+        // Scope overrides only ever shorten lifetimes
+        assert_eq!(policy.access("Client", &admin), Duration::minutes(5));
+        assert_eq!(policy.access("LongLived", &admin), Duration::minutes(5));
+        assert_eq!(policy.refresh("LongLived", &admin), Duration::days(90));
+        assert_eq!(policy.code("LongLived", &admin), Duration::minutes(10));
+    }
+}
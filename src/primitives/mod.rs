@@ -5,19 +5,29 @@ use chrono::Utc;
 use url::Url;
 
 pub mod authorizer;
+pub mod clock;
+pub mod device;
 pub mod generator;
 pub mod grant;
 pub mod issuer;
+pub mod jwt;
+pub mod lifetime;
 pub mod registrar;
 pub mod scope;
 
+mod expiry;
+
 type Time = DateTime<Utc>;
 
 /// Commonly used primitives for frontends and backends.
 pub mod prelude {
-    pub use super::authorizer::{Authorizer, Storage};
-    pub use super::issuer::{IssuedToken, Issuer, TokenMap, TokenSigner};
-    pub use super::generator::{TokenGenerator, RandomGenerator};
-    pub use super::registrar::{Registrar, Client, ClientUrl, ClientMap, PreGrant};
+    pub use super::authorizer::{Authorizer, RedeemedCode, SignedCodes, Storage};
+    pub use super::clock::{Clock, MockClock, SystemClock};
+    pub use super::device::{DeviceAuthorizer, DeviceStorage};
+    pub use super::issuer::{IssuedToken, Issuer, TokenMap, TokenSealer, TokenSigner};
+    pub use super::generator::{TokenGenerator, SecretGenerator, RandomGenerator};
+    pub use super::jwt::{JwkSet, JwtIssuer, JwtVerifier, PublicKeys};
+    pub use super::lifetime::{LifetimePolicy, Lifetimes};
+    pub use super::registrar::{Registrar, Client, ClientUrl, ClientMap, PasswordPolicy, PreGrant};
     pub use super::scope::Scope;
 }
@@ -6,7 +6,10 @@
 use super::scope::Scope;
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::Arc;
 use url::Url;
+use argon2::{self, Config, Variant};
+use rand::{thread_rng, Rng};
 use ring::{constant_time, digest};
 use ring::error::Unspecified;
 
@@ -23,6 +26,46 @@ pub trait Registrar {
 
     /// Look up a client id.
     fn client(&self, client_id: &str) -> Option<&Client>;
+
+    /// Determine if the client must protect its authorization codes with a PKCE challenge.
+    ///
+    /// The default implementation does not require PKCE from any client, while still accepting
+    /// challenges from clients which send one.
+    fn requires_pkce(&self, _client: &Client) -> bool {
+        false
+    }
+
+    /// Add a new client, for example through dynamic registration.
+    ///
+    /// Fails with `RegistrarError::AlreadyRegistered` instead of replacing an existing client. The
+    /// default implementation does not accept new clients and fails with
+    /// `RegistrarError::UnauthorizedClient`.
+    fn register(&mut self, _client: Client) -> Result<(), RegistrarError> {
+        Err(RegistrarError::UnauthorizedClient)
+    }
+
+    /// Replace the configuration of an existing client with the same identifier.
+    ///
+    /// Fails with `RegistrarError::Unregistered` if no such client exists. The default
+    /// implementation does not allow changes and fails with `RegistrarError::UnauthorizedClient`.
+    fn update(&mut self, _client: Client) -> Result<(), RegistrarError> {
+        Err(RegistrarError::UnauthorizedClient)
+    }
+
+    /// Remove a client, such that it can no longer request authorization or tokens.
+    ///
+    /// Fails with `RegistrarError::Unregistered` if no such client exists. The default
+    /// implementation does not allow removal and fails with `RegistrarError::UnauthorizedClient`.
+    fn remove(&mut self, _client_id: &str) -> Result<(), RegistrarError> {
+        Err(RegistrarError::UnauthorizedClient)
+    }
+
+    /// Replace a client whose passphrase was rehashed during its authentication.
+    ///
+    /// The client is produced by `Client::authenticate` and only differs in its passdata, which
+    /// is now stored by the policy it was migrated to. The default implementation discards it, such
+    /// that the previous hash is kept and the client is rehashed again on its next authentication.
+    fn rehash(&mut self, _client: Client) { }
 }
 
 /// A pair of `client_id` and an optional `redirect_url`.
@@ -84,6 +127,12 @@ pub enum RegistrarError {
 
     /// The client is not authorized.
     UnauthorizedClient,
+
+    /// None of the requested scope can be granted to the client.
+    InvalidScope,
+
+    /// A client with the same identifier is already registered.
+    AlreadyRegistered,
 }
 
 /// Clients are registered users of authorization tokens.
@@ -91,59 +140,324 @@ pub enum RegistrarError {
 /// There are two types of clients, public and confidential. Public clients operate without proof
 /// of identity while confidential clients are granted additional assertions on their communication
 /// with the servers. They might be allowed more freedom as they are harder to impersonate.
+#[derive(Clone)]
 pub struct Client {
     client_id: String,
     redirect_url: Url,
     default_scope: Scope,
+    allowed_scope: Scope,
     client_type: ClientType,
+    password_grant: bool,
+    implicit_grant: bool,
+    refresh_rotation: bool,
+    introspection: bool,
+    registration: Option<ClientRegistration>,
 }
 
+/// Information retained about a dynamically registered client.
+///
+/// Besides the metadata submitted during registration, this holds a digest of the registration
+/// access token with which the client can manage its own configuration.
+#[derive(Clone, Debug)]
+pub struct ClientRegistration {
+    token: Option<Vec<u8>>,
+
+    /// Timestamp of the registration, in seconds since the epoch.
+    pub issued_at: i64,
+
+    /// A human-readable name of the client.
+    pub client_name: Option<String>,
+
+    /// The grant types the client registered for.
+    pub grant_types: Vec<String>,
+
+    /// The response types the client registered for.
+    pub response_types: Vec<String>,
+}
+
+#[derive(Clone)]
 enum ClientType {
     /// A public client with no authentication information
     Public,
 
-    /// A confidential client who needs to be authenticated before communicating
-    Confidential{ passdata: Vec<u8>, },
+    /// A confidential client who needs to be authenticated before communicating.
+    Confidential{ passdata: Passdata, },
+}
+
+/// A stored passphrase hash together with the policy which produced it.
+#[derive(Clone)]
+struct Passdata {
+    data: Vec<u8>,
+    policy: Arc<PasswordPolicy>,
+    rehash: Option<Arc<PasswordPolicy>>,
 }
 
 /// A very simple, in-memory hash map of client ids to Client entries.
 pub struct ClientMap {
     clients: HashMap<String, Client>,
+    pkce_public: bool,
+    policy: Option<Arc<PasswordPolicy>>,
+    migrated: Vec<String>,
 }
 
 impl<'a> BoundClient<'a> {
     /// Finish the negotiations with the registrar.
     ///
-    /// The registrar is responsible for choosing the appropriate scope for the client. In the most
-    /// simple case, it will always choose some default scope for the client, regardless of its
-    /// wish. The standard permits this but requires the client to be notified of the resulting
-    /// scope of the token in such a case, when it retrieves its token via the access token
-    /// request.
+    /// The registrar is responsible for choosing the appropriate scope for the client. When no
+    /// scope was requested, the default scope of the client is chosen. Otherwise, the client is
+    /// granted those parts of the requested scope which it is allowed to request. The standard
+    /// permits granting less than requested but requires the client to be notified of the
+    /// resulting scope of the token in such a case, when it retrieves its token via the access
+    /// token request.
     ///
-    /// Currently, this scope agreement algorithm is the only supported method.
-    pub fn negotiate(self, _scope: Option<Scope>) -> PreGrant<'a> {
-        PreGrant {
+    /// Fails with `RegistrarError::InvalidScope` if none of the requested scope can be granted.
+    pub fn negotiate(self, scope: Option<Scope>) -> Result<PreGrant<'a>, RegistrarError> {
+        let scope = match scope {
+            None => self.client.default_scope.clone(),
+            Some(requested) => requested.intersection(&self.client.allowed_scope),
+        };
+
+        if scope.is_empty() {
+            return Err(RegistrarError::InvalidScope)
+        }
+
+        Ok(PreGrant {
             client_id: self.client_id,
             redirect_url: self.redirect_url,
-            scope: Cow::Owned(self.client.default_scope.clone()),
-        }
+            scope: Cow::Owned(scope),
+        })
     }
 }
 
 impl Client {
     /// Create a public client
     pub fn public(client_id: &str, redirect_url: Url, default_scope: Scope) -> Client {
-        Client { client_id: client_id.to_string(), redirect_url, default_scope, client_type: ClientType::Public }
+        Client {
+            client_id: client_id.to_string(),
+            redirect_url,
+            allowed_scope: default_scope.clone(),
+            default_scope,
+            client_type: ClientType::Public,
+            password_grant: false,
+            implicit_grant: false,
+            refresh_rotation: false,
+            introspection: false,
+            registration: None,
+        }
     }
 
     /// Create a confidential client
+    ///
+    /// The passphrase is stored as an Argon2id hash with a random salt.
     pub fn confidential(client_id: &str, redirect_url: Url, default_scope: Scope, passphrase: &[u8]) -> Client {
-        let passdata = SHA256Policy.store(client_id, passphrase);
+        Client::confidential_with_policy(client_id, redirect_url, default_scope, passphrase,
+            Arc::new(Argon2Policy))
+    }
+
+    /// Create a confidential client whose passphrase is stored by the given policy.
+    pub fn confidential_with_policy(client_id: &str, redirect_url: Url, default_scope: Scope, passphrase: &[u8],
+        policy: Arc<PasswordPolicy>) -> Client {
+        let passdata = policy.store(client_id, passphrase);
+        Client::confidential_from_passdata_with_policy(client_id, redirect_url, default_scope, &passdata, policy)
+    }
+
+    /// Create a confidential client from previously stored passdata.
+    ///
+    /// The data is usually obtained from `passdata` of a client when persisting it. Legacy SHA-256
+    /// hashes are accepted as well and replaced with an Argon2id hash on the next successful
+    /// authentication of the client.
+    #[allow(deprecated)]
+    pub fn confidential_from_passdata(client_id: &str, redirect_url: Url, default_scope: Scope, passdata: &[u8])
+    -> Client {
+        if Argon2Policy::is_encoded(passdata) {
+            return Client::confidential_from_passdata_with_policy(client_id, redirect_url, default_scope, passdata,
+                Arc::new(Argon2Policy))
+        }
+
+        Client::confidential_from_passdata_with_policy(client_id, redirect_url, default_scope, passdata,
+            Arc::new(SHA256Policy)).with_rehash(Arc::new(Argon2Policy))
+    }
+
+    /// Create a confidential client from passdata previously stored by the given policy.
+    pub fn confidential_from_passdata_with_policy(client_id: &str, redirect_url: Url, default_scope: Scope,
+        passdata: &[u8], policy: Arc<PasswordPolicy>) -> Client {
+        let passdata = Passdata { data: passdata.to_vec(), policy, rehash: None };
         Client {
             client_id: client_id.to_string(),
             redirect_url,
+            allowed_scope: default_scope.clone(),
             default_scope,
             client_type: ClientType::Confidential { passdata },
+            password_grant: false,
+            implicit_grant: false,
+            refresh_rotation: false,
+            introspection: false,
+            registration: None,
+        }
+    }
+
+    /// Migrate the passphrase to another policy on the next successful authentication at the token
+    /// endpoint, see `Client::authenticate`.
+    ///
+    /// Has no effect on public clients or if the passdata was already produced by a policy of
+    /// the same name.
+    pub fn with_rehash(mut self, policy: Arc<PasswordPolicy>) -> Client {
+        if let ClientType::Confidential { ref mut passdata } = self.client_type {
+            if passdata.policy.name() != policy.name() {
+                passdata.rehash = Some(policy);
+            }
+        }
+        self
+    }
+
+    /// Set the scope the client may request, the default scope is the initial allowed scope.
+    ///
+    /// Scope-tokens requested by the client but not contained in this scope are silently dropped
+    /// during negotiation.
+    pub fn with_allowed_scope(mut self, allowed_scope: Scope) -> Client {
+        self.allowed_scope = allowed_scope;
+        self
+    }
+
+    /// Trust the client with the credentials of resource owners.
+    ///
+    /// This allows the client to use the resource owner password credentials grant. It should only
+    /// be enabled for highly privileged clients, such as first-party applications.
+    pub fn with_password_grant(mut self) -> Client {
+        self.password_grant = true;
+        self
+    }
+
+    /// Whether the client may use the resource owner password credentials grant.
+    pub fn allows_password_grant(&self) -> bool {
+        self.password_grant
+    }
+
+    /// Allow the client to request tokens directly from the authorization endpoint.
+    ///
+    /// The implicit grant exposes the token to the user-agent and should only be enabled for
+    /// browser-based clients which can not use the authorization code grant.
+    pub fn with_implicit_grant(mut self) -> Client {
+        self.implicit_grant = true;
+        self
+    }
+
+    /// Whether the client may use the implicit grant.
+    pub fn allows_implicit_grant(&self) -> bool {
+        self.implicit_grant
+    }
+
+    /// Replace the refresh token on each refresh and detect the reuse of replaced tokens.
+    ///
+    /// Presenting a replaced refresh token revokes all tokens descending from the same grant. This
+    /// is recommended for public clients, whose refresh tokens are not bound to any credentials.
+    /// Issuers of self-contained tokens can not invalidate refresh tokens and keep handing out the
+    /// presented one instead.
+    pub fn with_refresh_rotation(mut self) -> Client {
+        self.refresh_rotation = true;
+        self
+    }
+
+    /// Whether refresh tokens of the client are rotated.
+    pub fn rotates_refresh_tokens(&self) -> bool {
+        self.refresh_rotation
+    }
+
+    /// Allow the client to introspect the tokens of all clients.
+    ///
+    /// Introspection reveals the owner and client of a token, so this should only be enabled for
+    /// resource servers. Only confidential clients can authenticate at the introspection endpoint.
+    pub fn with_introspection(mut self) -> Client {
+        self.introspection = true;
+        self
+    }
+
+    /// Whether the client may introspect tokens.
+    pub fn allows_introspection(&self) -> bool {
+        self.introspection
+    }
+
+    /// Whether the client may use the grant type at the token endpoint.
+    ///
+    /// Dynamically registered clients are restricted to the grant types of their registration.
+    /// The password grant is not registered but configured with `with_password_grant` instead.
+    pub fn allows_grant_type(&self, grant_type: &str) -> bool {
+        match self.registration {
+            Some(ref registration) => registration.grant_types.iter().any(|grant| grant == grant_type),
+            None => true,
+        }
+    }
+
+    /// Whether the client may request the response type at the authorization endpoint.
+    ///
+    /// Dynamically registered clients are restricted to the response types of their registration.
+    pub fn allows_response_type(&self, response_type: &str) -> bool {
+        match self.registration {
+            Some(ref registration) => registration.response_types.iter().any(|response| response == response_type),
+            None => true,
+        }
+    }
+
+    /// Attach the information of a dynamic registration.
+    pub fn with_registration(mut self, registration: ClientRegistration) -> Client {
+        self.registration = Some(registration);
+        self
+    }
+
+    /// Keep the credentials and trust settings of a previous registration of the client.
+    ///
+    /// Used when the configuration of a client is replaced without changing its secret. The
+    /// password grant, refresh token rotation and introspection are configured by the server and
+    /// thus also kept.
+    pub fn with_credentials_of(mut self, previous: &Client) -> Client {
+        self.client_type = previous.client_type.clone();
+        self.password_grant = previous.password_grant;
+        self.refresh_rotation = previous.refresh_rotation;
+        self.introspection = previous.introspection;
+        self
+    }
+
+    /// The identifier under which the client is registered.
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// The registered redirection url.
+    pub fn redirect_url(&self) -> &Url {
+        &self.redirect_url
+    }
+
+    /// The scope the client may request.
+    pub fn allowed_scope(&self) -> &Scope {
+        &self.allowed_scope
+    }
+
+    /// Whether the client needs to authenticate with a passphrase.
+    pub fn is_confidential(&self) -> bool {
+        match self.client_type {
+            ClientType::Public => false,
+            ClientType::Confidential { .. } => true,
+        }
+    }
+
+    /// The information of a dynamic registration, if the client was registered this way.
+    pub fn registration(&self) -> Option<&ClientRegistration> {
+        self.registration.as_ref()
+    }
+
+    /// The stored passphrase hash of a confidential client, for persisting the client.
+    pub fn passdata(&self) -> Option<Vec<u8>> {
+        match self.client_type {
+            ClientType::Public => None,
+            ClientType::Confidential { ref passdata } => Some(passdata.data.clone()),
+        }
+    }
+
+    /// The name of the policy which produced the current passdata, to be persisted with it.
+    pub fn password_policy(&self) -> Option<String> {
+        match self.client_type {
+            ClientType::Public => None,
+            ClientType::Confidential { ref passdata } => Some(passdata.policy.name().to_string()),
         }
     }
 
@@ -153,34 +467,136 @@ impl Client {
     pub fn check_authentication(&self, passphrase: Option<&[u8]>) -> Result<&Self, Unspecified> {
         match (passphrase, &self.client_type) {
             (None, &ClientType::Public) => Ok(self),
-            (Some(provided), &ClientType::Confidential{ passdata: ref stored })
-                => SHA256Policy.check(&self.client_id, provided, stored).map(|()| self),
+            (Some(provided), &ClientType::Confidential{ ref passdata }) => {
+                passdata.policy.check(&self.client_id, provided, &passdata.data)?;
+                Ok(self)
+            },
             _ => return Err(Unspecified)
         }
     }
+
+    /// Authenticate like `check_authentication` and perform a requested rehash.
+    ///
+    /// If the passphrase matches and the client is migrated to another policy, the result contains
+    /// a copy of the client with the passphrase stored by the new policy. The client itself is left
+    /// unchanged, the copy should be handed to `Registrar::rehash` to replace it.
+    pub fn authenticate(&self, passphrase: Option<&[u8]>) -> Result<Option<Client>, Unspecified> {
+        self.check_authentication(passphrase)?;
+        let (provided, policy) = match (passphrase, &self.client_type) {
+            (Some(provided), &ClientType::Confidential{ passdata: Passdata { rehash: Some(ref policy), .. } })
+                => (provided, policy.clone()),
+            _ => return Ok(None),
+        };
+
+        let data = policy.store(&self.client_id, provided);
+        let client_type = ClientType::Confidential { passdata: Passdata { data, policy, rehash: None } };
+        Ok(Some(Client { client_type, .. self.clone() }))
+    }
 }
 
-/// Determines how passphrases are stored and checked. Most likely you want to use Argon2
-trait PasswordPolicy {
+impl ClientRegistration {
+    /// Record a registration at the given time, without a registration access token.
+    pub fn new(issued_at: i64) -> ClientRegistration {
+        ClientRegistration {
+            token: None,
+            issued_at,
+            client_name: None,
+            grant_types: Vec::new(),
+            response_types: Vec::new(),
+        }
+    }
+
+    /// Allow managing the configuration with the registration access token.
+    ///
+    /// Only a digest of the token is kept. Since registration access tokens are randomly
+    /// generated, no additional salting is necessary.
+    pub fn with_access_token(mut self, token: &str) -> ClientRegistration {
+        self.token = Some(digest::digest(&digest::SHA256, token.as_bytes()).as_ref().to_vec());
+        self
+    }
+
+    /// Check a registration access token presented by the client.
+    pub fn check_token(&self, token: &str) -> bool {
+        let provided = digest::digest(&digest::SHA256, token.as_bytes());
+        match self.token {
+            Some(ref stored) => constant_time::verify_slices_are_equal(provided.as_ref(), stored).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Determines how passphrases are stored and checked.
+///
+/// Each client records the policy which produced its stored hash, such that clients with hashes
+/// of different policies can be checked side by side while migrating between them.
+pub trait PasswordPolicy: Send + Sync {
+    /// A name identifying the policy and its stored format, for persisting it with the hashes.
+    fn name(&self) -> &str;
     /// Transform the passphrase so it can be stored in the confidential client
     fn store(&self, client_id: &str, passphrase: &[u8]) -> Vec<u8>;
+    /// Check a provided passphrase against data previously produced by `store`.
     fn check(&self, client_id: &str, passphrase: &[u8], stored: &[u8]) -> Result<(), Unspecified>;
 }
 
-/// Hashes the passphrase, salting with the client id. This is not optimal for passwords and will
-/// be replaced with argon2 in a future commit which also enables better configurability, such as
-/// supplying a secret key to argon2. This will probably be combined with a slight rework of the
-/// exact semantics of `Client` and `Client::check_authentication`.
-#[deprecated(since="0.1.0-alpha.1", note="Should be replaced with argon2 as soon as possible")]
-struct SHA256Policy;
+/// Checks passphrases hashed with SHA-256, salted with the client id.
+///
+/// Only kept to verify existing hashes, clients should be migrated with `Client::with_rehash`.
+/// The policy can not create new hashes, `store` panics.
+#[deprecated(since="0.1.0-alpha.1", note="Only verifies legacy hashes, use Argon2Policy instead")]
+pub struct SHA256Policy;
+
+/// Hashes the passphrase with Argon2id and a random salt for each client.
+///
+/// The result is the standard encoded form including the parameters, such that they can be
+/// raised later without invalidating existing hashes. The client id is bound to the hash as
+/// associated data. This is the default policy for confidential clients.
+pub struct Argon2Policy;
+
+impl Argon2Policy {
+    const SALT_LENGTH: usize = 16;
+
+    fn config<'a>(client_id: &'a str) -> Config<'a> {
+        Config {
+            variant: Variant::Argon2id,
+            ad: client_id.as_bytes(),
+            .. Config::default()
+        }
+    }
+
+    fn is_encoded(stored: &[u8]) -> bool {
+        stored.starts_with(b"$argon2")
+    }
+}
+
+impl PasswordPolicy for Argon2Policy {
+    fn name(&self) -> &str {
+        "argon2id"
+    }
+
+    fn store(&self, client_id: &str, passphrase: &[u8]) -> Vec<u8> {
+        let salt = thread_rng().gen_iter::<u8>().take(Argon2Policy::SALT_LENGTH).collect::<Vec<u8>>();
+        argon2::hash_encoded(passphrase, &salt, &Argon2Policy::config(client_id))
+            .expect("Argon2 configuration is valid")
+            .into_bytes()
+    }
+
+    fn check(&self, client_id: &str, passphrase: &[u8], stored: &[u8]) -> Result<(), Unspecified> {
+        let encoded = ::std::str::from_utf8(stored).map_err(|_| Unspecified)?;
+        match argon2::verify_encoded_ext(encoded, passphrase, &[], client_id.as_bytes()) {
+            Ok(true) => Ok(()),
+            _ => Err(Unspecified),
+        }
+    }
+}
 
 #[allow(deprecated)]
 impl PasswordPolicy for SHA256Policy {
-    fn store(&self, client_id: &str, passphrase: &[u8]) -> Vec<u8> {
-        let mut context = digest::Context::new(&digest::SHA256);
-        context.update(client_id.as_bytes());
-        context.update(passphrase);
-        context.finish().as_ref().to_vec()
+    fn name(&self) -> &str {
+        "sha256"
+    }
+
+    fn store(&self, _client_id: &str, _passphrase: &[u8]) -> Vec<u8> {
+        panic!("SHA256Policy only verifies existing hashes")
     }
 
     fn check(&self, client_id: &str, passphrase: &[u8], stored: &[u8]) -> Result<(), Unspecified> {
@@ -198,11 +614,37 @@ impl PasswordPolicy for SHA256Policy {
 impl ClientMap {
     /// Create an empty map without any clients in it.
     pub fn new() -> ClientMap {
-        ClientMap { clients: HashMap::new() }
+        ClientMap { clients: HashMap::new(), pkce_public: false, policy: None, migrated: Vec::new() }
+    }
+
+    /// Create an empty map which migrates confidential clients to the password policy.
+    ///
+    /// Only clients inserted with passdata of one of the listed policies, identified by their
+    /// name, are rehashed with this policy on their next successful authentication. Clients of
+    /// other policies keep their passdata.
+    pub fn with_policy(policy: Arc<PasswordPolicy>, migrated: &[&str]) -> ClientMap {
+        ClientMap {
+            clients: HashMap::new(),
+            pkce_public: false,
+            policy: Some(policy),
+            migrated: migrated.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    /// Require public clients to use PKCE for all authorization requests. Confidential clients
+    /// may still choose to send a code challenge. Disabled by default.
+    pub fn require_pkce_for_public(&mut self, required: bool) {
+        self.pkce_public = required;
     }
 
     /// Insert or update the client record.
     pub fn register_client(&mut self, client: Client) {
+        let migrated = client.password_policy()
+            .map_or(false, |name| self.migrated.contains(&name));
+        let client = match self.policy {
+            Some(ref policy) if migrated => client.with_rehash(policy.clone()),
+            _ => client,
+        };
         self.clients.insert(client.client_id.clone(), client);
     }
 }
@@ -231,6 +673,43 @@ impl Registrar for ClientMap {
     fn client(&self, client_id: &str) -> Option<&Client> {
         self.clients.get(client_id)
     }
+
+    fn requires_pkce(&self, client: &Client) -> bool {
+        match client.client_type {
+            ClientType::Public => self.pkce_public,
+            ClientType::Confidential { .. } => false,
+        }
+    }
+
+    fn register(&mut self, client: Client) -> Result<(), RegistrarError> {
+        if self.clients.contains_key(&client.client_id) {
+            return Err(RegistrarError::AlreadyRegistered)
+        }
+
+        self.register_client(client);
+        Ok(())
+    }
+
+    fn update(&mut self, client: Client) -> Result<(), RegistrarError> {
+        if !self.clients.contains_key(&client.client_id) {
+            return Err(RegistrarError::Unregistered)
+        }
+
+        self.register_client(client);
+        Ok(())
+    }
+
+    fn remove(&mut self, client_id: &str) -> Result<(), RegistrarError> {
+        self.clients.remove(client_id)
+            .map(|_| ())
+            .ok_or(RegistrarError::Unregistered)
+    }
+
+    fn rehash(&mut self, client: Client) {
+        if self.clients.contains_key(&client.client_id) {
+            self.clients.insert(client.client_id.clone(), client);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -254,5 +733,167 @@ mod tests {
         assert!(client.check_authentication(Some(pass)).is_ok());
         assert!(client.check_authentication(Some(b"not the passphrase")).is_err());
         assert!(client.check_authentication(Some(b"")).is_err());
+        assert!(client.passdata().unwrap().starts_with(b"$argon2id$"));
+    }
+
+    /// Passdata as stored by earlier versions, before `SHA256Policy` became verify-only.
+    fn legacy_passdata(client_id: &str, passphrase: &[u8]) -> Vec<u8> {
+        let mut context = digest::Context::new(&digest::SHA256);
+        context.update(client_id.as_bytes());
+        context.update(passphrase);
+        context.finish().as_ref().to_vec()
+    }
+
+    #[test]
+    fn legacy_passdata_rehash() {
+        let pass = b"AB3fAj6GJpdxmEVeNCyPoA==";
+        let legacy = legacy_passdata("ClientId", pass);
+        let client = Client::confidential_from_passdata("ClientId", "https://example.com".parse().unwrap(),
+            "default".parse().unwrap(), &legacy);
+
+        assert!(client.authenticate(Some(b"not the passphrase")).is_err());
+        assert!(client.check_authentication(Some(pass)).is_ok());
+        assert_eq!(client.passdata().unwrap(), legacy);
+        assert_eq!(client.password_policy().unwrap(), "sha256");
+
+        let upgraded = client.authenticate(Some(pass)).unwrap().expect("Client was not rehashed");
+        assert_eq!(client.passdata().unwrap(), legacy);
+        assert_eq!(upgraded.password_policy().unwrap(), "argon2id");
+        assert!(upgraded.passdata().unwrap().starts_with(b"$argon2id$"));
+        assert!(upgraded.authenticate(Some(pass)).unwrap().is_none());
+    }
+
+    /// Stores the passphrase in reverse, to distinguish it from the builtin policies.
+    struct ReversePolicy;
+
+    impl PasswordPolicy for ReversePolicy {
+        fn name(&self) -> &str {
+            "reverse"
+        }
+
+        fn store(&self, _: &str, passphrase: &[u8]) -> Vec<u8> {
+            passphrase.iter().rev().cloned().collect()
+        }
+
+        fn check(&self, client_id: &str, passphrase: &[u8], stored: &[u8]) -> Result<(), Unspecified> {
+            constant_time::verify_slices_are_equal(&self.store(client_id, passphrase), stored)
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    #[allow(deprecated)]
+    fn legacy_policy_can_not_store() {
+        SHA256Policy.store("ClientId", b"Passphrase");
+    }
+
+    #[test]
+    fn custom_policy_migration() {
+        let pass = b"Passphrase";
+        let mut registrar = ClientMap::with_policy(Arc::new(ReversePolicy), &["argon2id"]);
+        registrar.register_client(Client::confidential("Argon", "https://example.com".parse().unwrap(),
+            "default".parse().unwrap(), pass));
+        registrar.register_client(Client::confidential_from_passdata("Legacy", "https://example.com".parse().unwrap(),
+            "default".parse().unwrap(), &legacy_passdata("Legacy", pass)));
+        registrar.register_client(Client::confidential_with_policy("Reverse", "https://example.com".parse().unwrap(),
+            "default".parse().unwrap(), pass, Arc::new(ReversePolicy)));
+
+        let reverse = registrar.client("Reverse").unwrap();
+        assert_eq!(reverse.passdata().unwrap(), b"esarhpssaP".to_vec());
+        assert!(reverse.authenticate(Some(pass)).unwrap().is_none());
+        assert_eq!(reverse.password_policy().unwrap(), "reverse");
+
+        let rehashed = {
+            let argon = registrar.client("Argon").unwrap();
+            assert!(argon.authenticate(Some(b"not the passphrase")).is_err());
+            assert_eq!(argon.password_policy().unwrap(), "argon2id");
+            argon.authenticate(Some(pass)).unwrap().expect("Client was not rehashed")
+        };
+        registrar.rehash(rehashed);
+
+        let argon = registrar.client("Argon").unwrap();
+        assert_eq!(argon.password_policy().unwrap(), "reverse");
+        assert_eq!(argon.passdata().unwrap(), b"esarhpssaP".to_vec());
+        assert!(argon.authenticate(Some(pass)).unwrap().is_none());
+
+        // Not on the migration list, the client is only upgraded to Argon2id by itself.
+        let legacy = registrar.client("Legacy").unwrap();
+        let rehashed = legacy.authenticate(Some(pass)).unwrap().expect("Client was not rehashed");
+        assert_eq!(rehashed.password_policy().unwrap(), "argon2id");
+    }
+
+    #[test]
+    fn argon2_salted_per_client() {
+        let pass = b"Passphrase";
+        let first = Argon2Policy.store("ClientId", pass);
+        let second = Argon2Policy.store("ClientId", pass);
+        assert!(first != second);
+        assert!(Argon2Policy.check("ClientId", pass, &first).is_ok());
+        assert!(Argon2Policy.check("ClientId", pass, &second).is_ok());
+        assert!(Argon2Policy.check("OtherClient", pass, &first).is_err());
+    }
+
+    #[test]
+    fn negotiate_scope() {
+        let mut registrar = ClientMap::new();
+        registrar.register_client(Client::public("ClientId", "https://example.com".parse().unwrap(),
+            "default".parse().unwrap()).with_allowed_scope("default read write".parse().unwrap()));
+
+        let bind = || registrar.bound_redirect(ClientUrl {
+            client_id: "ClientId".into(),
+            redirect_url: None,
+        }).ok().unwrap();
+
+        let defaulted = bind().negotiate(None).ok().unwrap();
+        assert_eq!(defaulted.scope.as_ref(), &"default".parse().unwrap());
+
+        let requested = bind().negotiate(Some("read".parse().unwrap())).ok().unwrap();
+        assert_eq!(requested.scope.as_ref(), &"read".parse().unwrap());
+
+        let partial = bind().negotiate(Some("read admin".parse().unwrap())).ok().unwrap();
+        assert_eq!(partial.scope.as_ref(), &"read".parse().unwrap());
+
+        assert!(bind().negotiate(Some("admin".parse().unwrap())).is_err());
+    }
+
+    #[test]
+    fn register_duplicate() {
+        let mut registrar = ClientMap::new();
+        let client = || Client::public("ClientId", "https://example.com".parse().unwrap(),
+            "default".parse().unwrap());
+
+        assert!(registrar.register(client()).is_ok());
+        assert!(registrar.client("ClientId").is_some());
+        match registrar.register(client()) {
+            Err(RegistrarError::AlreadyRegistered) => (),
+            _ => panic!("Expected duplicate registration to fail"),
+        }
+    }
+
+    #[test]
+    fn update_and_remove() {
+        let mut registrar = ClientMap::new();
+        let pass = b"Passphrase";
+        let original = Client::confidential("ClientId", "https://example.com".parse().unwrap(),
+            "default".parse().unwrap(), pass)
+            .with_registration(ClientRegistration::new(0).with_access_token("Token"));
+        registrar.register(original.clone()).ok().unwrap();
+
+        let updated = Client::public("ClientId", "https://example.com/other".parse().unwrap(),
+            "default".parse().unwrap())
+            .with_credentials_of(&original)
+            .with_registration(original.registration().unwrap().clone());
+        registrar.update(updated).ok().unwrap();
+
+        let stored = registrar.client("ClientId").unwrap();
+        assert_eq!(stored.redirect_url().as_str(), "https://example.com/other");
+        assert!(stored.check_authentication(Some(pass)).is_ok());
+        assert!(stored.registration().unwrap().check_token("Token"));
+        assert!(!stored.registration().unwrap().check_token("Other"));
+
+        assert!(registrar.remove("ClientId").is_ok());
+        assert!(registrar.client("ClientId").is_none());
+        assert!(registrar.remove("ClientId").is_err());
+        assert!(registrar.update(original).is_err());
     }
 }
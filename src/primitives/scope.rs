@@ -2,6 +2,7 @@
 use std::{cmp, fmt, str};
 
 use std::collections::HashSet;
+use std::collections::hash_set;
 
 /// Scope of a bearer token, a set of scope-tokens encoded with separation by spaces
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -25,6 +26,21 @@ impl Scope {
     pub fn privileged_to(&self, rhs: &Scope) -> bool {
         self.tokens.is_subset(&rhs.tokens)
     }
+
+    /// Create the scope consisting of all scope-tokens contained in both scopes.
+    pub fn intersection(&self, rhs: &Scope) -> Scope {
+        Scope { tokens: self.tokens.intersection(&rhs.tokens).cloned().collect() }
+    }
+
+    /// Determines if the scope does not contain any scope-token.
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Iterate over the scope-tokens, in no particular order.
+    pub fn iter(&self) -> hash_set::Iter<String> {
+        self.tokens.iter()
+    }
 }
 
 /// Error returned from parsing a scope as encoded in an authorization token request.
@@ -96,4 +112,16 @@ mod tests {
 
         assert_eq!(scope_base.partial_cmp(&scope_base), Some(cmp::Ordering::Equal));
     }
+
+    #[test]
+    fn test_intersection() {
+        let scope_base = "cap1 cap2".parse::<Scope>().unwrap();
+        let scope_other = "cap2 cap3".parse::<Scope>().unwrap();
+        let scope_disjoint = "cap4".parse::<Scope>().unwrap();
+
+        assert_eq!(scope_base.intersection(&scope_other), "cap2".parse().unwrap());
+        assert_eq!(scope_other.intersection(&scope_base), "cap2".parse().unwrap());
+        assert!(scope_base.intersection(&scope_disjoint).is_empty());
+        assert!(!scope_base.is_empty());
+    }
 }